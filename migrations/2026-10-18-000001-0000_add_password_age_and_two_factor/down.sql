@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE records DROP COLUMN two_factor;
+ALTER TABLE records DROP COLUMN password_changed_at;
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+
+ALTER TABLE records ADD COLUMN password_changed_at BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE records ADD COLUMN two_factor BOOLEAN NOT NULL DEFAULT 0;
+
+-- Existing passwords have no known age, so start counting from the upgrade.
+UPDATE records SET password_changed_at = CAST(strftime('%s', 'now') AS INTEGER);
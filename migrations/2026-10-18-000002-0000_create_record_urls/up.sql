@@ -0,0 +1,13 @@
+-- Your SQL goes here
+
+CREATE TABLE record_urls (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    record_id INTEGER NOT NULL REFERENCES records(id) ON DELETE CASCADE,
+    url TEXT NOT NULL,
+    host TEXT NOT NULL,
+    base_domain TEXT NOT NULL
+);
+
+CREATE INDEX record_urls_record_id ON record_urls(record_id);
+CREATE INDEX record_urls_host ON record_urls(host);
+CREATE INDEX record_urls_base_domain ON record_urls(base_domain);
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+
+CREATE TABLE secret_attributes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    record_id INTEGER NOT NULL REFERENCES records(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    value TEXT NOT NULL
+);
+
+CREATE INDEX secret_attributes_record_id ON secret_attributes(record_id);
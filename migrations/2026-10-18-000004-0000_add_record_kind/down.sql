@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE records DROP COLUMN confirm_use;
+ALTER TABLE records DROP COLUMN kind;
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+
+-- Records are logins unless they hold an SSH private key
+ALTER TABLE records ADD COLUMN kind TEXT NOT NULL DEFAULT 'login';
+ALTER TABLE records ADD COLUMN confirm_use BOOLEAN NOT NULL DEFAULT 0;
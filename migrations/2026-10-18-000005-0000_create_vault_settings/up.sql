@@ -0,0 +1,6 @@
+-- Your SQL goes here
+
+CREATE TABLE vault_settings (
+    id INTEGER PRIMARY KEY NOT NULL CHECK (id = 1),
+    data TEXT NOT NULL
+);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE master_table DROP COLUMN wipe_after_failures;
+ALTER TABLE master_table DROP COLUMN last_failed_unlock;
+ALTER TABLE master_table DROP COLUMN failed_unlocks;
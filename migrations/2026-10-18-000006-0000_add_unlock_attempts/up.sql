@@ -0,0 +1,7 @@
+-- Your SQL goes here
+
+-- Failed unlocks since the last successful one, kept in the clear since they are checked
+-- before the vault is unlocked. A wipe limit of 0 never wipes the vault.
+ALTER TABLE master_table ADD COLUMN failed_unlocks INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE master_table ADD COLUMN last_failed_unlock BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE master_table ADD COLUMN wipe_after_failures INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+DELETE FROM master_table WHERE slot <> 'password';
+DROP INDEX master_table_slot;
+ALTER TABLE master_table DROP COLUMN slot;
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+
+-- The master key can be wrapped more than once, each copy in its own named slot. The vault
+-- created so far only has the copy protected by the master password.
+ALTER TABLE master_table ADD COLUMN slot TEXT NOT NULL DEFAULT 'password';
+CREATE UNIQUE INDEX master_table_slot ON master_table(slot);
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+-- Slots whose key is derived from the password together with a key file
+ALTER TABLE master_table ADD COLUMN requires_key_file BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+DELETE FROM master_table WHERE slot NOT IN ('password', 'recovery');
+ALTER TABLE master_table DROP COLUMN kind;
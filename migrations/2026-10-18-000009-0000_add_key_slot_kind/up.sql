@@ -0,0 +1,6 @@
+-- Your SQL goes here
+
+-- What each slot is protected with, the slot name is only a label picked by the user. Slots
+-- of the same kind are tried one after the other when unlocking.
+ALTER TABLE master_table ADD COLUMN kind TEXT NOT NULL DEFAULT 'password';
+UPDATE master_table SET kind = 'recovery' WHERE slot = 'recovery';
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+
+-- Layout of the encrypted fields. Format 0 is a nonce followed by the ciphertext, format 1
+-- adds a version byte in front and binds every ciphertext to its record and field. Existing
+-- vaults are upgraded when they are next unlocked, the key is needed to re-encrypt them.
+CREATE TABLE vault_format (
+    id INTEGER PRIMARY KEY NOT NULL CHECK (id = 1),
+    ciphertext_format INTEGER NOT NULL
+);
+INSERT INTO vault_format (id, ciphertext_format) VALUES (1, 0);
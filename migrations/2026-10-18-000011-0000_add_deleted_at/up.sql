@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+-- Deleting a record moves it to the trash: the time it was deleted, NULL while it is in use
+ALTER TABLE records ADD COLUMN deleted_at BIGINT;
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+
+-- Folder the record is filed under, empty for none
+ALTER TABLE records ADD COLUMN folder TEXT NOT NULL DEFAULT '';
+
+CREATE TABLE record_tags (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    record_id INTEGER NOT NULL REFERENCES records(id) ON DELETE CASCADE,
+    tag TEXT NOT NULL,
+    UNIQUE (record_id, tag)
+);
+
+CREATE INDEX record_tags_tag ON record_tags(tag);
@@ -0,0 +1,59 @@
+#!/bin/bash
+# Install the native-messaging manifests for the current user, filling in where the host binary
+# lives and, for Chromium, which extension may talk to it.
+#
+#   native-messaging/install.sh [--host PATH] [--chromium-extension ID]
+#
+# PATH defaults to pandabox-native-host found on $PATH. The Firefox manifest is always
+# installed, the Chromium one only when the extension ID (32 letters a-p, shown on
+# chrome://extensions) is given.
+set -euo pipefail
+
+NAME="com.github.mastrhyperion98.pandabox"
+TEMPLATES="$(cd "$(dirname "$0")" && pwd)"
+host_path=""
+extension_id=""
+
+while [ $# -gt 0 ]; do
+    case "$1" in
+        --host) host_path="${2:?--host needs a path}"; shift 2 ;;
+        --chromium-extension) extension_id="${2:?--chromium-extension needs an ID}"; shift 2 ;;
+        *) echo "Unknown argument: $1" >&2; exit 1 ;;
+    esac
+done
+
+if [ -z "$host_path" ]; then
+    host_path="$(command -v pandabox-native-host || true)"
+fi
+# Browsers only start hosts given by an absolute path
+if [[ "$host_path" != /* ]] || [ ! -x "$host_path" ]; then
+    echo "pandabox-native-host not found, install it first or pass --host /absolute/path" >&2
+    exit 1
+fi
+# Written into JSON as it is
+if [[ "$host_path" == *[\"\\]* ]]; then
+    echo "The host path cannot contain quotes or backslashes: $host_path" >&2
+    exit 1
+fi
+if [ -n "$extension_id" ] && [[ ! "$extension_id" =~ ^[a-p]{32}$ ]]; then
+    echo "Not a Chromium extension ID: $extension_id" >&2
+    exit 1
+fi
+
+# Fill in a template, escaping what sed would read as part of the expression
+install_manifest() {
+    local template="$1" target_dir="$2"
+    local escaped_path="${host_path//|/\\|}"
+    escaped_path="${escaped_path//&/\\&}"
+    mkdir -p "$target_dir"
+    sed -e "s|@HOST_PATH@|$escaped_path|" -e "s|@EXTENSION_ID@|$extension_id|" \
+        "$TEMPLATES/$template" > "$target_dir/$NAME.json"
+    echo "Installed $target_dir/$NAME.json"
+}
+
+install_manifest firefox.json.in "$HOME/.mozilla/native-messaging-hosts"
+if [ -n "$extension_id" ]; then
+    install_manifest chromium.json.in "${XDG_CONFIG_HOME:-$HOME/.config}/chromium/NativeMessagingHosts"
+else
+    echo "Skipped Chromium, pass --chromium-extension ID to allow an extension"
+fi
@@ -0,0 +1,87 @@
+// Requests from background services (browser bridge, SSH agent) that the user has to allow
+// or deny in the GUI. Requests are shown one at a time in the order they arrived.
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How long a request waits for the user before it is denied, unless changed with `set_timeout`
+pub const APPROVAL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// What the approval prompt shows.
+#[derive(Clone)]
+pub struct ApprovalRequest {
+    pub title: String,
+    pub message: String,
+    /// The website, key, ... the request is about
+    pub subject: String,
+}
+
+/// A request waiting for the user to allow or deny it.
+struct PendingApproval {
+    id: u64,
+    request: ApprovalRequest,
+    reply: Sender<bool>,
+}
+
+/// Requests waiting for user confirmation, shared between the services and the GUI.
+#[derive(Clone)]
+pub struct ApprovalQueue {
+    pending: Arc<Mutex<VecDeque<PendingApproval>>>,
+    next_id: Arc<AtomicU64>,
+    // Milliseconds a request waits for a decision
+    timeout_ms: Arc<AtomicU64>,
+    // Called with the request now awaiting a decision whenever the queue changes
+    notify: Arc<dyn Fn(Option<ApprovalRequest>) + Send + Sync>,
+}
+
+impl ApprovalQueue {
+    /// `notify` is called from the requesting thread (or the one answering) so the GUI can
+    /// update its prompt.
+    pub fn new<F>(notify: F) -> Self
+    where
+        F: Fn(Option<ApprovalRequest>) + Send + Sync + 'static,
+    {
+        ApprovalQueue {
+            pending: Arc::default(),
+            next_id: Arc::default(),
+            timeout_ms: Arc::new(AtomicU64::new(APPROVAL_TIMEOUT.as_millis() as u64)),
+            notify: Arc::new(notify),
+        }
+    }
+
+    /// Change how long requests made from now on wait for the user
+    pub fn set_timeout(&self, timeout: Duration) {
+        self.timeout_ms.store(timeout.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// Allow or deny the request currently awaiting a decision
+    pub fn respond(&self, allowed: bool) {
+        let approval = self.pending.lock().unwrap().pop_front();
+        if let Some(approval) = approval {
+            approval.reply.send(allowed).ok();
+        }
+        self.notify();
+    }
+
+    /// Queue a request and block until the user answers it. Requests left unanswered for
+    /// the configured timeout are denied.
+    pub fn request(&self, request: ApprovalRequest) -> bool {
+        let (reply, decision) = mpsc::channel();
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.pending.lock().unwrap().push_back(PendingApproval { id, request, reply });
+        self.notify();
+
+        let timeout = Duration::from_millis(self.timeout_ms.load(Ordering::Relaxed));
+        let allowed = decision.recv_timeout(timeout).unwrap_or(false);
+        self.pending.lock().unwrap().retain(|approval| approval.id != id);
+        self.notify();
+        allowed
+    }
+
+    fn notify(&self) {
+        let current = self.pending.lock().unwrap().front().map(|approval| approval.request.clone());
+        (self.notify)(current);
+    }
+}
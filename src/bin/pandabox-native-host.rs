@@ -0,0 +1,102 @@
+// Browser native-messaging host. Browsers start this program and talk to it over stdio using
+// length prefixed JSON messages; every message is relayed to the running Pandabox instance.
+use std::io::{self, Read, Write};
+use log::error;
+
+use pandabox::bridge::protocol::{self, BridgeRequest, BridgeResponse, MAX_MESSAGE_SIZE};
+
+fn main() {
+    // stdout carries the protocol, so logs go to stderr where browsers collect them
+    env_logger::Builder::from_default_env()
+        .filter_level(log::LevelFilter::Warn)
+        .init();
+
+    let mut stdin = io::stdin().lock();
+    let mut stdout = io::stdout().lock();
+
+    loop {
+        let message = match read_message(&mut stdin) {
+            Ok(Some(message)) => message,
+            Ok(None) => break,
+            Err(e) => {
+                error!("Failed to read message from browser: {}", e);
+                break;
+            }
+        };
+
+        let response = match serde_json::from_slice::<BridgeRequest>(&message) {
+            Ok(request) => relay(request),
+            Err(e) => BridgeResponse::Error { message: format!("Malformed request: {}", e) },
+        };
+
+        if let Err(e) = write_message(&mut stdout, &response) {
+            error!("Failed to write message to browser: {}", e);
+            break;
+        }
+    }
+}
+
+/// Forward a browser request to Pandabox and return its answer
+#[cfg(unix)]
+fn relay(request: BridgeRequest) -> BridgeResponse {
+    use std::fs;
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::net::UnixStream;
+    use protocol::{socket_path, token_path, BridgeEnvelope};
+
+    let token = match fs::read_to_string(token_path()) {
+        Ok(token) => token.trim().to_string(),
+        Err(_) => return BridgeResponse::Error { message: String::from("Pandabox is not running") },
+    };
+
+    let result = UnixStream::connect(socket_path()).and_then(|stream| {
+        let mut writer = &stream;
+        serde_json::to_writer(&mut writer, &BridgeEnvelope { token, request })?;
+        writer.write_all(b"\n")?;
+
+        let mut line = String::new();
+        BufReader::new((&stream).take(MAX_MESSAGE_SIZE as u64)).read_line(&mut line)?;
+        serde_json::from_str::<BridgeResponse>(&line).map_err(io::Error::from)
+    });
+
+    result.unwrap_or_else(|e| {
+        error!("Failed to reach Pandabox: {}", e);
+        BridgeResponse::Error { message: String::from("Pandabox is not running") }
+    })
+}
+
+#[cfg(not(unix))]
+fn relay(_request: BridgeRequest) -> BridgeResponse {
+    BridgeResponse::Error { message: String::from("The browser bridge is only available on Unix systems") }
+}
+
+/// Read one message: a native-endian u32 length followed by that many bytes of JSON.
+/// Returns `None` once the browser closes stdin.
+fn read_message(input: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+    let mut length = [0u8; 4];
+    match input.read_exact(&mut length) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+
+    let length = u32::from_ne_bytes(length) as usize;
+    if length > MAX_MESSAGE_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "message too large"));
+    }
+
+    let mut message = vec![0u8; length];
+    input.read_exact(&mut message)?;
+    Ok(Some(message))
+}
+
+fn write_message(output: &mut impl Write, response: &BridgeResponse) -> io::Result<()> {
+    let message = serde_json::to_vec(response)?;
+    if message.len() > MAX_MESSAGE_SIZE {
+        return write_message(output, &BridgeResponse::Error { message: String::from("Response too large") });
+    }
+
+    output.write_all(&(message.len() as u32).to_ne_bytes())?;
+    output.write_all(&message)?;
+    output.flush()
+}
@@ -0,0 +1,3 @@
+pub mod protocol;
+#[cfg(unix)]
+pub mod server;
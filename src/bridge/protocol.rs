@@ -0,0 +1,76 @@
+// Messages exchanged between the browser native-messaging host and the running Pandabox
+// instance. The host forwards every browser message wrapped in an envelope carrying the
+// bridge token, one JSON document per line.
+use std::env;
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+
+pub const SOCKET_FILE: &str = "bridge.sock";
+pub const TOKEN_FILE: &str = "bridge.token";
+
+/// Largest message accepted from either side of the bridge (the browser limit for host replies).
+pub const MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+
+/// How closely the saved websites have to match the page asking for credentials
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LookupMatch {
+    Exact,
+    Host,
+    #[default]
+    BaseDomain,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum BridgeRequest {
+    Status,
+    Lookup {
+        url: String,
+        #[serde(default, rename = "match")]
+        match_mode: LookupMatch,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BridgeEnvelope {
+    pub token: String,
+    pub request: BridgeRequest,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BridgeCredential {
+    pub service: String,
+    pub email: String,
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum BridgeResponse {
+    Unlocked,
+    Locked,
+    Denied,
+    Credentials { credentials: Vec<BridgeCredential> },
+    Error { message: String },
+}
+
+/// Directory holding the bridge socket and token, private to the current user.
+pub fn runtime_dir() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("pandabox"),
+        None => {
+            let user = env::var("USER").unwrap_or_else(|_| String::from("user"));
+            env::temp_dir().join(format!("pandabox-{}", user))
+        }
+    }
+}
+
+pub fn socket_path() -> PathBuf {
+    runtime_dir().join(SOCKET_FILE)
+}
+
+pub fn token_path() -> PathBuf {
+    runtime_dir().join(TOKEN_FILE)
+}
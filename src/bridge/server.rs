@@ -0,0 +1,212 @@
+// Local socket answering credential lookups from the browser native-messaging host.
+// Every lookup has to be approved by the user in the GUI before any password leaves the vault.
+use std::error::Error;
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use log::{error, info, warn};
+use rand_core::{OsRng, RngCore};
+use crate::approval::{ApprovalQueue, ApprovalRequest};
+use crate::bridge::protocol::{
+    runtime_dir, socket_path, token_path, BridgeCredential, BridgeEnvelope, BridgeRequest,
+    BridgeResponse, LookupMatch, MAX_MESSAGE_SIZE,
+};
+use crate::session::session::{Session, SessionState};
+use crate::session::url_match::UrlMatch;
+
+/// How long a connection may take to send its request before it is dropped
+pub const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Handle to the running bridge, removes the socket and token files when dropped.
+pub struct BridgeServer {
+    socket: PathBuf,
+    token: PathBuf,
+}
+
+impl Drop for BridgeServer {
+    fn drop(&mut self) {
+        fs::remove_file(&self.socket).ok();
+        fs::remove_file(&self.token).ok();
+    }
+}
+
+/// Create the runtime directory holding the sockets and the bridge token, or check the one
+/// already there. Outside `XDG_RUNTIME_DIR` it lives in the shared temporary directory, where
+/// another user could have created it first to swap in their own socket or read the token, so
+/// it has to be a real directory owned by us that nobody else can enter.
+pub(crate) fn create_runtime_dir() -> io::Result<PathBuf> {
+    let dir = runtime_dir();
+    DirBuilder::new().recursive(true).mode(0o700).create(&dir)?;
+    check_private_dir(&dir)?;
+    Ok(dir)
+}
+
+/// Refuse `dir` unless it is a directory, not a link to one, owned by the current user and
+/// closed to everyone else
+pub fn check_private_dir(dir: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(dir)?;
+    let refuse = |reason: &str| {
+        Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("refusing to use {}: {}", dir.display(), reason),
+        ))
+    };
+    if !metadata.file_type().is_dir() {
+        return refuse("it is not a directory");
+    }
+    // SAFETY: getuid cannot fail and touches no memory
+    if metadata.uid() != unsafe { libc::getuid() } {
+        return refuse("it belongs to another user");
+    }
+    if metadata.mode() & 0o7777 != 0o700 {
+        return refuse("other users can access it");
+    }
+    Ok(())
+}
+
+/// Start listening for native-messaging host connections. Lookups are confirmed through `approvals`.
+pub fn start(session_state: SessionState, approvals: ApprovalQueue) -> io::Result<BridgeServer> {
+    create_runtime_dir()?;
+
+    let socket = socket_path();
+    if socket.exists() {
+        if UnixStream::connect(&socket).is_ok() {
+            return Err(io::Error::new(io::ErrorKind::AddrInUse, "another Pandabox instance owns the bridge"));
+        }
+        fs::remove_file(&socket)?;
+    }
+
+    let token = generate_token();
+    let token_file = token_path();
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&token_file)?;
+    file.write_all(token.as_bytes())?;
+
+    let listener = UnixListener::bind(&socket)?;
+    info!("Browser bridge listening on {}", socket.display());
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let session_state = session_state.clone();
+                    let approvals = approvals.clone();
+                    let token = token.clone();
+                    thread::spawn(move || {
+                        if let Err(e) = handle_connection(stream, &token, &session_state, &approvals) {
+                            warn!("Browser bridge connection failed: {}", e);
+                        }
+                    });
+                }
+                Err(e) => error!("Browser bridge accept failed: {}", e),
+            }
+        }
+    });
+
+    Ok(BridgeServer { socket, token: token_file })
+}
+
+/// Answer the one request sent over `stream` by the native-messaging host, which has to carry
+/// `token`. Lookups wait for the user to allow them through `approvals`.
+pub fn handle_connection(
+    stream: UnixStream,
+    token: &str,
+    session_state: &SessionState,
+    approvals: &ApprovalQueue,
+) -> Result<(), Box<dyn Error>> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new((&stream).take(MAX_MESSAGE_SIZE as u64));
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+
+    let response = match serde_json::from_str::<BridgeEnvelope>(&line) {
+        Ok(envelope) if tokens_match(&envelope.token, token) => {
+            handle_request(envelope.request, session_state, approvals)
+        }
+        Ok(_) => BridgeResponse::Error { message: String::from("Invalid bridge token") },
+        Err(e) => BridgeResponse::Error { message: format!("Malformed request: {}", e) },
+    };
+
+    let mut writer = &stream;
+    serde_json::to_writer(&mut writer, &response)?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+fn handle_request(
+    request: BridgeRequest,
+    session_state: &SessionState,
+    approvals: &ApprovalQueue,
+) -> BridgeResponse {
+    if session_state.lock().unwrap().is_none() {
+        return BridgeResponse::Locked;
+    }
+
+    match request {
+        BridgeRequest::Status => BridgeResponse::Unlocked,
+        BridgeRequest::Lookup { url, match_mode } => {
+            let allowed = approvals.request(ApprovalRequest {
+                title: String::from("Browser credential request"),
+                message: String::from("A browser extension is asking for the credentials saved for:"),
+                subject: url.clone(),
+            });
+            if !allowed {
+                return BridgeResponse::Denied;
+            }
+
+            match &*session_state.lock().unwrap() {
+                Some(session) => match find_credentials(session, &url, match_mode) {
+                    Ok(credentials) => BridgeResponse::Credentials { credentials },
+                    Err(e) => {
+                        error!("Failed to look up credentials: {}", e);
+                        BridgeResponse::Error { message: String::from("Lookup failed") }
+                    }
+                },
+                None => BridgeResponse::Locked,
+            }
+        }
+    }
+}
+
+fn find_credentials(session: &Session, url: &str, match_mode: LookupMatch) -> Result<Vec<BridgeCredential>, Box<dyn Error>> {
+    let mode = match match_mode {
+        LookupMatch::Exact => UrlMatch::Exact,
+        LookupMatch::Host => UrlMatch::Host,
+        LookupMatch::BaseDomain => UrlMatch::BaseDomain,
+    };
+
+    let mut credentials = Vec::new();
+    for record in session.find_credentials_for_url(url, mode)? {
+        credentials.push(BridgeCredential {
+            password: session.decrypt_password(&record)?,
+            service: record.service,
+            email: record.email,
+            username: record.username,
+        });
+    }
+    Ok(credentials)
+}
+
+fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Compare a token in constant time, so its content cannot be guessed byte by byte
+pub fn tokens_match(provided: &str, expected: &str) -> bool {
+    provided.len() == expected.len()
+        && provided
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
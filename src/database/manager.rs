@@ -1,130 +1,276 @@
-use std::path::PathBuf;
+use std::path::Path;
 use std::fs;
-use std::env;
+use std::sync::{Arc, Mutex};
+use diesel::connection::SimpleConnection;
 use diesel::sqlite::SqliteConnection;
+use diesel::migration::MigrationSource;
+use diesel::sqlite::Sqlite;
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
-use dotenvy::dotenv;
 use diesel::prelude::*;
 use diesel::result::Error;
 
-use crate::database::models::{MasterRecord, NewMasterRecord, NewRecord, Record};
+use crate::error::PandaboxError;
+use crate::paths;
+use crate::database::models::{KeySlotInfo, MasterRecord, NewMasterRecord, NewRecord, NewRecordDetails, NewRecordTag, NewRecordUrl, NewSecretAttribute, Record, RecordChanges, UnlockAttempts, PASSWORD_SLOT};
+use crate::session::url_match::{NormalizedUrl, UrlMatch};
 use crate::database::schema::{master_table};
 use crate::database::schema::records::dsl::*;
 
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
 
+/// A password re-encrypted while the vault is in use: the record id, the ciphertext it was read with
+/// and the new one, only written if the first is still stored
+pub type PasswordSwap = (i32, String, String);
+
+// One row of `PRAGMA integrity_check`
+#[derive(QueryableByName)]
+struct IntegrityCheck {
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    integrity_check: String,
+}
+
+// Applied to the connection when it is opened: WAL lets readers and the writer work side by
+// side, the busy timeout waits for other processes instead of failing with SQLITE_BUSY and
+// SQLite leaves foreign keys unchecked unless asked to.
+const CONNECTION_PRAGMAS: &str = "PRAGMA journal_mode = WAL; PRAGMA busy_timeout = 5000; PRAGMA foreign_keys = ON;";
+
+/// Access to the vault database through a single long-lived connection shared by every clone.
 #[derive(Clone)]
 pub struct DatabaseManager {
-    database_url: String,
+    connection: Arc<Mutex<SqliteConnection>>,
 }
 
 impl DatabaseManager {
-    pub fn new() -> Self {
-        dotenv().ok();
-        
-        // Get the home directory and create the application directory
-        let home_dir = env::var("HOME")
-            .or_else(|_| env::var("USERPROFILE"))
-            .map(PathBuf::from)
-            .unwrap_or_else(|_| PathBuf::from("."));
-            
-        let app_dir = home_dir.join("Pandabox");
-        fs::create_dir_all(&app_dir).expect("Failed to create application directory");
-        
-        // Set the database path
-        let db_path = app_dir.join("pandabox.db");
-        let database_url = db_path.to_str().expect("Invalid database path").to_string();
-        
-        // Set the DATABASE_URL environment variable for Diesel CLI
-        // SAFETY: This is safe because we're setting it before any other threads are spawned
-        // and it's only used for database connection initialization
-        unsafe {
-            std::env::set_var("DATABASE_URL", &database_url);
+    /// Open the vault in the data directory, creating it if needed. A vault from an older
+    /// release in `~/Pandabox` is moved there first.
+    pub fn new() -> Result<Self, PandaboxError> {
+        paths::migrate_legacy_vault()?;
+        Self::open(paths::default_vault_path())
+    }
+
+    /// Open the vault database at `path`, creating the file and bringing its schema up to date
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, PandaboxError> {
+        if let Some(parent) = path.as_ref().parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
         }
-        
+
+        let database_url = path
+            .as_ref()
+            .to_str()
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "database path is not valid UTF-8"))?;
+
         // Create or connect to the database
-        let mut connection = SqliteConnection::establish(&database_url)
-            .unwrap_or_else(|e| panic!("Error connecting to {}: {}", database_url, e));
+        let mut connection = SqliteConnection::establish(database_url)?;
+        // The first statement to touch the file, anything but a SQLite database fails here
+        connection
+            .batch_execute(CONNECTION_PRAGMAS)
+            .map_err(|e| PandaboxError::CorruptVault(e.to_string()))?;
 
         // Run migrations
-        connection
-            .run_pending_migrations(MIGRATIONS)
-            .expect("Failed to run migrations");
+        connection.run_pending_migrations(MIGRATIONS).map_err(PandaboxError::Migration)?;
 
-        DatabaseManager { database_url }
+        Ok(DatabaseManager {
+            connection: Arc::new(Mutex::new(connection)),
+        })
     }
 
-    fn establish_connection(&self) -> SqliteConnection {
-        SqliteConnection::establish(&self.database_url)
-            .unwrap_or_else(|_| panic!("Error connecting to {}", self.database_url))
+    /// Run `operation` on the shared connection. Operations never nest, so the lock is only
+    /// held for the duration of a single query or transaction.
+    fn with_connection<T>(&self, operation: impl FnOnce(&mut SqliteConnection) -> QueryResult<T>) -> QueryResult<T> {
+        // A panic while holding the lock leaves no half-applied state behind, transactions
+        // are rolled back by SQLite, so the connection stays usable
+        let mut connection = self.connection.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        operation(&mut connection)
     }
 
-    pub fn create_master_record(
-        &self,
-        salt: &[u8],
-        encrypted_master: &[u8],
-        nonce: &[u8],
-    ) -> QueryResult<usize> {
-        let mut connection = self.establish_connection();
-        let new_master_record = NewMasterRecord {
-            salt,
-            encrypted_master_key: encrypted_master,
-            nonce,
-        };
+    /// Store the master key wrapped in `new_slot`, replacing the key already in the slot of
+    /// that name
+    pub fn set_key_slot(&self, new_slot: &NewMasterRecord) -> QueryResult<usize> {
+        self.with_connection(|connection| {
+            connection.transaction(|connection| upsert_key_slot(connection, new_slot))
+        })
+    }
+
+    pub fn remove_key_slot(&self, name: &str) -> QueryResult<usize> {
+        use crate::database::schema::master_table::dsl::*;
 
-        diesel::insert_into(master_table::dsl::master_table)
-            .values(&new_master_record)
-            .execute(&mut connection)
+        self.with_connection(|connection| {
+            diesel::delete(master_table.filter(slot.eq(name))).execute(connection)
+        })
+    }
+
+    /// Every key slot of the vault, in the order they were added
+    pub fn list_key_slots(&self) -> QueryResult<Vec<KeySlotInfo>> {
+        use crate::database::schema::master_table::dsl::*;
+
+        self.with_connection(|connection| {
+            master_table
+                .order(id.asc())
+                .select(KeySlotInfo::as_select())
+                .load(connection)
+        })
+    }
+
+    /// The key slots protected with `slot_kind`, in the order they were added
+    pub fn get_key_slots_of_kind(&self, slot_kind: &str) -> QueryResult<Vec<MasterRecord>> {
+        use crate::database::schema::master_table::dsl::*;
+
+        self.with_connection(|connection| {
+            master_table
+                .filter(kind.eq(slot_kind))
+                .order(id.asc())
+                .select(MasterRecord::as_select())
+                .load(connection)
+        })
+    }
+
+    pub fn has_key_slot(&self, name: &str) -> QueryResult<bool> {
+        use crate::database::schema::master_table::dsl::*;
+
+        let count = self.with_connection(|connection| {
+            master_table
+                .filter(slot.eq(name))
+                .count()
+                .get_result::<i64>(connection)
+        })?;
+        Ok(count > 0)
     }
 
     pub fn check_master_table_exists(&self) -> QueryResult<bool> {
         use crate::database::schema::master_table::dsl::*;
 
-        let mut connection = self.establish_connection();
-        let count = master_table
-            .limit(1)
-            .count()
-            .get_result::<i64>(&mut connection)?;
+        let count = self.with_connection(|connection| {
+            master_table
+                .filter(slot.eq(PASSWORD_SLOT))
+                .count()
+                .get_result::<i64>(connection)
+        })?;
         Ok(count > 0)
     }
 
     pub fn get_master_record(&self) -> QueryResult<MasterRecord> {
+        self.get_key_slot(PASSWORD_SLOT)
+    }
+
+    pub fn get_key_slot(&self, name: &str) -> QueryResult<MasterRecord> {
         use crate::database::schema::master_table::dsl::*;
 
-        let mut connection = self.establish_connection();
-        master_table.first(&mut connection)
+        self.with_connection(|connection| {
+            master_table
+                .filter(slot.eq(name))
+                .select(MasterRecord::as_select())
+                .first(connection)
+        })
     }
 
-    pub fn insert_entry(
-        &self,
-        service_name: &str,
-        email_str: &str,
-        username_str: &str,
-        password_str: &str,
-        notes_str: &str,
-    ) -> QueryResult<Record> {
-
-
-        let mut connection = self.establish_connection();
-        let new_record = NewRecord {
-            service: service_name,
-            email: email_str,
-            username: username_str,
-            password: password_str,
-            notes: notes_str,
-        };
+    pub fn get_unlock_attempts(&self) -> QueryResult<UnlockAttempts> {
+        use crate::database::schema::master_table::dsl::*;
+
+        self.with_connection(|connection| {
+            master_table
+                .filter(slot.eq(PASSWORD_SLOT))
+                .select(UnlockAttempts::as_select())
+                .first(connection)
+        })
+    }
+
+    /// Count a failed unlock made at `now` and return the updated attempts
+    pub fn record_failed_unlock(&self, now: i64) -> QueryResult<UnlockAttempts> {
+        use crate::database::schema::master_table::dsl::*;
+
+        self.with_connection(|connection| {
+            connection.transaction(|connection| {
+                diesel::update(master_table.filter(slot.eq(PASSWORD_SLOT)))
+                    .set((failed_unlocks.eq(failed_unlocks + 1), last_failed_unlock.eq(now)))
+                    .execute(connection)?;
+                master_table
+                    .filter(slot.eq(PASSWORD_SLOT))
+                    .select(UnlockAttempts::as_select())
+                    .first(connection)
+            })
+        })
+    }
+
+    pub fn reset_failed_unlocks(&self) -> QueryResult<usize> {
+        use crate::database::schema::master_table::dsl::*;
+
+        self.with_connection(|connection| {
+            diesel::update(master_table.filter(slot.eq(PASSWORD_SLOT)))
+                .set((failed_unlocks.eq(0), last_failed_unlock.eq(0)))
+                .execute(connection)
+        })
+    }
+
+    pub fn set_wipe_after_failures(&self, limit: i32) -> QueryResult<usize> {
+        use crate::database::schema::master_table::dsl::*;
+
+        self.with_connection(|connection| {
+            diesel::update(master_table.filter(slot.eq(PASSWORD_SLOT)))
+                .set(wipe_after_failures.eq(limit))
+                .execute(connection)
+        })
+    }
+
+    /// Delete the master key and every record, leaving an empty vault behind. The file is
+    /// rewritten afterwards so the deleted pages do not linger in it or in the WAL.
+    pub fn wipe(&self) -> QueryResult<()> {
+        use crate::database::schema::{record_tags, record_urls, secret_attributes, vault_format, vault_settings};
+
+        self.with_connection(|connection| {
+            connection.transaction(|connection| {
+                diesel::delete(record_urls::table).execute(connection)?;
+                diesel::delete(record_tags::table).execute(connection)?;
+                diesel::delete(secret_attributes::table).execute(connection)?;
+                diesel::delete(records).execute(connection)?;
+                diesel::delete(vault_settings::table).execute(connection)?;
+                diesel::delete(vault_format::table).execute(connection)?;
+                diesel::delete(master_table::table).execute(connection)?;
+                Ok::<_, Error>(())
+            })?;
+            connection.batch_execute("VACUUM; PRAGMA wal_checkpoint(TRUNCATE);")
+        })
+    }
 
-        // Insert the record
-        diesel::insert_into(records)
-            .values(&new_record)
-            .execute(&mut connection)?;
+    /// Insert a record whose password is bound to its id: `seal` receives the new id and
+    /// returns the password to store. Both happen in one transaction, the password given in
+    /// `new_record` is only a placeholder.
+    pub fn insert_entry(&self, new_record: &NewRecord, seal: impl FnOnce(i32) -> QueryResult<String>) -> QueryResult<Record> {
+        self.with_connection(|connection| {
+            connection.transaction(|connection| {
+                let last_id = insert_sealed(connection, new_record, seal)?;
 
-        // Get the ID of the last inserted record
-        let last_id: i32 = diesel::select(diesel::dsl::sql::<diesel::sql_types::Integer>("last_insert_rowid()"))
-            .get_result(&mut connection)?;
+                // Fetch the complete record
+                records.find(last_id).first(connection)
+            })
+        })
+    }
 
-        // Fetch the complete record
-        records.find(last_id).first(&mut connection)
+    /// Insert every record in a single transaction, nothing is written if any insert fails.
+    /// `details` holds what goes next to the record at the same index, records past its end get
+    /// none. `seal` receives the index of each record and its new id and returns its password,
+    /// like for `insert_entry`. `progress` receives the number of records written so far and
+    /// the total, returning `false` rolls everything back with `Error::RollbackTransaction`.
+    pub fn insert_entries(
+        &self,
+        new_records: &[NewRecord],
+        details: &[NewRecordDetails],
+        mut seal: impl FnMut(usize, i32) -> QueryResult<String>,
+        mut progress: impl FnMut(usize, usize) -> bool,
+    ) -> QueryResult<usize> {
+        self.with_connection(|connection| {
+            connection.transaction(|connection| {
+                for (index, new_record) in new_records.iter().enumerate() {
+                    let record_id = insert_sealed(connection, new_record, |record_id| seal(index, record_id))?;
+                    if let Some(details) = details.get(index) {
+                        insert_details(connection, record_id, details)?;
+                    }
+                    if !progress(index + 1, new_records.len()) {
+                        return Err(Error::RollbackTransaction);
+                    }
+                }
+                Ok(new_records.len())
+            })
+        })
     }
 
     pub fn delete_entry(
@@ -132,51 +278,616 @@ impl DatabaseManager {
         index: i32,
     ) -> Result<usize, Error> {
 
-        let mut connection = self.establish_connection();
-
         // Assuming `Record` is a model you're working with and it implements the Diesel traits
         use crate::database::schema::records::dsl::*;
-        let target_record = records.find(index);
+        use crate::database::schema::{record_tags, record_urls, secret_attributes};
+
+        self.with_connection(|connection| {
+            connection.transaction(|connection| {
+                // Remove the websites, tags and attributes attached to the record first
+                diesel::delete(record_urls::table.filter(record_urls::record_id.eq(index)))
+                    .execute(connection)?;
+                diesel::delete(record_tags::table.filter(record_tags::record_id.eq(index)))
+                    .execute(connection)?;
+                diesel::delete(secret_attributes::table.filter(secret_attributes::record_id.eq(index)))
+                    .execute(connection)?;
 
-        // Execute delete operation and return the number of affected rows
-        let result = diesel::delete(target_record).execute(&mut connection)?;
+                // Execute delete operation and return the number of affected rows
+                let target_record = records.find(index);
+                diesel::delete(target_record).execute(connection)
+            })
+        })
+    }
+
+    /// Move a record to the trash, it is left out of every lookup until restored
+    pub fn trash_entry(&self, record_id: i32, now: i64) -> QueryResult<usize> {
+        self.with_connection(|connection| {
+            diesel::update(records.find(record_id).filter(deleted_at.is_null()))
+                .set(deleted_at.eq(now))
+                .execute(connection)
+        })
+    }
+
+    /// Move several records to the trash at once, returns how many were moved
+    pub fn trash_entries(&self, record_ids: &[i32], now: i64) -> QueryResult<usize> {
+        self.with_connection(|connection| {
+            diesel::update(records.filter(id.eq_any(record_ids)).filter(deleted_at.is_null()))
+                .set(deleted_at.eq(now))
+                .execute(connection)
+        })
+    }
 
-        Ok(result)
+    /// Take a record out of the trash
+    pub fn restore_entry(&self, record_id: i32) -> QueryResult<usize> {
+        self.restore_entries(&[record_id])
     }
+
+    /// Take several records out of the trash, returns how many were restored
+    pub fn restore_entries(&self, record_ids: &[i32]) -> QueryResult<usize> {
+        self.with_connection(|connection| {
+            diesel::update(records.filter(id.eq_any(record_ids)).filter(deleted_at.is_not_null()))
+                .set(deleted_at.eq(None::<i64>))
+                .execute(connection)
+        })
+    }
+
+    /// Delete the records moved to the trash at or before `deleted_before` for good, together
+    /// with their websites, tags and attributes. Returns the number of records deleted.
+    pub fn purge_trash(&self, deleted_before: i64) -> QueryResult<usize> {
+        use crate::database::schema::{record_tags, record_urls, secret_attributes};
+
+        self.with_connection(|connection| {
+            connection.transaction(|connection| {
+                let expired = records.filter(deleted_at.le(deleted_before)).select(id);
+                diesel::delete(record_urls::table.filter(record_urls::record_id.eq_any(expired)))
+                    .execute(connection)?;
+                diesel::delete(record_tags::table.filter(record_tags::record_id.eq_any(expired)))
+                    .execute(connection)?;
+                diesel::delete(secret_attributes::table.filter(secret_attributes::record_id.eq_any(expired)))
+                    .execute(connection)?;
+                diesel::delete(records.filter(deleted_at.le(deleted_before))).execute(connection)
+            })
+        })
+    }
+
+    /// Records in use, the trash left out
     pub fn get_all_records(&self) -> QueryResult<Vec<Record>> {
         use crate::database::schema::records::dsl::*;
         
-        let mut connection = self.establish_connection();
-        records.load::<Record>(&mut connection)
+        self.with_connection(|connection| records.filter(deleted_at.is_null()).load::<Record>(connection))
+    }
+
+    /// Every record of the vault, those in the trash included
+    pub fn get_records_with_trash(&self) -> QueryResult<Vec<Record>> {
+        self.with_connection(|connection| records.load::<Record>(connection))
+    }
+
+    /// Records in the trash, most recently deleted first
+    pub fn get_trashed_records(&self) -> QueryResult<Vec<Record>> {
+        self.with_connection(|connection| {
+            records
+                .filter(deleted_at.is_not_null())
+                .order(deleted_at.desc())
+                .load::<Record>(connection)
+        })
     }
 
     pub fn get_record_by_id(&self, record_id: i32) -> QueryResult<Record> {
         use crate::database::schema::records::dsl::*;
         
-        let mut connection = self.establish_connection();
-        records.find(record_id).first::<Record>(&mut connection)
+        self.with_connection(|connection| records.find(record_id).first::<Record>(connection))
     }
 
-    pub fn update_record(
+    /// The records with the given ids, those in the trash left out
+    pub fn get_records_by_ids(&self, record_ids: &[i32]) -> QueryResult<Vec<Record>> {
+        self.with_connection(|connection| {
+            records
+                .filter(id.eq_any(record_ids))
+                .filter(deleted_at.is_null())
+                .load::<Record>(connection)
+        })
+    }
+
+    pub fn get_records_by_kind(&self, record_kind: &str) -> QueryResult<Vec<Record>> {
+        use crate::database::schema::records::dsl::*;
+
+        self.with_connection(|connection| {
+            records
+                .filter(kind.eq(record_kind))
+                .filter(deleted_at.is_null())
+                .load::<Record>(connection)
+        })
+    }
+
+    pub fn update_record(&self, record_id: i32, changes: &RecordChanges) -> QueryResult<usize> {
+        use crate::database::schema::records::dsl::*;
+
+        self.with_connection(|connection| {
+            diesel::update(records.find(record_id))
+                .set(changes)
+                .execute(connection)
+        })
+    }
+
+    /// Replace the websites attached to a record
+    pub fn set_record_urls(&self, target_record_id: i32, urls: &[NormalizedUrl]) -> QueryResult<usize> {
+        use crate::database::schema::record_urls::dsl::*;
+
+        let new_urls = new_record_urls(target_record_id, urls);
+        self.with_connection(|connection| {
+            connection.transaction(|connection| {
+                diesel::delete(record_urls.filter(record_id.eq(target_record_id))).execute(connection)?;
+                diesel::insert_into(record_urls).values(&new_urls).execute(connection)
+            })
+        })
+    }
+
+    /// All websites as `(record_id, url)` pairs
+    pub fn get_all_record_urls(&self) -> QueryResult<Vec<(i32, String)>> {
+        use crate::database::schema::record_urls::dsl::*;
+
+        self.with_connection(|connection| {
+            record_urls
+                .order(id)
+                .select((record_id, url))
+                .load::<(i32, String)>(connection)
+        })
+    }
+
+    /// File several records under `target_folder`, an empty name takes them out of their folder.
+    /// Records in the trash are left out. Returns the number of records changed.
+    pub fn set_folder(&self, record_ids: &[i32], target_folder: &str) -> QueryResult<usize> {
+        self.with_connection(|connection| {
+            diesel::update(records.filter(id.eq_any(record_ids)).filter(deleted_at.is_null()))
+                .set(folder.eq(target_folder))
+                .execute(connection)
+        })
+    }
+
+    /// Attach `new_tag` to several records, those already tagged with it and those in the trash
+    /// are left as they are. Returns the number of records newly tagged.
+    pub fn add_tag(&self, record_ids: &[i32], new_tag: &str) -> QueryResult<usize> {
+        use crate::database::schema::record_tags;
+
+        self.with_connection(|connection| {
+            connection.transaction(|connection| {
+                let active: Vec<i32> = records
+                    .filter(id.eq_any(record_ids))
+                    .filter(deleted_at.is_null())
+                    .select(id)
+                    .load(connection)?;
+                let new_tags: Vec<NewRecordTag> = active
+                    .into_iter()
+                    .map(|record_id| NewRecordTag { record_id, tag: new_tag })
+                    .collect();
+                diesel::insert_or_ignore_into(record_tags::table)
+                    .values(&new_tags)
+                    .execute(connection)
+            })
+        })
+    }
+
+    /// All tags as `(record_id, tag)` pairs
+    pub fn get_all_record_tags(&self) -> QueryResult<Vec<(i32, String)>> {
+        use crate::database::schema::record_tags::dsl::*;
+
+        self.with_connection(|connection| {
+            record_tags
+                .order(tag)
+                .select((record_id, tag))
+                .load::<(i32, String)>(connection)
+        })
+    }
+
+    /// Replace the Secret Service attributes attached to a record
+    pub fn set_secret_attributes(&self, target_record_id: i32, attributes: &[(&str, &str)]) -> QueryResult<usize> {
+        use crate::database::schema::secret_attributes::dsl::*;
+
+        let new_attributes: Vec<NewSecretAttribute> = attributes
+            .iter()
+            .map(|(attribute_name, attribute_value)| NewSecretAttribute {
+                record_id: target_record_id,
+                name: attribute_name,
+                value: attribute_value,
+            })
+            .collect();
+
+        self.with_connection(|connection| {
+            connection.transaction(|connection| {
+                diesel::delete(secret_attributes.filter(record_id.eq(target_record_id))).execute(connection)?;
+                diesel::insert_into(secret_attributes).values(&new_attributes).execute(connection)
+            })
+        })
+    }
+
+    /// All Secret Service attributes as `(record_id, name, value)` tuples
+    pub fn get_all_secret_attributes(&self) -> QueryResult<Vec<(i32, String, String)>> {
+        use crate::database::schema::secret_attributes::dsl::*;
+
+        self.with_connection(|connection| {
+            secret_attributes
+                .order(id)
+                .select((record_id, name, value))
+                .load::<(i32, String, String)>(connection)
+        })
+    }
+
+    /// Problems found by SQLite's `PRAGMA integrity_check`, empty for a sound file
+    pub fn integrity_check(&self) -> QueryResult<Vec<String>> {
+        let results = self.with_connection(|connection| {
+            diesel::sql_query("PRAGMA integrity_check").load::<IntegrityCheck>(connection)
+        })?;
+        Ok(results
+            .into_iter()
+            .map(|result| result.integrity_check)
+            .filter(|result| result != "ok")
+            .collect())
+    }
+
+    /// Migrations of this version that the vault has not applied yet
+    pub fn pending_migrations(&self) -> Result<Vec<String>, PandaboxError> {
+        let pending = self
+            .with_connection(|connection| Ok(connection.pending_migrations(MIGRATIONS)))?
+            .map_err(PandaboxError::Migration)?;
+        Ok(pending.iter().map(|migration| migration.name().to_string()).collect())
+    }
+
+    /// Migrations applied to the vault that this version does not know, left by a newer one
+    pub fn unknown_migrations(&self) -> Result<Vec<String>, PandaboxError> {
+        let applied = self
+            .with_connection(|connection| Ok(connection.applied_migrations()))?
+            .map_err(PandaboxError::Migration)?;
+        let known: Vec<String> = MigrationSource::<Sqlite>::migrations(&MIGRATIONS)
+            .map_err(PandaboxError::Migration)?
+            .iter()
+            .map(|migration| migration.name().version().to_string())
+            .collect();
+        Ok(applied
+            .iter()
+            .map(|version| version.to_string())
+            .filter(|version| !known.contains(version))
+            .collect())
+    }
+
+    /// Websites attached to records that no longer exist, as record id and URL
+    pub fn get_orphaned_record_urls(&self) -> QueryResult<Vec<(i32, String)>> {
+        use crate::database::schema::record_urls;
+
+        self.with_connection(|connection| {
+            record_urls::table
+                .filter(record_urls::record_id.ne_all(records.select(id)))
+                .select((record_urls::record_id, record_urls::url))
+                .load(connection)
+        })
+    }
+
+    /// Tags attached to records that no longer exist, as record id and tag
+    pub fn get_orphaned_record_tags(&self) -> QueryResult<Vec<(i32, String)>> {
+        use crate::database::schema::record_tags;
+
+        self.with_connection(|connection| {
+            record_tags::table
+                .filter(record_tags::record_id.ne_all(records.select(id)))
+                .select((record_tags::record_id, record_tags::tag))
+                .load(connection)
+        })
+    }
+
+    /// Lookup attributes attached to records that no longer exist, as record id and name
+    pub fn get_orphaned_secret_attributes(&self) -> QueryResult<Vec<(i32, String)>> {
+        use crate::database::schema::secret_attributes;
+
+        self.with_connection(|connection| {
+            secret_attributes::table
+                .filter(secret_attributes::record_id.ne_all(records.select(id)))
+                .select((secret_attributes::record_id, secret_attributes::name))
+                .load(connection)
+        })
+    }
+
+    /// Layout of the encrypted fields, see the `create_vault_format` migration
+    pub fn get_ciphertext_format(&self) -> QueryResult<i32> {
+        use crate::database::schema::vault_format::dsl::*;
+
+        let format = self.with_connection(|connection| {
+            vault_format
+                .select(ciphertext_format)
+                .first::<i32>(connection)
+                .optional()
+        })?;
+        Ok(format.unwrap_or(0))
+    }
+
+    pub fn set_ciphertext_format(&self, format: i32) -> QueryResult<usize> {
+        use crate::database::schema::vault_format::dsl::*;
+
+        self.with_connection(|connection| {
+            diesel::replace_into(vault_format)
+                .values((id.eq(1), ciphertext_format.eq(format)))
+                .execute(connection)
+        })
+    }
+
+    /// Replace the encrypted passwords and vault settings with `passwords` and `settings` and
+    /// record the new `format`, all in one transaction
+    pub fn rewrite_ciphertexts(&self, passwords: &[(i32, String)], settings: Option<&str>, format: i32) -> QueryResult<()> {
+        self.with_connection(|connection| {
+            connection.transaction(|connection| write_ciphertexts(connection, passwords, settings, format))
+        })
+    }
+
+    /// Replace fields re-encrypted in the background, given as their id, the ciphertext they
+    /// were read with and the new one, in one transaction. A field changed since it was read
+    /// keeps its newer value. The new `format` is only recorded once no field is left that
+    /// `is_outdated` still flags. Returns the number replaced and the number still outdated.
+    pub fn swap_ciphertexts(
         &self,
-        record_id: i32,
-        service_name: &str,
-        email_str: &str,
-        username_str: &str,
-        password_str: &str,
-        notes_str: &str,
-    ) -> QueryResult<usize> {
+        passwords: &[PasswordSwap],
+        settings: Option<(&str, &str)>,
+        format: i32,
+        is_outdated: impl Fn(&str) -> bool,
+    ) -> QueryResult<(usize, usize)> {
+        use crate::database::schema::{vault_format, vault_settings};
+
+        self.with_connection(|connection| {
+            connection.transaction(|connection| {
+                let mut swapped = 0;
+                for (record_id, read, encrypted_password) in passwords {
+                    swapped += diesel::update(records.find(record_id).filter(password.eq(read)))
+                        .set(password.eq(encrypted_password))
+                        .execute(connection)?;
+                }
+                if let Some((read, encrypted_settings)) = settings {
+                    swapped += diesel::update(vault_settings::table.filter(vault_settings::data.eq(read)))
+                        .set(vault_settings::data.eq(encrypted_settings))
+                        .execute(connection)?;
+                }
+
+                let stored: Vec<String> = records.select(password).load(connection)?;
+                let stored_settings: Option<String> = vault_settings::table
+                    .select(vault_settings::data)
+                    .first(connection)
+                    .optional()?;
+                let outdated = stored.iter().chain(stored_settings.iter()).filter(|field| is_outdated(field)).count();
+                if outdated == 0 {
+                    diesel::replace_into(vault_format::table)
+                        .values((vault_format::id.eq(1), vault_format::ciphertext_format.eq(format)))
+                        .execute(connection)?;
+                }
+                Ok((swapped, outdated))
+            })
+        })
+    }
+
+    /// Switch the vault to a new master key in one transaction, provided nothing changed since
+    /// the fields were read. Every password is given as its record id, the ciphertext it was
+    /// read with and the new one, the settings as the ciphertext read, `None` when there were
+    /// none, and the new one. The new key is wrapped in `password_slot` and every other key
+    /// slot, which still holds the old key, is deleted. Returns the names of the deleted slots,
+    /// or `None` without writing anything when a field changed or a record was added or deleted
+    /// meanwhile: it would be left encrypted with a key that no longer exists.
+    pub fn replace_master_key(
+        &self,
+        passwords: &[PasswordSwap],
+        settings: (Option<&str>, Option<&str>),
+        format: i32,
+        password_slot: &NewMasterRecord,
+    ) -> QueryResult<Option<Vec<String>>> {
+        use crate::database::schema::{master_table, vault_format, vault_settings};
+
+        let replaced = self.with_connection(|connection| {
+            connection.transaction(|connection| {
+                let (settings_read, encrypted_settings) = settings;
+                let record_count: i64 = records.count().get_result(connection)?;
+                if record_count != passwords.len() as i64 {
+                    return Err(Error::RollbackTransaction);
+                }
+                for (record_id, read, encrypted_password) in passwords {
+                    let swapped = diesel::update(records.find(record_id).filter(password.eq(read)))
+                        .set(password.eq(encrypted_password))
+                        .execute(connection)?;
+                    if swapped == 0 {
+                        return Err(Error::RollbackTransaction);
+                    }
+                }
+                let stored_settings = vault_settings::table
+                    .select(vault_settings::data)
+                    .first::<String>(connection)
+                    .optional()?;
+                if stored_settings.as_deref() != settings_read {
+                    return Err(Error::RollbackTransaction);
+                }
+                if let Some(encrypted_settings) = encrypted_settings {
+                    diesel::update(vault_settings::table)
+                        .set(vault_settings::data.eq(encrypted_settings))
+                        .execute(connection)?;
+                }
+                diesel::replace_into(vault_format::table)
+                    .values((vault_format::id.eq(1), vault_format::ciphertext_format.eq(format)))
+                    .execute(connection)?;
+
+                upsert_key_slot(connection, password_slot)?;
+                let stale = master_table::table
+                    .filter(master_table::slot.ne(password_slot.slot))
+                    .select(master_table::slot)
+                    .load::<String>(connection)?;
+                diesel::delete(master_table::table.filter(master_table::slot.ne(password_slot.slot)))
+                    .execute(connection)?;
+                Ok(stale)
+            })
+        });
+        match replaced {
+            Ok(stale) => Ok(Some(stale)),
+            Err(Error::RollbackTransaction) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The encrypted vault settings, `None` until they are first saved
+    pub fn get_vault_settings(&self) -> QueryResult<Option<String>> {
+        use crate::database::schema::vault_settings::dsl::*;
+
+        self.with_connection(|connection| {
+            vault_settings
+                .select(data)
+                .first::<String>(connection)
+                .optional()
+        })
+    }
+
+    pub fn set_vault_settings(&self, encrypted_settings: &str) -> QueryResult<usize> {
+        use crate::database::schema::vault_settings::dsl::*;
+
+        self.with_connection(|connection| {
+            diesel::replace_into(vault_settings)
+                .values((id.eq(1), data.eq(encrypted_settings)))
+                .execute(connection)
+        })
+    }
+
+    /// Find the records with a website matching the given URL
+    pub fn find_records_by_url(&self, target: &NormalizedUrl, mode: UrlMatch) -> QueryResult<Vec<Record>> {
+        use crate::database::schema::record_urls;
+        use crate::database::schema::records::dsl::*;
+
+        let matching_ids = match mode {
+            UrlMatch::Exact => record_urls::table
+                .filter(record_urls::url.eq(&target.url))
+                .select(record_urls::record_id)
+                .into_boxed(),
+            UrlMatch::Host => record_urls::table
+                .filter(record_urls::host.eq(&target.host))
+                .select(record_urls::record_id)
+                .into_boxed(),
+            UrlMatch::BaseDomain => record_urls::table
+                .filter(record_urls::base_domain.eq(&target.base_domain))
+                .select(record_urls::record_id)
+                .into_boxed(),
+        };
+
+        self.with_connection(|connection| {
+            records
+                .filter(id.eq_any(matching_ids))
+                .filter(deleted_at.is_null())
+                .load::<Record>(connection)
+        })
+    }
+
+    /// Find the records whose service, email, username, notes, folder or tags contain the given
+    /// text
+    pub fn search_records(&self, query: &str) -> QueryResult<Vec<Record>> {
         use crate::database::schema::records::dsl::*;
+        use crate::database::schema::record_tags;
+
+        let pattern = format!("%{}%", query.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+        self.with_connection(|connection| {
+            let tagged = record_tags::table
+                .filter(record_tags::tag.like(&pattern).escape('\\'))
+                .select(record_tags::record_id);
+            records
+                .filter(
+                    service.like(&pattern).escape('\\')
+                        .or(email.like(&pattern).escape('\\'))
+                        .or(username.like(&pattern).escape('\\'))
+                        .or(notes.like(&pattern).escape('\\'))
+                        .or(folder.like(&pattern).escape('\\'))
+                        .or(id.eq_any(tagged)),
+                )
+                .filter(deleted_at.is_null())
+                .load::<Record>(connection)
+        })
+    }
+}
+
+/// Insert `new_record`, then store the password `seal` makes for its id. Returns the id.
+fn insert_sealed(connection: &mut SqliteConnection, new_record: &NewRecord, seal: impl FnOnce(i32) -> QueryResult<String>) -> QueryResult<i32> {
+    diesel::insert_into(records)
+        .values(new_record)
+        .execute(connection)?;
 
-        let mut connection = self.establish_connection();
+    // Get the ID of the last inserted record
+    let last_id: i32 = diesel::select(diesel::dsl::sql::<diesel::sql_types::Integer>("last_insert_rowid()"))
+        .get_result(connection)?;
+
+    let sealed_password = seal(last_id)?;
+    diesel::update(records.find(last_id))
+        .set(password.eq(sealed_password))
+        .execute(connection)?;
+    Ok(last_id)
+}
+
+/// Store the folder, websites and tags of the record `target_record_id` that was just inserted
+fn insert_details(connection: &mut SqliteConnection, target_record_id: i32, details: &NewRecordDetails) -> QueryResult<()> {
+    use crate::database::schema::{record_tags, record_urls};
+
+    if !details.folder.is_empty() {
+        diesel::update(records.find(target_record_id))
+            .set(folder.eq(&details.folder))
+            .execute(connection)?;
+    }
+    diesel::insert_into(record_urls::table)
+        .values(&new_record_urls(target_record_id, &details.urls))
+        .execute(connection)?;
+    let new_tags: Vec<NewRecordTag> = details
+        .tags
+        .iter()
+        .map(|new_tag| NewRecordTag { record_id: target_record_id, tag: new_tag })
+        .collect();
+    diesel::insert_or_ignore_into(record_tags::table)
+        .values(&new_tags)
+        .execute(connection)?;
+    Ok(())
+}
+
+fn new_record_urls(target_record_id: i32, urls: &[NormalizedUrl]) -> Vec<NewRecordUrl<'_>> {
+    urls.iter()
+        .map(|normalized| NewRecordUrl {
+            record_id: target_record_id,
+            url: &normalized.url,
+            host: &normalized.host,
+            base_domain: &normalized.base_domain,
+        })
+        .collect()
+}
+
+/// Update the slot named like `new_slot` in place, so the unlock attempts kept in the row
+/// survive, or add it
+fn upsert_key_slot(connection: &mut SqliteConnection, new_slot: &NewMasterRecord) -> QueryResult<usize> {
+    use crate::database::schema::master_table::dsl::*;
+
+    let updated = diesel::update(master_table.filter(slot.eq(new_slot.slot)))
+        .set((
+            salt.eq(new_slot.salt),
+            encrypted_master_key.eq(new_slot.encrypted_master_key),
+            nonce.eq(new_slot.nonce),
+            requires_key_file.eq(new_slot.requires_key_file),
+            kind.eq(new_slot.kind),
+        ))
+        .execute(connection)?;
+    if updated > 0 {
+        return Ok(updated);
+    }
+    diesel::insert_into(master_table)
+        .values(new_slot)
+        .execute(connection)
+}
+
+/// Replace the encrypted passwords and vault settings and record their `format`
+fn write_ciphertexts(connection: &mut SqliteConnection, passwords: &[(i32, String)], settings: Option<&str>, format: i32) -> QueryResult<()> {
+    use crate::database::schema::{vault_format, vault_settings};
+
+    for (record_id, encrypted_password) in passwords {
         diesel::update(records.find(record_id))
-            .set((
-                service.eq(service_name),
-                email.eq(email_str),
-                username.eq(username_str),
-                password.eq(password_str),
-                notes.eq(notes_str),
-            ))
-            .execute(&mut connection)
+            .set(password.eq(encrypted_password))
+            .execute(connection)?;
+    }
+    if let Some(settings) = settings {
+        diesel::update(vault_settings::table)
+            .set(vault_settings::data.eq(settings))
+            .execute(connection)?;
     }
+    diesel::replace_into(vault_format::table)
+        .values((vault_format::id.eq(1), vault_format::ciphertext_format.eq(format)))
+        .execute(connection)?;
+    Ok(())
 }
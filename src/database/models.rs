@@ -1,5 +1,6 @@
 use diesel::prelude::*;
-use crate::database::schema::{master_table, records};
+use crate::database::schema::{master_table, record_tags, record_urls, records, secret_attributes};
+use crate::session::url_match::NormalizedUrl;
 
 /// Represents a record in the database.
 #[derive(Queryable, Selectable)]
@@ -12,6 +13,20 @@ pub struct Record {
     pub username: String,
     pub password: String,
     pub notes: String,
+    pub password_changed_at: i64,
+    pub two_factor: bool,
+    pub kind: String,
+    pub confirm_use: bool,
+    /// When the record was moved to the trash, `None` while it is in use
+    pub deleted_at: Option<i64>,
+    /// Folder the record is filed under, empty for none
+    pub folder: String,
+}
+
+impl Record {
+    pub fn kind(&self) -> RecordKind {
+        RecordKind::parse(&self.kind)
+    }
 }
 
 /// Represents a new record to be inserted into the database.
@@ -23,9 +38,95 @@ pub struct NewRecord<'a> {
     pub username: &'a str,
     pub password: &'a str,
     pub notes: &'a str,
+    pub password_changed_at: i64,
+    pub two_factor: bool,
+    pub kind: &'a str,
+    pub confirm_use: bool,
+}
+
+/// What is stored next to a new record in its own tables, written in the same transaction.
+#[derive(Default)]
+pub struct NewRecordDetails {
+    pub folder: String,
+    pub urls: Vec<NormalizedUrl>,
+    pub tags: Vec<String>,
+}
+
+/// Represents the editable fields of an existing record.
+#[derive(AsChangeset)]
+#[diesel(table_name = records)]
+pub struct RecordChanges<'a> {
+    pub service: &'a str,
+    pub email: &'a str,
+    pub username: &'a str,
+    pub password: &'a str,
+    pub notes: &'a str,
+    pub password_changed_at: Option<i64>,
+    pub two_factor: bool,
+    pub kind: &'a str,
+    pub confirm_use: bool,
+}
+
+/// What a record holds, stored as text in the `kind` column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RecordKind {
+    /// Website or application login, the password field holds the password
+    #[default]
+    Login,
+    /// The password field holds an OpenSSH private key served by the SSH agent
+    SshKey,
 }
 
-/// Represents a master record in the database.
+impl RecordKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RecordKind::Login => "login",
+            RecordKind::SshKey => "ssh-key",
+        }
+    }
+
+    /// Unknown values are treated as logins
+    pub fn parse(kind: &str) -> Self {
+        match kind {
+            "ssh-key" => RecordKind::SshKey,
+            _ => RecordKind::Login,
+        }
+    }
+}
+
+/// Represents a new website to be attached to a record.
+#[derive(Insertable)]
+#[diesel(table_name = record_urls)]
+pub struct NewRecordUrl<'a> {
+    pub record_id: i32,
+    pub url: &'a str,
+    pub host: &'a str,
+    pub base_domain: &'a str,
+}
+
+/// Represents a tag attached to a record.
+#[derive(Insertable)]
+#[diesel(table_name = record_tags)]
+pub struct NewRecordTag<'a> {
+    pub record_id: i32,
+    pub tag: &'a str,
+}
+
+/// Represents a new Secret Service lookup attribute to be attached to a record.
+#[derive(Insertable)]
+#[diesel(table_name = secret_attributes)]
+pub struct NewSecretAttribute<'a> {
+    pub record_id: i32,
+    pub name: &'a str,
+    pub value: &'a str,
+}
+
+/// Slot holding the master key protected by the master password, every vault has it
+pub const PASSWORD_SLOT: &str = "password";
+/// Slot holding the master key protected by the recovery key
+pub const RECOVERY_SLOT: &str = "recovery";
+
+/// Represents a master record in the database, one per key slot.
 #[derive(Queryable, Selectable)]
 #[diesel(table_name = master_table)]
 #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
@@ -34,6 +135,34 @@ pub struct MasterRecord {
     pub encrypted_master_key: Vec<u8>,
     pub nonce: Vec<u8>,
     pub salt: Vec<u8>,
+    pub slot: String,
+    /// The key is derived from the secret together with a key file
+    pub requires_key_file: bool,
+    /// What the slot is protected with, see `encrypt::key_slot`
+    pub kind: String,
+}
+
+/// A key slot as listed to the user, without its key material.
+#[derive(Queryable, Selectable, Clone, Debug)]
+#[diesel(table_name = master_table)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct KeySlotInfo {
+    pub slot: String,
+    pub kind: String,
+    pub requires_key_file: bool,
+}
+
+/// Failed unlocks recorded in the master password slot, readable while the vault is locked.
+#[derive(Queryable, Selectable, Clone, Copy, Debug)]
+#[diesel(table_name = master_table)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct UnlockAttempts {
+    /// Failed unlocks since the last successful one
+    pub failed_unlocks: i32,
+    /// Unix time of the last failed unlock
+    pub last_failed_unlock: i64,
+    /// Failed unlocks after which the vault is wiped, 0 never wipes it
+    pub wipe_after_failures: i32,
 }
 
 /// Represents a new master record to be inserted into the database.
@@ -43,4 +172,7 @@ pub struct NewMasterRecord<'a> {
     pub encrypted_master_key: &'a [u8],
     pub nonce: &'a [u8],
     pub salt: &'a [u8],
+    pub slot: &'a str,
+    pub requires_key_file: bool,
+    pub kind: &'a str,
 }
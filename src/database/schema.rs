@@ -6,6 +6,30 @@ diesel::table! {
         encrypted_master_key -> Binary,
         nonce -> Binary,
         salt -> Binary,
+        failed_unlocks -> Integer,
+        last_failed_unlock -> BigInt,
+        wipe_after_failures -> Integer,
+        slot -> Text,
+        requires_key_file -> Bool,
+        kind -> Text,
+    }
+}
+
+diesel::table! {
+    record_tags (id) {
+        id -> Integer,
+        record_id -> Integer,
+        tag -> Text,
+    }
+}
+
+diesel::table! {
+    record_urls (id) {
+        id -> Integer,
+        record_id -> Integer,
+        url -> Text,
+        host -> Text,
+        base_domain -> Text,
     }
 }
 
@@ -17,7 +41,40 @@ diesel::table! {
         username -> Text,
         password -> Text,
         notes -> Text,
+        password_changed_at -> BigInt,
+        two_factor -> Bool,
+        kind -> Text,
+        confirm_use -> Bool,
+        deleted_at -> Nullable<BigInt>,
+        folder -> Text,
+    }
+}
+
+diesel::table! {
+    secret_attributes (id) {
+        id -> Integer,
+        record_id -> Integer,
+        name -> Text,
+        value -> Text,
+    }
+}
+
+diesel::table! {
+    vault_format (id) {
+        id -> Integer,
+        ciphertext_format -> Integer,
     }
 }
 
-diesel::allow_tables_to_appear_in_same_query!(master_table, records,);
+diesel::table! {
+    vault_settings (id) {
+        id -> Integer,
+        data -> Text,
+    }
+}
+
+diesel::joinable!(record_tags -> records (record_id));
+diesel::joinable!(record_urls -> records (record_id));
+diesel::joinable!(secret_attributes -> records (record_id));
+
+diesel::allow_tables_to_appear_in_same_query!(master_table, record_tags, record_urls, records, secret_attributes, vault_format, vault_settings,);
@@ -3,54 +3,65 @@ use argon2::Algorithm::Argon2id;
 use argon2::Argon2;
 use argon2::ParamsBuilder;
 use chacha20poly1305::aead::generic_array::GenericArray;
-use chacha20poly1305::aead::Aead;
-use chacha20poly1305::consts::U12;
-use chacha20poly1305::{ChaCha20Poly1305, Error as ChaChaError, Key, KeyInit, Nonce};
+use chacha20poly1305::aead::{Aead, Payload};
+use chacha20poly1305::consts::{U12, U24};
+use chacha20poly1305::{ChaCha20Poly1305, Error as ChaChaError, Key, KeyInit, Nonce, XChaCha20Poly1305, XNonce};
 use rand::Rng;
 use rand_core::RngCore;
-use zeroize::Zeroize;
+use crate::encrypt::key_file::KeyFile;
+use crate::encrypt::secure_memory::SecretBytes;
 
 const SALT_LENGTH: usize = 32;
-const PASSWORD_LENGTH: usize = 32;
 
-#[derive(Clone)]
-pub struct CryptEngine {
-    key: Vec<u8>, // Store the Argon2 output (not the ChaCha20 key directly)
+/// First byte of record ciphertexts: ChaCha20-Poly1305 bound to the record by associated data
+pub const CIPHERTEXT_V1: u8 = 0x01;
+/// XChaCha20-Poly1305, like `CIPHERTEXT_V1` with a 192-bit nonce so random nonces do not
+/// collide however many fields a vault encrypts over its life. Written by default.
+pub const CIPHERTEXT_V2: u8 = 0x02;
+
+/// Length of the nonce following the version byte of a record ciphertext, `None` for unknown
+/// versions
+pub fn record_nonce_len(version: u8) -> Option<usize> {
+    match version {
+        CIPHERTEXT_V1 => Some(12),
+        CIPHERTEXT_V2 => Some(24),
+        _ => None,
+    }
 }
 
-impl Drop for CryptEngine {
-    fn drop(&mut self) {
-        self.key.zeroize();
-    }
+#[derive(Clone)]
+pub struct CryptEngine {
+    key: SecretBytes, // Store the Argon2 output (not the ChaCha20 key directly)
 }
 
 impl CryptEngine {
-    pub fn new(password: &str, salt: &[u8]) -> Result<Self, PasswordHashError> {
-        let derived_key = CryptEngine::derive_key(password, salt);
+    /// Derive the key from `password` and, for vaults requiring one, the key file
+    pub fn new(password: &str, key_file: Option<&KeyFile>, salt: &[u8]) -> Result<Self, PasswordHashError> {
+        let derived_key = CryptEngine::derive_key(password, key_file, salt)?;
 
         Ok(CryptEngine {
-            key: derived_key, // Store the whole PasswordHashString
+            key: SecretBytes::from_vec(derived_key), // Store the whole PasswordHashString
         })
     }
 
     //Derive key.  This is a separate function so that it can be called after password verification
-    fn derive_key(password: &str, salt: &[u8]) -> Vec<u8> {
+    // The key file digest is the Argon2 secret, the same password gives another key without it
+    fn derive_key(password: &str, key_file: Option<&KeyFile>, salt: &[u8]) -> Result<Vec<u8>, PasswordHashError> {
         let params = ParamsBuilder::new()
             .m_cost(19456)
             .t_cost(2)
             .p_cost(4)
             .output_len(32)
-            .build()
-            .unwrap();
+            .build()?;
 
-        let derived_key_length = params.output_len(); // Get the output length
-        let mut derived_key = vec![0u8; derived_key_length.unwrap()];
-        let argon2 = Argon2::new(Argon2id, argon2::Version::V0x13, params);
-        argon2
-            .hash_password_into(password.as_bytes(), salt, &mut derived_key)
-            .unwrap();
+        let mut derived_key = vec![0u8; params.output_len().unwrap_or(32)];
+        let argon2 = match key_file {
+            Some(key_file) => Argon2::new_with_secret(key_file.digest(), Argon2id, argon2::Version::V0x13, params)?,
+            None => Argon2::new(Argon2id, argon2::Version::V0x13, params),
+        };
+        argon2.hash_password_into(password.as_bytes(), salt, &mut derived_key)?;
 
-        derived_key
+        Ok(derived_key)
     }
 
     // Function to generate a random master encryption key
@@ -62,7 +73,7 @@ impl CryptEngine {
 
     // Function to encrypt the master key using ChaCha20Poly1305
     pub fn encrypt_master_key(&self, master_key: &[u8]) -> Result<(Vec<u8>, Vec<u8>), ChaChaError> {
-        let key = Key::from_slice(self.key.as_slice()); // Derive key from stored hash
+        let key = Key::from_slice(&self.key); // Derive key from stored hash
         let nonce = Self::generate_nonce();
         let cipher = ChaCha20Poly1305::new(key);
         let ciphertext = cipher.encrypt(&nonce, master_key.as_ref())?;
@@ -75,27 +86,52 @@ impl CryptEngine {
         nonce: &[u8],
         ciphertext: &[u8],
     ) -> Result<Vec<u8>, ChaChaError> {
-        let key = Key::from_slice(self.key.as_slice());
+        let key = Key::from_slice(&self.key);
         let nonce = Nonce::from_slice(nonce);
         let cipher = ChaCha20Poly1305::new(key);
-        let plaintext = cipher.decrypt(&nonce, ciphertext.as_ref())?;
+        let plaintext = cipher.decrypt(nonce, ciphertext.as_ref())?;
         Ok(plaintext)
     }
 
-    pub fn encrypt_record(&self, record: &[u8], master_key: Vec<u8>) -> Result<Vec<u8>, ChaChaError> {
-        let key = Key::from_slice(master_key.as_slice());
-        let nonce = Self::generate_nonce();
-        let cipher = ChaCha20Poly1305::new(key);
-        let ciphertext = cipher.encrypt(&nonce, record.as_ref())?;
-        
-        // Prepend nonce to ciphertext so we can decrypt later
-        let mut result = nonce.to_vec();
+    /// Encrypt a record field with `master_key`, binding the ciphertext to `associated_data`
+    /// so it fails to decrypt anywhere else. The result starts with `CIPHERTEXT_V2` and the nonce.
+    pub fn encrypt_record(&self, record: &[u8], master_key: &[u8], associated_data: &[u8]) -> Result<Vec<u8>, ChaChaError> {
+        let key = Key::from_slice(master_key);
+        let nonce = Self::generate_extended_nonce();
+        let cipher = XChaCha20Poly1305::new(key);
+        let ciphertext = cipher.encrypt(&nonce, Payload { msg: record, aad: associated_data })?;
+
+        // Prepend the version and the nonce to the ciphertext so we can decrypt later
+        let mut result = Vec::with_capacity(1 + nonce.len() + ciphertext.len());
+        result.push(CIPHERTEXT_V2);
+        result.extend_from_slice(&nonce);
         result.extend_from_slice(&ciphertext);
         Ok(result)
     }
 
-    pub fn decrypt_record(&self, data: &[u8], master_key: Vec<u8>) -> Result<Vec<u8>, ChaChaError> {
-        let key = Key::from_slice(master_key.as_slice());
+    /// Decrypt a field written by `encrypt_record` with the same `associated_data`, in any of
+    /// the versioned formats
+    pub fn decrypt_record(&self, data: &[u8], master_key: &[u8], associated_data: &[u8]) -> Result<Vec<u8>, ChaChaError> {
+        let Some((&version, data)) = data.split_first() else {
+            return Err(ChaChaError);
+        };
+        let Some(nonce_len) = record_nonce_len(version).filter(|nonce_len| data.len() >= *nonce_len) else {
+            return Err(ChaChaError);
+        };
+
+        let key = Key::from_slice(master_key);
+        let (nonce, ciphertext) = data.split_at(nonce_len);
+        let payload = Payload { msg: ciphertext, aad: associated_data };
+        match version {
+            CIPHERTEXT_V1 => ChaCha20Poly1305::new(key).decrypt(Nonce::from_slice(nonce), payload),
+            _ => XChaCha20Poly1305::new(key).decrypt(XNonce::from_slice(nonce), payload),
+        }
+    }
+
+    /// Decrypt a field written before ciphertexts had a version and associated data: the nonce
+    /// followed by the ciphertext. Only used to upgrade old vaults.
+    pub fn decrypt_legacy_record(&self, data: &[u8], master_key: &[u8]) -> Result<Vec<u8>, ChaChaError> {
+        let key = Key::from_slice(master_key);
         let cipher = ChaCha20Poly1305::new(key);
         
         // Extract nonce from the data (first 12 bytes)
@@ -110,11 +146,18 @@ impl CryptEngine {
         Ok(plaintext)
     }
 
+    // 192-bit nonce for XChaCha20Poly1305
+    fn generate_extended_nonce() -> GenericArray<u8, U24> {
+        let mut nonce = [0u8; 24];
+        OsRng.fill_bytes(&mut nonce);
+        *XNonce::from_slice(&nonce)
+    }
+
     // Helper function to generate a random nonce
     fn generate_nonce() -> GenericArray<u8, U12> {
         let mut nonce = [0u8; 12]; // 12-bit nonce for ChaCha20Poly1305
         OsRng.fill_bytes(&mut nonce);
-        Nonce::from_slice(&nonce).clone()
+        *Nonce::from_slice(&nonce)
     } // Recommended salt length (in bytes)
 
     pub fn generate_salt() -> Vec<u8> {
@@ -123,25 +166,31 @@ impl CryptEngine {
         salt
     }
     
-    pub fn generate_random_password() -> String {
+    /// Random password of `length` characters (at least 4), with symbols unless `symbols` is false
+    pub fn generate_random_password(length: usize, symbols: bool) -> String {
         const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ\
                                 abcdefghijklmnopqrstuvwxyz\
                                 0123456789\
                                 !@#$%^&*()_+-=[]{}|;:,.<>?";
         
+        let length = length.max(4);
+        // Symbols come last in the charset
+        let charset = if symbols { CHARSET } else { &CHARSET[..62] };
         let mut rng = rand::rng();
-        let mut password = String::with_capacity(PASSWORD_LENGTH);
+        let mut password = String::with_capacity(length);
         
         // Ensure at least one character from each character class for better security
-        password.push(CHARSET[rng.random_range(0..26)] as char); // Uppercase
-        password.push(CHARSET[rng.random_range(26..52)] as char); // Lowercase
-        password.push(CHARSET[rng.random_range(52..62)] as char); // Digit
-        password.push(CHARSET[rng.random_range(62..CHARSET.len())] as char); // Special char
+        password.push(charset[rng.random_range(0..26)] as char); // Uppercase
+        password.push(charset[rng.random_range(26..52)] as char); // Lowercase
+        password.push(charset[rng.random_range(52..62)] as char); // Digit
+        if symbols {
+            password.push(charset[rng.random_range(62..charset.len())] as char); // Special char
+        }
         
         // Fill the rest randomly
-        for _ in 4..PASSWORD_LENGTH {
-            let idx = rng.random_range(0..CHARSET.len());
-            password.push(CHARSET[idx] as char);
+        for _ in password.len()..length {
+            let idx = rng.random_range(0..charset.len());
+            password.push(charset[idx] as char);
         }
         
         // Shuffle to avoid predictable patterns
@@ -149,7 +198,6 @@ impl CryptEngine {
         use rand::seq::SliceRandom;
         bytes.shuffle(&mut rng);
 
-        let password = unsafe { String::from_utf8_unchecked(bytes) };
-        password
+        unsafe { String::from_utf8_unchecked(bytes) }
     }
 }
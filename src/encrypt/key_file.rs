@@ -0,0 +1,49 @@
+// Key files are a second unlock factor: any file, whose SHA-256 digest is given to Argon2 as
+// its secret input next to the master password. Without the file the password alone derives
+// a different key.
+use std::fs;
+use std::io;
+use std::path::Path;
+use rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use zeroize::{Zeroize, Zeroizing};
+
+// Size of the random files made by `KeyFile::create`
+const GENERATED_KEY_FILE_LENGTH: usize = 64;
+
+/// The digest of a key file, the file content itself is not kept
+pub struct KeyFile {
+    digest: Zeroizing<[u8; 32]>,
+}
+
+impl KeyFile {
+    pub fn from_bytes(content: &[u8]) -> Self {
+        KeyFile {
+            digest: Zeroizing::new(Sha256::digest(content).into()),
+        }
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut content = fs::read(path)?;
+        let key_file = KeyFile::from_bytes(&content);
+        content.zeroize();
+        Ok(key_file)
+    }
+
+    /// Write a new file of random bytes at `path` and return it as a key file. An existing
+    /// file is never overwritten, losing it could lock a vault for good.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut content = Zeroizing::new([0u8; GENERATED_KEY_FILE_LENGTH]);
+        OsRng.fill_bytes(content.as_mut());
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .and_then(|mut file| io::Write::write_all(&mut file, content.as_ref()))?;
+        Ok(KeyFile::from_bytes(content.as_ref()))
+    }
+
+    pub(crate) fn digest(&self) -> &[u8] {
+        self.digest.as_ref()
+    }
+}
@@ -0,0 +1,83 @@
+// The master key is stored once per key slot, each copy wrapped with a key derived from a
+// different secret, like the key slots of LUKS. A protector derives that key for one kind of
+// secret. Unlocking tries every slot of the protector's kind, so any of them opens the vault.
+use crate::encrypt::cryptography::CryptEngine;
+use crate::encrypt::key_file::KeyFile;
+use crate::error::PandaboxError;
+
+/// Slots protected by a master password, optionally together with a key file
+pub const PASSWORD_KIND: &str = "password";
+/// Slots protected by a recovery key
+pub const RECOVERY_KIND: &str = "recovery";
+/// Slots protected by a key file alone
+pub const KEY_FILE_KIND: &str = "key-file";
+
+/// A secret that wraps the master key in a key slot. Other unlock methods plug in by
+/// implementing it: a FIDO2 token would send the slot salt as its hmac-secret challenge and
+/// derive the engine from the answer.
+pub trait KeyProtector {
+    /// Stored with the slot, unlocking only tries the slots of the same kind
+    fn kind(&self) -> &'static str;
+
+    /// Whether slots made with this protector also need the key file, stored with the slot
+    fn uses_key_file(&self) -> bool {
+        false
+    }
+
+    /// Derive the engine wrapping the master key of a slot made with `salt`.
+    /// `with_key_file` is what the slot was made with.
+    fn engine(&self, salt: &[u8], with_key_file: bool) -> Result<CryptEngine, PandaboxError>;
+}
+
+/// The master password and, for slots made with one, the key file
+pub struct MasterPassword<'a> {
+    pub password: &'a str,
+    pub key_file: Option<&'a KeyFile>,
+}
+
+impl KeyProtector for MasterPassword<'_> {
+    fn kind(&self) -> &'static str {
+        PASSWORD_KIND
+    }
+
+    fn uses_key_file(&self) -> bool {
+        self.key_file.is_some()
+    }
+
+    fn engine(&self, salt: &[u8], with_key_file: bool) -> Result<CryptEngine, PandaboxError> {
+        // Slots without a key file ignore the one given
+        let key_file = match (with_key_file, self.key_file) {
+            (true, None) => return Err(PandaboxError::KeyFileRequired),
+            (true, key_file) => key_file,
+            (false, _) => None,
+        };
+        derive_engine(self.password, key_file, salt)
+    }
+}
+
+/// A recovery key in the canonical form of `recovery::normalize_recovery_key`
+pub struct RecoveryKey<'a>(pub &'a str);
+
+impl KeyProtector for RecoveryKey<'_> {
+    fn kind(&self) -> &'static str {
+        RECOVERY_KIND
+    }
+
+    fn engine(&self, salt: &[u8], _with_key_file: bool) -> Result<CryptEngine, PandaboxError> {
+        derive_engine(self.0, None, salt)
+    }
+}
+
+impl KeyProtector for KeyFile {
+    fn kind(&self) -> &'static str {
+        KEY_FILE_KIND
+    }
+
+    fn engine(&self, salt: &[u8], _with_key_file: bool) -> Result<CryptEngine, PandaboxError> {
+        derive_engine("", Some(self), salt)
+    }
+}
+
+fn derive_engine(secret: &str, key_file: Option<&KeyFile>, salt: &[u8]) -> Result<CryptEngine, PandaboxError> {
+    CryptEngine::new(secret, key_file, salt).map_err(|e| PandaboxError::Crypto(e.to_string()))
+}
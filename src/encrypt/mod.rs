@@ -1 +1,6 @@
 pub mod cryptography;
+pub mod key_file;
+pub mod key_slot;
+pub mod recovery;
+pub mod secure_memory;
+pub mod strength;
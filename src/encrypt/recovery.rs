@@ -0,0 +1,26 @@
+// Recovery keys are 12 words from the BIP39 English word list, 128 bits of randomness that are
+// easy to write down. The last word carries a checksum, so a mistyped key is caught before the
+// slow key derivation runs.
+use bip39::{Language, Mnemonic};
+use rand_core::{OsRng, RngCore};
+use zeroize::{Zeroize, Zeroizing};
+
+/// Words in a recovery key
+pub const RECOVERY_KEY_WORDS: usize = 12;
+
+/// A new random recovery key, words separated by single spaces
+pub fn generate_recovery_key() -> Zeroizing<String> {
+    let mut entropy = [0u8; RECOVERY_KEY_WORDS / 3 * 4];
+    OsRng.fill_bytes(&mut entropy);
+    let mnemonic = Mnemonic::from_entropy_in(Language::English, &entropy).expect("16 bytes is a valid entropy length");
+    entropy.zeroize();
+    Zeroizing::new(mnemonic.to_string())
+}
+
+/// The recovery key typed by the user in its canonical form: lowercase words separated by
+/// single spaces. `None` when the words do not form a recovery key.
+pub fn normalize_recovery_key(input: &str) -> Option<Zeroizing<String>> {
+    let lowered = Zeroizing::new(input.to_lowercase());
+    let mnemonic = Mnemonic::parse_in_normalized(Language::English, &lowered).ok()?;
+    (mnemonic.word_count() == RECOVERY_KEY_WORDS).then(|| Zeroizing::new(mnemonic.to_string()))
+}
@@ -0,0 +1,221 @@
+// Keys of an unlocked vault live in memory for as long as it stays open. With the
+// `secure-memory` feature on Linux they get pages of their own: locked so they are never
+// swapped out, left out of core dumps and fenced by inaccessible guard pages. The process also
+// refuses core dumps and ptrace while a vault is unlocked. Elsewhere they are ordinary heap
+// memory, wiped when dropped.
+use std::ops::Deref;
+use zeroize::Zeroize;
+
+/// A fixed-size secret such as a master key, wiped when dropped
+pub struct SecretBytes {
+    buffer: imp::Buffer,
+}
+
+impl SecretBytes {
+    pub fn new(bytes: &[u8]) -> Self {
+        SecretBytes { buffer: imp::Buffer::new(bytes) }
+    }
+
+    /// Move `bytes` into protected memory, wiping the vector
+    pub fn from_vec(mut bytes: Vec<u8>) -> Self {
+        let secret = SecretBytes::new(&bytes);
+        bytes.zeroize();
+        secret
+    }
+}
+
+impl Deref for SecretBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.buffer.as_slice()
+    }
+}
+
+impl Clone for SecretBytes {
+    fn clone(&self) -> Self {
+        SecretBytes::new(self)
+    }
+}
+
+/// Keeps core dumps off while it lives. Every unlocked session holds one, dumps are allowed
+/// again once the last of them is dropped.
+pub struct NoCoreDumps(());
+
+impl NoCoreDumps {
+    pub fn new() -> Self {
+        imp::hold_core_dumps();
+        NoCoreDumps(())
+    }
+}
+
+impl Default for NoCoreDumps {
+    fn default() -> Self {
+        NoCoreDumps::new()
+    }
+}
+
+impl Drop for NoCoreDumps {
+    fn drop(&mut self) {
+        imp::release_core_dumps();
+    }
+}
+
+#[cfg(all(feature = "secure-memory", target_os = "linux"))]
+mod imp {
+    use std::ptr;
+    use std::sync::Mutex;
+    use log::warn;
+    use zeroize::{Zeroize, Zeroizing};
+
+    // Sessions keeping core dumps off
+    static NO_CORE_DUMPS: Mutex<usize> = Mutex::new(0);
+
+    /// The secret sits at the end of its own locked pages, between two guard pages, so reading
+    /// or writing past it faults instead of reaching other data. When no pages can be mapped,
+    /// for instance under a tight address space limit, it is kept on the heap like elsewhere.
+    pub enum Buffer {
+        Mapped(Mapping),
+        Heap(Zeroizing<Vec<u8>>),
+    }
+
+    impl Buffer {
+        pub fn new(bytes: &[u8]) -> Self {
+            match Mapping::new(bytes) {
+                Ok(mapping) => Buffer::Mapped(mapping),
+                Err(e) => {
+                    warn!("Failed to map memory for a secret, keeping it on the heap: {}", e);
+                    Buffer::Heap(Zeroizing::new(bytes.to_vec()))
+                }
+            }
+        }
+
+        pub fn as_slice(&self) -> &[u8] {
+            match self {
+                Buffer::Mapped(mapping) => mapping.as_slice(),
+                Buffer::Heap(bytes) => bytes,
+            }
+        }
+    }
+
+    pub struct Mapping {
+        mapping: *mut libc::c_void,
+        mapping_len: usize,
+        data: *mut u8,
+        len: usize,
+    }
+
+    // The pages are only written when the mapping is made and dropped
+    unsafe impl Send for Mapping {}
+    unsafe impl Sync for Mapping {}
+
+    impl Mapping {
+        fn new(bytes: &[u8]) -> std::io::Result<Self> {
+            let page = page_size();
+            let data_len = bytes.len().div_ceil(page).max(1) * page;
+            let mapping_len = data_len + 2 * page;
+
+            // SAFETY: a fresh private anonymous mapping, the pointers stay inside it
+            unsafe {
+                let mapping = libc::mmap(
+                    ptr::null_mut(),
+                    mapping_len,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                    -1,
+                    0,
+                );
+                if mapping == libc::MAP_FAILED {
+                    return Err(std::io::Error::last_os_error());
+                }
+                let data_pages = mapping.cast::<u8>().add(page);
+                if libc::mprotect(mapping, page, libc::PROT_NONE) != 0
+                    || libc::mprotect(data_pages.add(data_len).cast(), page, libc::PROT_NONE) != 0
+                {
+                    warn!("Failed to set up guard pages: {}", std::io::Error::last_os_error());
+                }
+                // Limited by RLIMIT_MEMLOCK, the secret is still kept out of core dumps without it
+                if libc::mlock(data_pages.cast(), data_len) != 0 {
+                    warn!("Failed to lock a secret in memory: {}", std::io::Error::last_os_error());
+                }
+                if libc::madvise(data_pages.cast(), data_len, libc::MADV_DONTDUMP) != 0 {
+                    warn!("Failed to exclude a secret from core dumps: {}", std::io::Error::last_os_error());
+                }
+
+                let data = data_pages.add(data_len - bytes.len());
+                ptr::copy_nonoverlapping(bytes.as_ptr(), data, bytes.len());
+                Ok(Mapping { mapping, mapping_len, data, len: bytes.len() })
+            }
+        }
+
+        fn as_slice(&self) -> &[u8] {
+            // SAFETY: `data` points to `len` initialized bytes of the mapping
+            unsafe { std::slice::from_raw_parts(self.data, self.len) }
+        }
+    }
+
+    impl Drop for Mapping {
+        fn drop(&mut self) {
+            // SAFETY: the mapping is owned by this value and unmapped only here
+            unsafe {
+                std::slice::from_raw_parts_mut(self.data, self.len).zeroize();
+                let page = page_size();
+                libc::munlock(self.mapping.cast::<u8>().add(page).cast(), self.mapping_len - 2 * page);
+                libc::munmap(self.mapping, self.mapping_len);
+            }
+        }
+    }
+
+    fn page_size() -> usize {
+        // SAFETY: sysconf has no preconditions
+        match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+            size if size > 0 => size as usize,
+            _ => 4096,
+        }
+    }
+
+    pub fn hold_core_dumps() {
+        let mut holders = NO_CORE_DUMPS.lock().unwrap();
+        if *holders == 0 {
+            set_dumpable(false);
+        }
+        *holders += 1;
+    }
+
+    pub fn release_core_dumps() {
+        let mut holders = NO_CORE_DUMPS.lock().unwrap();
+        *holders -= 1;
+        if *holders == 0 {
+            set_dumpable(true);
+        }
+    }
+
+    // Not dumpable also means other processes of the user cannot ptrace or read our memory
+    fn set_dumpable(dumpable: bool) {
+        // SAFETY: PR_SET_DUMPABLE only takes an integer argument
+        if unsafe { libc::prctl(libc::PR_SET_DUMPABLE, dumpable as libc::c_ulong) } != 0 {
+            warn!("Failed to change whether the process is dumpable: {}", std::io::Error::last_os_error());
+        }
+    }
+}
+
+#[cfg(not(all(feature = "secure-memory", target_os = "linux")))]
+mod imp {
+    use zeroize::Zeroizing;
+
+    pub struct Buffer(Zeroizing<Vec<u8>>);
+
+    impl Buffer {
+        pub fn new(bytes: &[u8]) -> Self {
+            Buffer(Zeroizing::new(bytes.to_vec()))
+        }
+
+        pub fn as_slice(&self) -> &[u8] {
+            &self.0
+        }
+    }
+
+    pub fn hold_core_dumps() {}
+
+    pub fn release_core_dumps() {}
+}
@@ -0,0 +1,157 @@
+// Small entropy based password strength estimator. It is intentionally conservative:
+// repeated characters, keyboard/alphabet sequences and common passwords all lower the score.
+use std::fmt;
+
+const COMMON_PASSWORDS: &[&str] = &[
+    "password", "123456", "12345678", "qwerty", "abc123", "letmein", "welcome", "admin",
+    "iloveyou", "monkey", "dragon", "football", "baseball", "master", "sunshine", "princess",
+    "login", "passw0rd", "trustno1", "azerty",
+];
+
+const SEQUENCES: &[&str] = &[
+    "abcdefghijklmnopqrstuvwxyz",
+    "0123456789",
+    "qwertyuiop",
+    "asdfghjkl",
+    "zxcvbnm",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PasswordStrength {
+    VeryWeak,
+    Weak,
+    Fair,
+    Strong,
+    VeryStrong,
+}
+
+impl PasswordStrength {
+    pub fn label(&self) -> &'static str {
+        match self {
+            PasswordStrength::VeryWeak => "Very weak",
+            PasswordStrength::Weak => "Weak",
+            PasswordStrength::Fair => "Fair",
+            PasswordStrength::Strong => "Strong",
+            PasswordStrength::VeryStrong => "Very strong",
+        }
+    }
+
+    pub fn from_entropy(bits: f64) -> Self {
+        match bits {
+            b if b < 28.0 => PasswordStrength::VeryWeak,
+            b if b < 36.0 => PasswordStrength::Weak,
+            b if b < 60.0 => PasswordStrength::Fair,
+            b if b < 128.0 => PasswordStrength::Strong,
+            _ => PasswordStrength::VeryStrong,
+        }
+    }
+}
+
+/// Entropy a new master password needs unless the settings ask for another minimum
+pub const DEFAULT_MIN_MASTER_ENTROPY: u32 = 60;
+
+/// Why a new master password was refused
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MasterPasswordProblem {
+    /// The confirmation differs from the password
+    Mismatch,
+    /// The estimated entropy is below the required minimum
+    TooWeak { bits: f64, required: u32 },
+}
+
+impl fmt::Display for MasterPasswordProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MasterPasswordProblem::Mismatch => write!(f, "The passwords do not match"),
+            MasterPasswordProblem::TooWeak { bits, required } => write!(
+                f,
+                "{} ({:.0} bits), at least {} bits are required",
+                PasswordStrength::from_entropy(*bits).label(),
+                bits,
+                required
+            ),
+        }
+    }
+}
+
+/// Check a new master password and its confirmation, returning the estimated entropy
+pub fn check_master_password(password: &str, confirmation: &str, min_entropy: u32) -> Result<f64, MasterPasswordProblem> {
+    let bits = estimate_entropy(password);
+    if bits < f64::from(min_entropy) {
+        return Err(MasterPasswordProblem::TooWeak { bits, required: min_entropy });
+    }
+    if password != confirmation {
+        return Err(MasterPasswordProblem::Mismatch);
+    }
+    Ok(bits)
+}
+
+/// Estimate the entropy of a password in bits.
+pub fn estimate_entropy(password: &str) -> f64 {
+    if password.is_empty() {
+        return 0.0;
+    }
+
+    let lowered = password.to_lowercase();
+    if COMMON_PASSWORDS.iter().any(|common| lowered == *common) {
+        return 0.0;
+    }
+
+    // Size of the character pool the password draws from
+    let mut pool = 0u32;
+    if password.chars().any(|c| c.is_ascii_lowercase()) {
+        pool += 26;
+    }
+    if password.chars().any(|c| c.is_ascii_uppercase()) {
+        pool += 26;
+    }
+    if password.chars().any(|c| c.is_ascii_digit()) {
+        pool += 10;
+    }
+    if password.chars().any(|c| c.is_ascii_punctuation() || c == ' ') {
+        pool += 33;
+    }
+    if !password.is_ascii() {
+        pool += 100;
+    }
+
+    // Characters that repeat the previous one or continue a known sequence add little
+    let chars: Vec<char> = lowered.chars().collect();
+    let mut effective_length = 1.0;
+    for pair in chars.windows(2) {
+        if pair[0] == pair[1] || is_sequence(pair[0], pair[1]) {
+            effective_length += 0.25;
+        } else {
+            effective_length += 1.0;
+        }
+    }
+
+    let mut bits = effective_length * f64::from(pool).log2();
+
+    // A common password embedded in a longer one only counts for a handful of bits. One that
+    // is part of a longer common password found as well, like 123456 in 12345678, is already
+    // paid for.
+    let embedded: Vec<&str> = COMMON_PASSWORDS
+        .iter()
+        .copied()
+        .filter(|common| lowered.contains(common))
+        .collect();
+    for common in &embedded {
+        if !embedded.iter().any(|other| other.len() > common.len() && other.contains(common)) {
+            bits -= (common.len() as f64 - 1.0) * f64::from(pool).log2() * 0.75;
+        }
+    }
+
+    bits.max(0.0)
+}
+
+fn is_sequence(a: char, b: char) -> bool {
+    SEQUENCES.iter().any(|sequence| {
+        sequence
+            .find(a)
+            .is_some_and(|index| sequence[index + a.len_utf8()..].starts_with(b))
+            || sequence
+                .find(b)
+                .is_some_and(|index| sequence[index + b.len_utf8()..].starts_with(a))
+    })
+}
@@ -0,0 +1,140 @@
+// Errors raised while opening and unlocking the vault, shown to the user instead of panicking.
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::time::Duration;
+use diesel::result::{ConnectionError, Error as DieselError};
+
+#[derive(Debug)]
+pub enum PandaboxError {
+    /// Creating the application directory or reading a file failed
+    Io(io::Error),
+    /// A query against the vault failed
+    Database(DieselError),
+    /// The vault database could not be opened
+    Connection(ConnectionError),
+    /// The vault schema could not be brought up to date
+    Migration(Box<dyn Error + Send + Sync>),
+    /// Deriving a key, encrypting or decrypting failed
+    Crypto(String),
+    /// The master password does not decrypt the master key. `remaining_attempts` counts the
+    /// failures left before the vault is wiped, `None` when it never is.
+    WrongPassword { remaining_attempts: Option<u32> },
+    /// The vault was created with a key file and none was given
+    KeyFileRequired,
+    /// The recovery key does not decrypt the master key, counted like a wrong password
+    WrongRecoveryKey { remaining_attempts: Option<u32> },
+    /// The vault was created without a recovery key
+    NoRecoveryKey,
+    /// The vault has no key slot of the given kind to unlock it with
+    NoKeySlot(&'static str),
+    /// Adding or removing a key slot was refused, e.g. for a name already taken
+    KeySlotRefused(String),
+    /// Too many unlocks failed, the next one is allowed after the given time
+    UnlockDelayed(Duration),
+    /// Too many unlocks failed and the vault wiped itself
+    VaultWiped,
+    /// The vault opened but its content is not what Pandabox wrote
+    CorruptVault(String),
+    /// Entries kept changing while every one of them was being re-encrypted
+    VaultChanged,
+}
+
+impl PandaboxError {
+    /// Short heading for the error page
+    pub fn summary(&self) -> &'static str {
+        match self {
+            PandaboxError::Io(_) => "Pandabox could not access its files",
+            PandaboxError::Database(_) => "The vault database returned an error",
+            PandaboxError::Connection(_) => "The vault database could not be opened",
+            PandaboxError::Migration(_) => "The vault could not be upgraded",
+            PandaboxError::Crypto(_) => "Encryption failed",
+            PandaboxError::WrongPassword { .. } => "Wrong master password",
+            PandaboxError::KeyFileRequired => "Key file required",
+            PandaboxError::WrongRecoveryKey { .. } => "Wrong recovery key",
+            PandaboxError::NoRecoveryKey => "No recovery key",
+            PandaboxError::NoKeySlot(_) => "No matching key slot",
+            PandaboxError::KeySlotRefused(_) => "Key slot not changed",
+            PandaboxError::UnlockDelayed(_) => "Too many failed attempts",
+            PandaboxError::VaultWiped => "The vault was wiped",
+            PandaboxError::CorruptVault(_) => "The vault is damaged",
+            PandaboxError::VaultChanged => "The vault changed meanwhile",
+        }
+    }
+}
+
+impl fmt::Display for PandaboxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PandaboxError::Io(e) => write!(f, "I/O error: {}", e),
+            PandaboxError::Database(e) => write!(f, "Database error: {}", e),
+            PandaboxError::Connection(e) => write!(f, "Could not open the database: {}", e),
+            PandaboxError::Migration(e) => write!(f, "Migration failed: {}", e),
+            PandaboxError::Crypto(e) => write!(f, "Cryptographic error: {}", e),
+            PandaboxError::WrongPassword { remaining_attempts: None } => write!(f, "The master password is incorrect"),
+            PandaboxError::WrongPassword { remaining_attempts: Some(1) } => {
+                write!(f, "The master password is incorrect, the vault is wiped after one more failed attempt")
+            }
+            PandaboxError::WrongPassword { remaining_attempts: Some(remaining) } => {
+                write!(f, "The master password is incorrect, {} attempts left before the vault is wiped", remaining)
+            }
+            PandaboxError::KeyFileRequired => write!(f, "This vault is unlocked with the master password and its key file"),
+            PandaboxError::WrongRecoveryKey { remaining_attempts: Some(1) } => {
+                write!(f, "The recovery key is incorrect, the vault is wiped after one more failed attempt")
+            }
+            PandaboxError::WrongRecoveryKey { remaining_attempts: Some(remaining) } => {
+                write!(f, "The recovery key is incorrect, {} attempts left before the vault is wiped", remaining)
+            }
+            PandaboxError::WrongRecoveryKey { remaining_attempts: None } => write!(f, "The recovery key is incorrect"),
+            PandaboxError::NoRecoveryKey => write!(f, "This vault was created without a recovery key"),
+            PandaboxError::NoKeySlot(kind) => write!(f, "This vault has no {} key slot", kind),
+            PandaboxError::KeySlotRefused(e) => write!(f, "Key slot not changed: {}", e),
+            PandaboxError::UnlockDelayed(delay) => {
+                write!(f, "Too many failed attempts, try again in {} seconds", delay.as_secs_f64().ceil() as u64)
+            }
+            PandaboxError::VaultWiped => write!(f, "Too many failed attempts, every entry of the vault was deleted"),
+            PandaboxError::CorruptVault(e) => write!(f, "Corrupt vault: {}", e),
+            PandaboxError::VaultChanged => write!(f, "Entries kept changing while they were re-encrypted, nothing was changed"),
+        }
+    }
+}
+
+impl Error for PandaboxError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PandaboxError::Io(e) => Some(e),
+            PandaboxError::Database(e) => Some(e),
+            PandaboxError::Connection(e) => Some(e),
+            PandaboxError::Migration(e) => Some(e.as_ref()),
+            PandaboxError::Crypto(_)
+            | PandaboxError::WrongPassword { .. }
+            | PandaboxError::KeyFileRequired
+            | PandaboxError::WrongRecoveryKey { .. }
+            | PandaboxError::NoRecoveryKey
+            | PandaboxError::NoKeySlot(_)
+            | PandaboxError::KeySlotRefused(_)
+            | PandaboxError::UnlockDelayed(_)
+            | PandaboxError::VaultWiped
+            | PandaboxError::CorruptVault(_)
+            | PandaboxError::VaultChanged => None,
+        }
+    }
+}
+
+impl From<io::Error> for PandaboxError {
+    fn from(e: io::Error) -> Self {
+        PandaboxError::Io(e)
+    }
+}
+
+impl From<DieselError> for PandaboxError {
+    fn from(e: DieselError) -> Self {
+        PandaboxError::Database(e)
+    }
+}
+
+impl From<ConnectionError> for PandaboxError {
+    fn from(e: ConnectionError) -> Self {
+        PandaboxError::Connection(e)
+    }
+}
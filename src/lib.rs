@@ -0,0 +1,33 @@
+//! The Pandabox vault as a library: opening and unlocking vault files, reading and writing
+//! records and exporting them, plus the local services the desktop app runs on top of it.
+//!
+//! ```no_run
+//! use pandabox::{DatabaseManager, Session};
+//! use std::sync::Arc;
+//!
+//! # fn main() -> Result<(), pandabox::PandaboxError> {
+//! let manager = Arc::new(DatabaseManager::open("vault.db")?);
+//! let session = Session::unlock(manager, "master password", None)?;
+//! for record in session.get_all_records()? {
+//!     println!("{}", record.service);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+pub mod database;
+pub mod encrypt;
+pub mod error;
+pub mod paths;
+pub mod session;
+pub mod settings;
+pub mod ssh_agent;
+pub mod bridge;
+#[cfg(unix)]
+pub mod approval;
+#[cfg(target_os = "linux")]
+pub mod secret_service;
+
+pub use database::manager::DatabaseManager;
+pub use database::models::{Record, RecordKind};
+pub use error::PandaboxError;
+pub use session::session::{Entry, Session, SessionState};
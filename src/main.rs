@@ -1,187 +1,269 @@
 // Prevent console window in addition to Slint window in Windows release builds when, e.g., starting the app via file manager. Ignored on other platforms.
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use crate::encrypt::cryptography::CryptEngine;
+use pandabox::encrypt::cryptography::CryptEngine;
+use pandabox::encrypt::key_file::KeyFile;
+use pandabox::encrypt::key_slot::{KEY_FILE_KIND, PASSWORD_KIND, RECOVERY_KIND};
+use pandabox::encrypt::recovery::{normalize_recovery_key, RECOVERY_KEY_WORDS};
+use pandabox::encrypt::strength::{check_master_password, estimate_entropy, PasswordStrength};
 use slint::{Model, ModelRc, SharedString, StandardListViewItem, VecModel, Weak, Timer, TimerMode};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
+use std::path::PathBuf;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
-use zeroize::Zeroize;
-use crate::database::manager::DatabaseManager;
-use crate::session::session::Session;
+use std::time::Duration;
+use pandabox::database::manager::DatabaseManager;
+use pandabox::error::PandaboxError;
+use pandabox::paths;
+use pandabox::settings::{AppSettings, Theme, VaultSettings};
+use pandabox::database::models::{KeySlotInfo, Record, RecordKind, PASSWORD_SLOT, RECOVERY_SLOT};
+use pandabox::session::session::{unix_now, Entry, Session, SessionState};
+use zeroize::Zeroizing;
+use pandabox::session::health::HealthIssue;
+use pandabox::session::url_match::split_urls;
+use pandabox::session::export;
 use arboard::Clipboard;
 use std::fs::File;
 use std::io::BufReader;
-use csv::{Writer, Reader};
-use serde::{Serialize, Deserialize};
 use rfd::FileDialog;
-use log::{error, warn, info};
-
-mod database;
-mod encrypt;
-mod session;
+use log::{debug, error, warn, info};
+#[cfg(unix)]
+use pandabox::approval::{ApprovalQueue, ApprovalRequest};
+#[cfg(unix)]
+use pandabox::bridge::server::BridgeServer;
+#[cfg(unix)]
+use pandabox::ssh_agent::server::AgentServer;
+#[cfg(target_os = "linux")]
+use pandabox::secret_service::{SecretService, VaultEvent};
 
 slint::include_modules!();
 
 const APP_NAME: &str = "Pandabox";
+// How long the Undo button stays after deleting an entry
+const UNDO_TOAST_DURATION: Duration = Duration::from_secs(8);
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+// Shown in the first column of the rows ticked for a bulk action
+const SELECTED_MARK: &str = "✓";
 
-#[derive(Debug, Serialize, Deserialize)]
-struct CsvRecord {
-    service: String,
-    email: String,
-    username: String,
-    password: String,
-    notes: String,
-}
+/// Set by the busy overlay's Cancel button, checked by the running worker thread
+type CancelFlag = Arc<AtomicBool>;
 
-// Assume you have a function to write to your database
 fn on_authenticate(
     data: SharedString,
-    manager: Rc<DatabaseManager>
-) -> (bool, Option<Session>) {
-    match manager.get_master_record() {
-        Ok(mut master_record) => {
-            let engine = CryptEngine::new(data.as_str(), &master_record.salt).unwrap();
-            let mut status = false;
-            let mut session = None;
-            match engine.decrypt_master_key(&master_record.nonce, &master_record.encrypted_master_key) {
-                Ok(decrypted_key) => {
-                    master_record.encrypted_master_key.zeroize();
-                    master_record.nonce.zeroize();
-                    master_record.salt.zeroize();
-                    session = Some(Session::new(decrypted_key, engine.clone(), manager.clone()));
-                    status = true;
+    key_file_path: SharedString,
+    manager: Arc<DatabaseManager>
+) -> Result<Session, PandaboxError> {
+    let key_file = read_key_file(&key_file_path)?;
+    Session::unlock(manager, data.as_str(), key_file.as_ref())
+}
+
+/// Shake the password field and outline it in red after a wrong master password
+fn shake_password_field(ui_weak: &Weak<EntryWindow>) {
+    // Trigger shake animation
+    if let Some(ui) = ui_weak.upgrade() {
+        ui.set_auth_error(true);
+        
+        // Trigger shake sequence
+        let ui_weak_shake = ui.as_weak();
+        std::thread::spawn(move || {
+            for i in 1..=6 {
+                std::thread::sleep(std::time::Duration::from_millis(80));
+                let ui_weak_clone = ui_weak_shake.clone();
+                slint::invoke_from_event_loop(move || {
+                    if let Some(ui) = ui_weak_clone.upgrade() {
+                        ui.set_shake_trigger(i);
+                    }
+                }).ok();
+            }
+            // Reset shake
+            std::thread::sleep(std::time::Duration::from_millis(80));
+            let ui_weak_clone = ui_weak_shake.clone();
+            slint::invoke_from_event_loop(move || {
+                if let Some(ui) = ui_weak_clone.upgrade() {
+                    ui.set_shake_trigger(0);
                 }
-                Err(_) => {
-                    error!("Failed to decrypt master key");
+            }).ok();
+            
+            // Clear error after 2 seconds
+            std::thread::sleep(std::time::Duration::from_secs(2));
+            let ui_weak_clone = ui_weak_shake.clone();
+            slint::invoke_from_event_loop(move || {
+                if let Some(ui) = ui_weak_clone.upgrade() {
+                    ui.set_auth_error(false);
                 }
-            };
-            (status, session)
-        }
-        Err(e) => {
-            error!("Failed to get master record: {}", e);
-            (false, None)
-        }
+            }).ok();
+        });
     }
 }
 
-fn make_db_callback<F, T>(
-    manager: Rc<DatabaseManager>,
+fn make_db_callback<F, T, R>(
+    manager: Arc<DatabaseManager>,
     ui_weak: Weak<EntryWindow>,
     fn_to_call: F,
-    success_page: Page,
+    on_success: fn(&EntryWindow, R),
 ) -> impl Fn(T) + 'static
 where
-    F: Fn(T, Rc<DatabaseManager>) -> bool + Send + Sync + 'static,
-    T: Clone + 'static,
+    F: Fn(T, Arc<DatabaseManager>) -> Result<R, PandaboxError> + Send + Sync + 'static,
+    T: Send + 'static,
+    R: Send + 'static,
 {
     let manager = manager.clone(); // captured by the closure
     let ui_weak = ui_weak.clone();
 
+    let fn_to_call = Arc::new(fn_to_call);
+
     move |input: T| {
-        // Call the supplied function
-        if !fn_to_call(input.clone(), manager.clone()) {
-            return;
-        }
+        let manager = manager.clone();
+        let ui_weak = ui_weak.clone();
+        let fn_to_call = Arc::clone(&fn_to_call);
+        let return_page = ui_weak.upgrade().map(|ui| ui.get_current_page());
+        show_busy_uncancellable(&ui_weak, "Please wait");
 
-        // Upgrade the weak UI pointer and change page
-        if let Some(ui) = ui_weak.upgrade() {
-            ui.set_current_page(success_page);
-        }
+        // Key derivation runs on a worker so the window keeps repainting
+        std::thread::spawn(move || {
+            let result = fn_to_call(input, manager);
+
+            slint::invoke_from_event_loop(move || {
+                hide_busy(&ui_weak);
+                // Call the supplied function
+                let value = match result {
+                    Ok(value) => value,
+                    Err(e) => {
+                        error!("{}", e);
+                        show_error_page(&ui_weak, &e, return_page);
+                        return;
+                    }
+                };
+
+                // Upgrade the weak UI pointer and show the result
+                if let Some(ui) = ui_weak.upgrade() {
+                    on_success(&ui, value);
+                }
+            }).ok();
+        });
     }
 }
 
 
-fn create_db(manager: Rc<DatabaseManager>, data: SharedString) -> bool {
-    // Before creating the database perhaps we should create the salt, nonce and encyrption key
-    let mut salt = CryptEngine::generate_salt();
-    let engine = CryptEngine::new(data.as_str(), &salt).unwrap();
-    let mut master_key = CryptEngine::generate_master_key();
-    let (mut nonce, mut ciphertext) = engine.encrypt_master_key(master_key.as_ref()).unwrap();
+/// The key file picked in the UI, `None` when the path is empty
+fn read_key_file(path: &str) -> Result<Option<KeyFile>, PandaboxError> {
+    if path.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(KeyFile::read(path)?))
+}
 
-    match manager.create_master_record(salt.as_ref(), ciphertext.as_ref(), nonce.as_ref()) {
-        Ok(_) => {
-            // Securely wipe sensitive data from memory now that it's committed to database
-            salt.zeroize();
-            master_key.zeroize();
-            nonce.zeroize();
-            ciphertext.zeroize();
-            true
-        }
-        Err(e) => {
-            error!("Failed to create Master Record: {}", e);
-            // Securely wipe sensitive data from memory
-            salt.zeroize();
-            master_key.zeroize();
-            false
-        }
+/// Create the vault and, when asked for, its recovery key
+fn create_db(manager: Arc<DatabaseManager>, data: SharedString, with_recovery_key: bool, key_file_path: SharedString) -> Result<Option<Zeroizing<String>>, PandaboxError> {
+    let key_file = read_key_file(&key_file_path)?;
+    // The new vault still has to be unlocked on the authentication page
+    let session = Session::create(manager, data.as_str(), key_file.as_ref())?;
+    if with_recovery_key {
+        return session.create_recovery_key().map(Some);
     }
+    Ok(None)
 }
 
 fn handle_save_service(
     session: &Session,
     form_mode: SharedString,
     current_index: i32,
-    record_id_str: SharedString,
-    service: SharedString,
-    email: SharedString,
-    username: SharedString,
-    password: SharedString,
-    notes: SharedString,
+    data: ServiceData,
     ui_weak: slint::Weak<EntryWindow>
 ) {
     let index = current_index as usize;
     
-    // Basic validation for required fields (except password in edit mode)
-    if service.is_empty() || email.is_empty() || username.is_empty() {
+    let kind = RecordKind::parse(&data.kind);
+
+    // Basic validation for required fields (except password in edit mode),
+    // SSH keys only need a name
+    if data.service.is_empty() || (kind == RecordKind::Login && (data.email.is_empty() || data.username.is_empty())) {
+        return;
+    }
+
+    if kind == RecordKind::SshKey
+        && !data.password.is_empty()
+        && let Err(e) = pandabox::ssh_agent::keys::parse_private_key(&data.password)
+    {
+        warn!("Rejected SSH key for {}: {}", data.service, e);
+        show_error_toast(&ui_weak, &format!("Invalid SSH private key: {}", e));
         return;
     }
 
     if let Some(ui) = ui_weak.upgrade() {
         if form_mode.as_str() == "Add" {
             // For Add mode, password is required
-            if password.is_empty() {
+            if data.password.is_empty() {
                 return;
             }
-            insert_entry(session, &service, &email, username, password, notes, ui);
+            insert_entry(session, &data, ui);
         } else {
             // For Edit mode, password can be empty (keep existing password)
-            let record_id = record_id_str.as_str().parse::<i32>().unwrap_or(0);
-            update_entry(session, index, record_id, &service, &email, username, password, notes, ui);
+            let record_id = data.id.as_str().parse::<i32>().unwrap_or(0);
+            update_entry(session, index, record_id, &data, ui);
         }
     }
 }
 
-fn update_entry(session: &Session, index: usize, record_id: i32, service: &SharedString, email: &SharedString, username: SharedString, password: SharedString, notes: SharedString, ui: EntryWindow) {
+/// The form contents as the entry the session stores
+fn entry_from_service_data(data: &ServiceData) -> Entry {
+    Entry {
+        service: data.service.to_string(),
+        email: data.email.to_string(),
+        username: data.username.to_string(),
+        password: Zeroizing::new(data.password.to_string()),
+        notes: data.notes.to_string(),
+        two_factor: data.two_factor,
+        kind: RecordKind::parse(&data.kind),
+        confirm_use: data.confirm_use,
+    }
+}
+
+fn update_entry(session: &Session, index: usize, record_id: i32, data: &ServiceData, ui: EntryWindow) {
     let table_model_handle = ui.global::<AppData>().get_table_rows();
+    // The tick, folder and tags are not edited in the form
+    let existing_row = table_model_handle.row_data(index);
+    let existing_cell = |column: usize| existing_row.as_ref().and_then(|row| row.row_data(column)).unwrap_or_default();
     let row_data: ModelRc<StandardListViewItem> = ModelRc::new(VecModel::from(vec![
+        existing_cell(0),
         StandardListViewItem::from(record_id.to_string().as_str()),
-        StandardListViewItem::from(service.as_str()),
-        StandardListViewItem::from(email.as_str()),
-        StandardListViewItem::from(username.as_str()),
+        StandardListViewItem::from(data.service.as_str()),
+        StandardListViewItem::from(data.email.as_str()),
+        StandardListViewItem::from(data.username.as_str()),
         StandardListViewItem::from("••••••••"), // Hide password in display
-        StandardListViewItem::from(notes.as_str()),
+        StandardListViewItem::from(data.notes.as_str()),
+        StandardListViewItem::from(two_factor_label(data.two_factor)),
+        StandardListViewItem::from(urls_label(&split_urls(&data.urls)).as_str()),
+        StandardListViewItem::from(kind_label(RecordKind::parse(&data.kind), data.confirm_use)),
+        existing_cell(10),
+        existing_cell(11),
     ]));
 
+    let mut entry = entry_from_service_data(data);
+
     // If password is empty, retrieve the existing password from the database
-    let password_to_use = if password.is_empty() {
+    if data.password.is_empty() {
         match session.get_decrypted_password(record_id) {
-            Ok(existing_password) => SharedString::from(existing_password.as_str()),
+            Ok(existing_password) => entry.password = Zeroizing::new(existing_password),
             Err(e) => {
                 error!("Failed to retrieve existing password: {}", e);
                 return;
             }
         }
-    } else {
-        password
-    };
+    }
 
-    if session.update_entry(record_id, service, email, &username, &password_to_use, &notes) {
+    if session.update_entry(record_id, &entry) {
+        if let Err(e) = session.set_record_urls(record_id, &split_urls(&data.urls)) {
+            error!("Failed to save websites: {}", e);
+        }
         table_model_handle.set_row_data(index, row_data);
     }
 }
 
-fn insert_entry(session: &Session, service: &SharedString, email: &SharedString, username: SharedString, password: SharedString, notes: SharedString, ui: EntryWindow) {
+fn insert_entry(session: &Session, data: &ServiceData, ui: EntryWindow) {
     // Get current timestamp
     let table_model_handle = ui.global::<AppData>().get_table_rows();
     // This is the key: We "downcast" the generic model handle to the specific
@@ -190,19 +272,16 @@ fn insert_entry(session: &Session, service: &SharedString, email: &SharedString,
     if let Some(vec_model) = table_model_handle.as_any().downcast_ref::<VecModel<ModelRc<StandardListViewItem>>>() {
 
         // Insert the entry and get the created record with its ID
-        match session.insert_entry(service, email, &username, &password, &notes) {
+        match session.insert_entry(&entry_from_service_data(data)) {
             Ok(record) => {
+                let urls = split_urls(&data.urls);
+                if let Err(e) = session.set_record_urls(record.id, &urls) {
+                    error!("Failed to save websites: {}", e);
+                }
+
                 // Create a new row with the actual ID from the database
-                let row_data: Vec<slint::StandardListViewItem> = vec![
-                    StandardListViewItem::from(record.id.to_string().as_str()),
-                    StandardListViewItem::from(record.service.as_str()),
-                    StandardListViewItem::from(record.email.as_str()),
-                    StandardListViewItem::from(record.username.as_str()),
-                    StandardListViewItem::from("••••••••"), // Hide password in display
-                    StandardListViewItem::from(record.notes.as_str()),
-                ];
-
-                let new_row = ModelRc::new(VecModel::from(row_data));
+                let urls: Vec<String> = urls.iter().map(|url| url.to_string()).collect();
+                let new_row = ModelRc::new(VecModel::from(record_row(&record, &urls, &[], false)));
                 vec_model.push(new_row);
             }
             Err(e) => {
@@ -222,6 +301,7 @@ fn delete_entry(index: SharedString, session: &Session, ui_weak: Weak<EntryWindo
             Ok(index_to_remove) => {
                 if session.delete_entry(index_to_remove) {
                     refresh_table_data(&ui_weak, session);
+                    show_undo_toast(&ui_weak, "Entry moved to the trash", &[index_to_remove]);
                 } else {
                     error!("Failed to delete entry from database");
                 }
@@ -233,32 +313,89 @@ fn delete_entry(index: SharedString, session: &Session, ui_weak: Weak<EntryWindo
     }
 }
 
-fn create_db_submitted(input: SharedString, database_manager: Rc<DatabaseManager>) -> bool {
-    create_db(database_manager, input)
+fn create_db_submitted((input, with_recovery_key, key_file_path): (SharedString, bool, SharedString), database_manager: Arc<DatabaseManager>) -> Result<Option<Zeroizing<String>>, PandaboxError> {
+    create_db(database_manager, input, with_recovery_key, key_file_path)
+}
+
+fn authenticate_submitted((input, key_file_path): (SharedString, SharedString), database_manager: Arc<DatabaseManager>) -> Result<Session, PandaboxError> {
+    on_authenticate(input, key_file_path, database_manager)
 }
 
-fn authenticate_submitted(input: SharedString, database_manager: Rc<DatabaseManager>) -> (bool, Option<Session>) {
-    on_authenticate(input, database_manager)
+/// Let the user pick the key file of the vault, an empty string when cancelled
+fn choose_key_file() -> SharedString {
+    FileDialog::new()
+        .set_title("Choose the key file")
+        .pick_file()
+        .map(|path| SharedString::from(path.to_string_lossy().as_ref()))
+        .unwrap_or_default()
 }
 
-fn init_manager() -> (bool, Option<Rc<DatabaseManager>>) {
-    // The .env file is loaded by the DatabaseManager constructor, so we just need to create it.
-    let manager = Rc::new(DatabaseManager::new());
+/// Write a new random key file where the user asks for it, an empty string when cancelled or
+/// when the file could not be written
+fn generate_key_file() -> SharedString {
+    let Some(path) = FileDialog::new()
+        .set_title("Save the new key file")
+        .set_file_name("pandabox.key")
+        .save_file()
+    else {
+        return SharedString::new();
+    };
 
-    // Check if the master table has any records.
-    // This tells us if the database has been initialized.
-    match manager.check_master_table_exists() {
-        Ok(exists) => (exists, Some(manager)),
+    match KeyFile::create(&path) {
+        Ok(_) => {
+            info!("Generated the key file {}", path.display());
+            SharedString::from(path.to_string_lossy().as_ref())
+        }
         Err(e) => {
-            error!("Failed to check master table: {}", e);
-            // This might happen if the database file is corrupt or migrations haven't run.
-            // For now, we'll treat it as if the DB doesn't exist.
-            (false, Some(manager))
+            error!("Failed to generate the key file {}: {}", path.display(), e);
+            SharedString::new()
+        }
+    }
+}
+
+/// The vault file opened by this run
+fn vault_path() -> PathBuf {
+    paths::vault_override(vault_argument()).unwrap_or_else(paths::default_vault_path)
+}
+
+fn init_manager() -> Result<(bool, Arc<DatabaseManager>), PandaboxError> {
+    // A vault picked by the user is opened as is, otherwise the one in the data directory
+    let manager = match paths::vault_override(vault_argument()) {
+        Some(path) => {
+            info!("Opening vault {}", path.display());
+            DatabaseManager::open(path)?
+        }
+        None => DatabaseManager::new()?,
+    };
+    let manager = Arc::new(manager);
+
+    // Check if the master table has any records.
+    // This tells us if the database has been initialized. Failing here means the file is not
+    // a usable vault, creating a new master record on top of it would hide the problem.
+    let exists = manager.check_master_table_exists()?;
+    Ok((exists, manager))
+}
+
+/// The path given with `--vault <path>` or `--vault=<path>`
+fn vault_argument() -> Option<PathBuf> {
+    let mut args = std::env::args_os().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--vault" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.to_str().and_then(|arg| arg.strip_prefix("--vault=")) {
+            return Some(PathBuf::from(path));
         }
     }
+    None
 }
 
-fn export_csv_handler(session: &Session, ui_weak: Weak<EntryWindow>) {
+/// Export the whole vault, or only the records in `selection`
+fn export_csv_handler(session_state: &SessionState, ui_weak: Weak<EntryWindow>, cancel: CancelFlag, selection: Option<Vec<i32>>) {
+    let Some(session) = session_state.lock().unwrap().clone() else {
+        return;
+    };
+
     // Open file dialog to select save location
     let file_path = FileDialog::new()
         .set_file_name("pandabox_export.csv")
@@ -266,182 +403,400 @@ fn export_csv_handler(session: &Session, ui_weak: Weak<EntryWindow>) {
         .add_filter("All Files", &["*"])
         .save_file();
     
-    if let Some(path) = file_path {
-        // Get all records
-        match session.get_all_records() {
-            Ok(records) => {
-            match File::create(&path) {
-                Ok(file) => {
-                    let mut wtr = Writer::from_writer(file);
-                    
-                    // Write each record with decrypted password
-                    for record in records {
-                        match session.decrypt_password(&record.password) {
-                            Ok(decrypted_password) => {
-                                let csv_record = CsvRecord {
-                                    service: record.service,
-                                    email: record.email,
-                                    username: record.username,
-                                    password: decrypted_password,
-                                    notes: record.notes,
-                                };
-                                
-                                if let Err(e) = wtr.serialize(csv_record) {
-                                    error!("Failed to write CSV record: {}", e);
-                                }
-                            }
-                            Err(e) => {
-                                error!("Failed to decrypt password for {}: {}", record.service, e);
-                            }
-                        }
-                    }
-                    
-                    wtr.flush().ok();
-                    
-                    // Show success toast
-                    if let Some(ui) = ui_weak.upgrade() {
-                        let msg = format!("Exported to {}", path.file_name().unwrap_or_default().to_string_lossy());
-                        ui.global::<AppData>().set_toast_message(SharedString::from(msg));
-                        ui.global::<AppData>().set_show_toast(true);
-                        
-                        let ui_weak_timer = ui.as_weak();
-                        let timer = Timer::default();
-                        timer.start(TimerMode::SingleShot, std::time::Duration::from_secs(3), move || {
-                            if let Some(ui) = ui_weak_timer.upgrade() {
-                                ui.global::<AppData>().set_show_toast(false);
-                            }
-                        });
-                        std::mem::forget(timer);
-                    }
+    let Some(path) = file_path else {
+        return;
+    };
+
+    show_busy(&ui_weak, "Exporting records", &cancel);
+
+    // Decrypting the whole vault takes a while, keep the window responsive meanwhile
+    std::thread::spawn(move || {
+        let result = export_records(&session, selection.as_deref(), &path, &ui_weak, &cancel);
+
+        slint::invoke_from_event_loop(move || {
+            hide_busy(&ui_weak);
+            match result {
+                Ok(true) => {
+                    show_info_toast(&ui_weak, &format!("Exported to {}", path.file_name().unwrap_or_default().to_string_lossy()));
                 }
+                Ok(false) => show_info_toast(&ui_weak, "Export cancelled"),
                 Err(e) => {
-                    error!("Failed to create CSV file: {}", e);
+                    error!("Failed to export records: {}", e);
+                    show_error_toast(&ui_weak, &format!("Export failed, nothing was written: {}", e));
                 }
             }
+        }).ok();
+    });
+}
+
+/// Decrypt every record, or those in `selection`, and write them to `path`. The file is only
+/// created once everything is decrypted, returns `false` when cancelled before that.
+fn export_records(session: &Session, selection: Option<&[i32]>, path: &std::path::Path, ui_weak: &Weak<EntryWindow>, cancel: &AtomicBool) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let mut reported = 0;
+    let progress = |done, total| {
+        report_progress(ui_weak, "Exporting", &mut reported, done, total);
+        !cancel.load(Ordering::Relaxed)
+    };
+    let csv_records = match selection {
+        Some(record_ids) => export::decrypt_selected_records(session, record_ids, progress)?,
+        None => export::decrypt_records(session, progress)?,
+    };
+
+    match csv_records {
+        Some(csv_records) => {
+            export::write_csv(File::create(path)?, csv_records)?;
+            Ok(true)
         }
-        Err(e) => {
-            error!("Failed to get records for export: {}", e);
-        }
-    }
+        None => Ok(false),
     }
 }
 
-fn import_csv_handler(session: &Session, ui_weak: Weak<EntryWindow>) {
+fn import_csv_handler(session_state: &SessionState, ui_weak: Weak<EntryWindow>, cancel: CancelFlag) {
+    let Some(session) = session_state.lock().unwrap().clone() else {
+        return;
+    };
+
     // Open file dialog to select CSV file
     let file_path = FileDialog::new()
         .add_filter("CSV Files", &["csv"])
         .add_filter("All Files", &["*"])
         .pick_file();
     
-    if let Some(path) = file_path {
-    match File::open(&path) {
-        Ok(file) => {
-            let mut rdr = Reader::from_reader(BufReader::new(file));
-            let mut count = 0;
-            
-            for result in rdr.deserialize() {
-                match result {
-                    Ok(record) => {
-                        let csv_record: CsvRecord = record;
-                        match session.insert_entry(
-                            &SharedString::from(csv_record.service),
-                            &SharedString::from(csv_record.email),
-                            &SharedString::from(csv_record.username),
-                            &SharedString::from(csv_record.password),
-                            &SharedString::from(csv_record.notes),
-                        ) {
-                            Ok(_) => count += 1,
-                            Err(e) => error!("Failed to import record: {}", e),
-                        }
+    let Some(path) = file_path else {
+        return;
+    };
+
+    show_busy(&ui_weak, "Importing records", &cancel);
+
+    // Encrypting and writing a large file takes a while, keep the window responsive meanwhile
+    std::thread::spawn(move || {
+        // Every row has to parse before anything is written
+        let result = File::open(&path).map_err(Into::into).and_then(|file| export::read_csv(BufReader::new(file))).and_then(|entries| {
+            let mut reported = 0;
+            Ok(session.import_entries(&entries, |done, total| {
+                report_progress(&ui_weak, "Importing", &mut reported, done, total);
+                !cancel.load(Ordering::Relaxed)
+            })?)
+        });
+
+        slint::invoke_from_event_loop(move || {
+            hide_busy(&ui_weak);
+            match result {
+                Ok(count) => {
+                    info!("Imported {} records", count);
+                    refresh_table_data(&ui_weak, &session);
+                    show_info_toast(&ui_weak, &format!("Imported {} records", count));
+                }
+                Err(e) if matches!(e.downcast_ref::<diesel::result::Error>(), Some(diesel::result::Error::RollbackTransaction)) => {
+                    info!("Import cancelled");
+                    show_info_toast(&ui_weak, "Import cancelled, nothing was imported");
+                }
+                Err(e) => {
+                    error!("Import rolled back: {}", e);
+                    show_error_toast(&ui_weak, &format!("Import failed, nothing was imported: {}", e));
+                }
+            }
+        }).ok();
+    });
+}
+
+/// Cover the window while a worker thread runs, `cancel` is cleared and set again by the
+/// overlay's Cancel button
+fn show_busy(ui_weak: &Weak<EntryWindow>, message: &str, cancel: &CancelFlag) {
+    cancel.store(false, Ordering::Relaxed);
+    if let Some(ui) = ui_weak.upgrade() {
+        ui.set_busy_message(SharedString::from(message));
+        ui.set_busy_progress(-1.0);
+        ui.set_busy_cancellable(true);
+        ui.set_show_busy(true);
+    }
+}
+
+/// Busy overlay for work that cannot be interrupted
+fn show_busy_uncancellable(ui_weak: &Weak<EntryWindow>, message: &str) {
+    if let Some(ui) = ui_weak.upgrade() {
+        ui.set_busy_message(SharedString::from(message));
+        ui.set_busy_progress(-1.0);
+        ui.set_busy_cancellable(false);
+        ui.set_show_busy(true);
+    }
+}
+
+fn hide_busy(ui_weak: &Weak<EntryWindow>) {
+    if let Some(ui) = ui_weak.upgrade() {
+        ui.set_show_busy(false);
+    }
+}
+
+/// Post progress to the busy overlay from a worker thread. Only every percent is posted so the
+/// event loop is not flooded, `reported` keeps track of the last one.
+fn report_progress(ui_weak: &Weak<EntryWindow>, verb: &'static str, reported: &mut usize, done: usize, total: usize) {
+    if done != total && (done - *reported) * 100 < total {
+        return;
+    }
+    *reported = done;
+
+    let ui_weak = ui_weak.clone();
+    slint::invoke_from_event_loop(move || {
+        if let Some(ui) = ui_weak.upgrade() {
+            ui.set_busy_message(SharedString::from(format!("{} {} of {} records", verb, done, total)));
+            ui.set_busy_progress(done as f32 / total as f32);
+        }
+    }).ok();
+}
+
+fn save_all_handler(ui_weak: Weak<EntryWindow>) {
+    // SQLite auto-commits, but we can show a confirmation
+    show_info_toast(&ui_weak, "All data saved!");
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    // Initialize logger
+    env_logger::Builder::from_default_env()
+        .filter_level(log::LevelFilter::Info)
+        .init();
+    
+    info!("Starting {} application", APP_NAME);
+    
+    match init_manager() {
+        Ok((db_exist, manager)) => run(db_exist, manager)?,
+        Err(e) => {
+            error!("Failed to initialize database manager: {}", e);
+            run_error_ui(&e)?;
+        }
+    }
+    Ok(())
+}
+
+/// Show only the error page, used when the vault cannot be opened at all
+fn run_error_ui(error: &PandaboxError) -> Result<(), Box<dyn Error>> {
+    let ui = EntryWindow::new()?;
+    ui.set_app_version(SharedString::from(env!("CARGO_PKG_VERSION")));
+    show_error_page(&ui.as_weak(), error, None);
+    ui.on_quit(|| {
+        slint::quit_event_loop().ok();
+    });
+    ui.run()?;
+    Ok(())
+}
+
+/// Replace the current page with an explanation of `error`. With a `return_page` the user can
+/// go back and try again, otherwise the only way out is quitting.
+fn show_error_page(ui_weak: &Weak<EntryWindow>, error: &PandaboxError, return_page: Option<Page>) {
+    if let Some(ui) = ui_weak.upgrade() {
+        ui.set_error_title(SharedString::from(error.summary()));
+        ui.set_error_message(SharedString::from(error.to_string()));
+        ui.set_error_can_return(return_page.is_some());
+        if let Some(page) = return_page {
+            ui.set_error_return_page(page);
+        }
+        ui.set_current_page(Page::Error);
+    }
+}
+
+/// Unlock the vault on a worker thread, deriving the key with Argon2 takes long enough to
+/// freeze the window. On success the session is shared with the services and the vault is
+/// shown, otherwise `on_error` reports the failure.
+fn unlock_in_background(
+    ui_weak: Weak<EntryWindow>,
+    session_state: SessionState,
+    #[cfg(target_os = "linux")] secret_service: Option<Arc<SecretService>>,
+    busy_message: &str,
+    unlock: impl FnOnce() -> Result<Session, PandaboxError> + Send + 'static,
+    on_error: fn(&Weak<EntryWindow>, PandaboxError),
+) {
+    show_busy_uncancellable(&ui_weak, busy_message);
+
+    std::thread::spawn(move || {
+        let result = unlock().map(Arc::new);
+        if let Ok(session) = &result {
+            // Update the session state
+            *session_state.lock().unwrap() = Some(Arc::clone(session));
+            upgrade_cipher_suite_in_background(&session_state, session);
+
+            #[cfg(target_os = "linux")]
+            if let Some(secret_service) = &secret_service {
+                secret_service.vault_unlocked();
+            }
+        }
+
+        slint::invoke_from_event_loop(move || {
+            hide_busy(&ui_weak);
+            match result {
+                Ok(session) => {
+                    // Update UI
+                    if let Some(ui) = ui_weak.upgrade() {
+                        // Recovering may have dropped the key file, the next unlock asks for what the vault needs now
+                        ui.set_requires_key_file(session.requires_key_file().unwrap_or_else(|e| {
+                            error!("Failed to look for a key file requirement: {}", e);
+                            ui.get_requires_key_file()
+                        }));
+                        ui.set_auth_error(false);
+                        ui.set_auth_message(SharedString::new());
+                        ui.set_recover_message(SharedString::new());
+                        ui.set_current_page(Page::Passlock);
+                        refresh_table_data(&ui_weak, &session);
                     }
-                    Err(e) => error!("Failed to parse CSV record: {}", e),
                 }
+                Err(e) => on_error(&ui_weak, e),
             }
-            
-            // Refresh table
-            refresh_table_data(&ui_weak, session);
-            
-            // Show success toast
+        }).ok();
+    });
+}
+
+fn authentication_failed(ui_weak: &Weak<EntryWindow>, error: PandaboxError) {
+    match error {
+        e @ PandaboxError::WrongPassword { remaining_attempts } => {
+            error!("Failed to decrypt master key");
+            // Only worth a line of text when the vault is going to be wiped
+            let message = if remaining_attempts.is_some() { e.to_string() } else { String::new() };
             if let Some(ui) = ui_weak.upgrade() {
-                ui.global::<AppData>().set_toast_message(SharedString::from(format!("Imported {} records", count)));
-                ui.global::<AppData>().set_show_toast(true);
-                
-                let ui_weak_timer = ui.as_weak();
-                let timer = Timer::default();
-                timer.start(TimerMode::SingleShot, std::time::Duration::from_secs(3), move || {
-                    if let Some(ui) = ui_weak_timer.upgrade() {
-                        ui.global::<AppData>().set_show_toast(false);
-                    }
-                });
-                std::mem::forget(timer);
+                ui.set_auth_message(SharedString::from(message));
             }
+            shake_password_field(ui_weak);
         }
-        Err(e) => {
-            error!("Failed to open CSV file: {}", e);
+        e @ (PandaboxError::UnlockDelayed(_) | PandaboxError::KeyFileRequired) => {
+            warn!("Unlock refused: {}", e);
             if let Some(ui) = ui_weak.upgrade() {
-                ui.global::<AppData>().set_toast_message(SharedString::from("Failed to open CSV file"));
-                ui.global::<AppData>().set_show_toast(true);
-                
-                let ui_weak_timer = ui.as_weak();
-                let timer = Timer::default();
-                timer.start(TimerMode::SingleShot, std::time::Duration::from_secs(3), move || {
-                    if let Some(ui) = ui_weak_timer.upgrade() {
-                        ui.global::<AppData>().set_show_toast(false);
-                    }
-                });
-                std::mem::forget(timer);
+                ui.set_auth_message(SharedString::from(e.to_string()));
             }
+            shake_password_field(ui_weak);
         }
+        e => unlock_failed(ui_weak, e, Page::Authenticate),
     }
+}
+
+fn recovery_failed(ui_weak: &Weak<EntryWindow>, error: PandaboxError) {
+    match error {
+        e @ (PandaboxError::WrongRecoveryKey { .. } | PandaboxError::UnlockDelayed(_)) => {
+            warn!("Recovery refused: {}", e);
+            if let Some(ui) = ui_weak.upgrade() {
+                ui.set_recover_message(SharedString::from(e.to_string()));
+            }
+        }
+        e => unlock_failed(ui_weak, e, Page::Recover),
     }
 }
 
-fn save_all_handler(ui_weak: Weak<EntryWindow>) {
-    // SQLite auto-commits, but we can show a confirmation
+fn unlock_failed(ui_weak: &Weak<EntryWindow>, error: PandaboxError, return_page: Page) {
     if let Some(ui) = ui_weak.upgrade() {
-        ui.global::<AppData>().set_toast_message(SharedString::from("All data saved!"));
-        ui.global::<AppData>().set_show_toast(true);
-        
-        let ui_weak_timer = ui.as_weak();
-        let timer = Timer::default();
-        timer.start(TimerMode::SingleShot, std::time::Duration::from_secs(2), move || {
-            if let Some(ui) = ui_weak_timer.upgrade() {
-                ui.global::<AppData>().set_show_toast(false);
+        ui.set_auth_message(SharedString::new());
+        ui.set_recover_message(SharedString::new());
+    }
+    match error {
+        e @ PandaboxError::VaultWiped => {
+            error!("{}", e);
+            // The vault is empty now, going back starts over with a new one
+            if let Some(ui) = ui_weak.upgrade() {
+                ui.set_can_recover(false);
+                ui.set_requires_key_file(false);
+                ui.set_can_unlock_with_key_file(false);
             }
+            show_error_page(ui_weak, &e, Some(Page::CreateDb));
+        }
+        e => {
+            error!("Failed to unlock the vault: {}", e);
+            show_error_page(ui_weak, &e, Some(return_page));
+        }
+    }
+}
+
+/// Re-encrypt the fields written with an older cipher suite while the vault is in use. Locking
+/// the vault or rotating its key stops the upgrade, it starts over at the next unlock.
+fn upgrade_cipher_suite_in_background(session_state: &SessionState, session: &Arc<Session>) {
+    match session.needs_cipher_upgrade() {
+        Ok(true) => {}
+        Ok(false) => return,
+        Err(e) => {
+            error!("Failed to read the ciphertext format: {}", e);
+            return;
+        }
+    }
+
+    let session_state = Arc::clone(session_state);
+    let session = Arc::clone(session);
+    std::thread::spawn(move || {
+        let result = session.upgrade_cipher_suite(|_, _| {
+            session_state.lock().unwrap().as_ref().is_some_and(|current| Arc::ptr_eq(current, &session))
         });
-        std::mem::forget(timer);
+        match result {
+            Ok(Some(_)) => {}
+            Ok(None) => info!("Cipher suite upgrade stopped, the vault was locked"),
+            Err(e) => error!("Failed to upgrade the cipher suite: {}", e),
+        }
+    });
+}
+
+/// Show the recovery key of a new vault so it can be written down, or go straight to the
+/// authentication page when the vault has none
+fn show_created_vault(ui: &EntryWindow, recovery_key: Option<Zeroizing<String>>) {
+    match recovery_key {
+        Some(recovery_key) => {
+            let words: Vec<SharedString> = recovery_key.split(' ').map(SharedString::from).collect();
+            ui.set_recovery_words(ModelRc::new(VecModel::from(words)));
+            ui.set_can_recover(true);
+            ui.set_recovery_key_return_page(Page::Authenticate);
+            ui.set_current_page(Page::RecoveryKey);
+        }
+        None => ui.set_current_page(Page::Authenticate),
     }
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    // Initialize logger
-    env_logger::Builder::from_default_env()
-        .filter_level(log::LevelFilter::Info)
-        .init();
-    
-    info!("Starting Pandabox application");
-    
-    let (db_exist, manager) = init_manager();
+/// Save the recovery key with a short explanation as a text file to print or store offline
+fn save_emergency_kit(words: &[SharedString]) {
+    let Some(path) = FileDialog::new()
+        .add_filter("Text", &["txt"])
+        .set_file_name("pandabox-emergency-kit.txt")
+        .save_file()
+    else {
+        return;
+    };
 
-    match manager {
-        Some(manager) => run(db_exist, manager)?,
-        None => error!("Failed to initialize database manager"),
+    let mut kit = Zeroizing::new(String::from("Pandabox emergency kit\n\n"));
+    kit.push_str(&format!("Vault: {}\n\nRecovery key:\n", vault_path().display()));
+    for (index, word) in words.iter().enumerate() {
+        kit.push_str(&format!("{:>2}. {}\n", index + 1, word));
+    }
+    kit.push_str("\nChoose \"Forgot your master password?\" on the unlock screen and enter these words to\n");
+    kit.push_str("set a new master password. Anyone holding this key can open the vault, keep it offline.\n");
+
+    match std::fs::write(&path, kit.as_bytes()) {
+        Ok(()) => info!("Saved the emergency kit to {}", path.display()),
+        Err(e) => error!("Failed to save the emergency kit: {}", e),
     }
-    Ok(())
 }
 
-fn run(db_exist: bool, manager: Rc<DatabaseManager>) -> Result<(), Box<dyn Error>> {
+fn run(db_exist: bool, manager: Arc<DatabaseManager>) -> Result<(), Box<dyn Error>> {
     get_initial_ui(db_exist, manager)?;
     Ok(())
 }
 
-fn get_initial_ui(db_exist: bool, manager: Rc<DatabaseManager>) -> Result<(), Box<dyn Error>> {
+fn get_initial_ui(db_exist: bool, manager: Arc<DatabaseManager>) -> Result<(), Box<dyn Error>> {
     let ui = EntryWindow::new()?;
     
     // Set version from Cargo.toml
     ui.set_app_version(SharedString::from(env!("CARGO_PKG_VERSION")));
 
+    let app_settings = Rc::new(RefCell::new(AppSettings::load()));
+    {
+        let app_settings = app_settings.borrow();
+        ui.invoke_apply_theme(SharedString::from(app_settings.theme.as_str()));
+        ui.window().set_size(slint::LogicalSize::new(app_settings.window_width as f32, app_settings.window_height as f32));
+    }
+
     if db_exist {
+        ui.set_can_recover(manager.has_key_slot(RECOVERY_SLOT).unwrap_or_else(|e| {
+            error!("Failed to look for a recovery key: {}", e);
+            false
+        }));
+        ui.set_requires_key_file(manager.get_master_record().map(|record| record.requires_key_file).unwrap_or_else(|e| {
+            error!("Failed to look for a key file requirement: {}", e);
+            false
+        }));
+        ui.set_can_unlock_with_key_file(manager.get_key_slots_of_kind(KEY_FILE_KIND).map(|slots| !slots.is_empty()).unwrap_or_else(|e| {
+            error!("Failed to look for key file slots: {}", e);
+            false
+        }));
         ui.set_current_page(Page::Authenticate);
     }
     else{
@@ -450,77 +805,147 @@ fn get_initial_ui(db_exist: bool, manager: Rc<DatabaseManager>) -> Result<(), Bo
 
     let ui_weak = ui.as_weak();
 
-    ui.on_create_db_submitted(make_db_callback(
+    let cancel: CancelFlag = Arc::default();
+    let cancel_for_busy = Arc::clone(&cancel);
+    ui.on_cancel_busy(move || {
+        cancel_for_busy.store(true, Ordering::Relaxed);
+    });
+
+    let create_vault = make_db_callback(
         manager.clone(),
         ui_weak.clone(),
         create_db_submitted,
-        Page::Authenticate,
-    ));
+        show_created_vault,
+    );
+    let app_settings_for_create = Rc::clone(&app_settings);
+    let ui_weak_for_create = ui_weak.clone();
+    ui.on_create_db_submitted(move |password: SharedString, confirmation: SharedString, with_recovery_key: bool, key_file: SharedString| {
+        // The form only offers to create the vault for an accepted password, check it again here
+        let min_entropy = app_settings_for_create.borrow().min_master_password_entropy;
+        match check_master_password(&password, &confirmation, min_entropy) {
+            Ok(_) => {
+                if let Some(ui) = ui_weak_for_create.upgrade() {
+                    ui.set_requires_key_file(!key_file.is_empty());
+                }
+                create_vault((password, with_recovery_key, key_file))
+            }
+            Err(problem) => warn!("Refused the new master password: {}", problem),
+        }
+    });
+    let app_settings_for_check = Rc::clone(&app_settings);
+    ui.on_check_master_password(move |password: SharedString, confirmation: SharedString| {
+        let min_entropy = app_settings_for_check.borrow().min_master_password_entropy;
+        match check_master_password(&password, &confirmation, min_entropy) {
+            Ok(_) => SharedString::new(),
+            Err(problem) => SharedString::from(problem.to_string()),
+        }
+    });
+    ui.on_choose_key_file(choose_key_file);
+    ui.on_generate_key_file(generate_key_file);
+    ui.on_password_strength(|password: SharedString| {
+        let bits = estimate_entropy(&password);
+        SharedString::from(format!("{} ({:.0} bits)", PasswordStrength::from_entropy(bits).label(), bits))
+    });
 
     // Create a session state that will be shared between the UI and the authentication callback
-    let session_state = Arc::new(Mutex::new(None::<Session>));
+    let session_state: SessionState = Arc::new(Mutex::new(None));
     let session_state_for_auth = Arc::clone(&session_state);
     let ui_weak_for_auth = ui_weak.clone();
+
+    #[cfg(target_os = "linux")]
+    let secret_service = if std::env::args().any(|arg| arg == "--secret-service") {
+        setup_secret_service(&ui, manager.clone(), Arc::clone(&session_state)).map(Arc::new)
+    } else {
+        None
+    };
     
-    ui.on_authenticate_submitted(move |input| {
-        let ui_weak = ui_weak_for_auth.clone();
-        let session_state = session_state_for_auth.clone();
-        let (state, session) = authenticate_submitted(input, manager.clone());
-        if state {
-            if let Some(session) = session {
-                // Update the session state
-                *session_state.lock().unwrap() = Some(session);
-                
-                // Update UI
-                if let Some(ui) = ui_weak.upgrade() {
-                    ui.set_auth_error(false);
-                    ui.set_current_page(Page::Passlock);
-                    refresh_table_data(&ui_weak, session_state.lock().unwrap().as_ref().unwrap());
-                }
-            }
-        } else {
-            // Trigger shake animation
-            if let Some(ui) = ui_weak.upgrade() {
-                ui.set_auth_error(true);
-                
-                // Trigger shake sequence
-                let ui_weak_shake = ui.as_weak();
-                std::thread::spawn(move || {
-                    for i in 1..=6 {
-                        std::thread::sleep(std::time::Duration::from_millis(80));
-                        let ui_weak_clone = ui_weak_shake.clone();
-                        slint::invoke_from_event_loop(move || {
-                            if let Some(ui) = ui_weak_clone.upgrade() {
-                                ui.set_shake_trigger(i);
-                            }
-                        }).ok();
-                    }
-                    // Reset shake
-                    std::thread::sleep(std::time::Duration::from_millis(80));
-                    let ui_weak_clone = ui_weak_shake.clone();
-                    slint::invoke_from_event_loop(move || {
-                        if let Some(ui) = ui_weak_clone.upgrade() {
-                            ui.set_shake_trigger(0);
-                        }
-                    }).ok();
-                    
-                    // Clear error after 2 seconds
-                    std::thread::sleep(std::time::Duration::from_secs(2));
-                    let ui_weak_clone = ui_weak_shake.clone();
-                    slint::invoke_from_event_loop(move || {
-                        if let Some(ui) = ui_weak_clone.upgrade() {
-                            ui.set_auth_error(false);
-                        }
-                    }).ok();
-                });
-            }
-        }
+    let manager_for_auth = manager.clone();
+    #[cfg(target_os = "linux")]
+    let secret_service_for_auth = secret_service.clone();
+    ui.on_authenticate_submitted(move |input, key_file| {
+        let manager = manager_for_auth.clone();
+        unlock_in_background(
+            ui_weak_for_auth.clone(),
+            session_state_for_auth.clone(),
+            #[cfg(target_os = "linux")]
+            secret_service_for_auth.clone(),
+            "Unlocking the vault",
+            move || authenticate_submitted((input, key_file), manager),
+            authentication_failed,
+        );
     });
-    ui.on_generate_password(|| SharedString::from(CryptEngine::generate_random_password()));
 
-    let ui_weak_for_save = ui_weak.clone();
-    let session_state_for_save = Arc::clone(&session_state);
-    ui.on_save_service(move |data: ServiceData, mode: SharedString, row: i32| {
+    let ui_weak_for_key_file = ui_weak.clone();
+    let session_state_for_key_file = Arc::clone(&session_state);
+    let manager_for_key_file = manager.clone();
+    #[cfg(target_os = "linux")]
+    let secret_service_for_key_file = secret_service.clone();
+    ui.on_unlock_with_key_file(move || {
+        let Some(path) = FileDialog::new().set_title("Choose the key file").pick_file() else {
+            return;
+        };
+        let manager = manager_for_key_file.clone();
+        unlock_in_background(
+            ui_weak_for_key_file.clone(),
+            session_state_for_key_file.clone(),
+            #[cfg(target_os = "linux")]
+            secret_service_for_key_file.clone(),
+            "Unlocking the vault",
+            move || Session::unlock_with(manager, &KeyFile::read(path)?),
+            authentication_failed,
+        );
+    });
+
+    let ui_weak_for_recover = ui_weak.clone();
+    let session_state_for_recover = Arc::clone(&session_state);
+    let manager_for_recover = manager.clone();
+    let app_settings_for_recover = Rc::clone(&app_settings);
+    ui.on_recover_submitted(move |recovery_key: SharedString, password: SharedString, confirmation: SharedString| {
+        let min_entropy = app_settings_for_recover.borrow().min_master_password_entropy;
+        if let Err(problem) = check_master_password(&password, &confirmation, min_entropy) {
+            warn!("Refused the new master password: {}", problem);
+            return;
+        }
+        let manager = manager_for_recover.clone();
+        unlock_in_background(
+            ui_weak_for_recover.clone(),
+            session_state_for_recover.clone(),
+            #[cfg(target_os = "linux")]
+            secret_service.clone(),
+            "Recovering the vault",
+            // The key file may be lost too, the recovered vault opens with the new password alone
+            move || Session::recover(manager, &recovery_key, &password, None),
+            recovery_failed,
+        );
+    });
+    ui.on_check_recovery_key(|recovery_key: SharedString| {
+        if recovery_key.trim().is_empty() || normalize_recovery_key(&recovery_key).is_some() {
+            SharedString::new()
+        } else {
+            SharedString::from(format!("A recovery key is made of {} words from the emergency kit", RECOVERY_KEY_WORDS))
+        }
+    });
+
+    let ui_weak_for_kit = ui_weak.clone();
+    ui.on_save_emergency_kit(move || {
+        if let Some(ui) = ui_weak_for_kit.upgrade() {
+            let words: Vec<SharedString> = ui.get_recovery_words().iter().collect();
+            save_emergency_kit(&words);
+        }
+    });
+
+    let app_settings_for_generator = Rc::clone(&app_settings);
+    ui.on_generate_password(move || {
+        let generator = app_settings_for_generator.borrow().generator;
+        SharedString::from(CryptEngine::generate_random_password(generator.length, generator.symbols))
+    });
+    ui.on_quit(|| {
+        slint::quit_event_loop().ok();
+    });
+
+    let ui_weak_for_save = ui_weak.clone();
+    let session_state_for_save = Arc::clone(&session_state);
+    ui.on_save_service(move |data: ServiceData, mode: SharedString, row: i32| {
         let session_guard = session_state_for_save.lock().unwrap();
         if let Some(session) = &*session_guard {
             // Extract the service data from the ServiceData struct
@@ -528,12 +953,7 @@ fn get_initial_ui(db_exist: bool, manager: Rc<DatabaseManager>) -> Result<(), Bo
                 session, 
                 mode, 
                 row,
-                data.id, 
-                data.service, 
-                data.email, 
-                data.username, 
-                data.password, 
-                data.notes, 
+                data,
                 ui_weak_for_save.clone()
             );
         }
@@ -553,6 +973,161 @@ fn get_initial_ui(db_exist: bool, manager: Rc<DatabaseManager>) -> Result<(), Bo
         }
     });
 
+    let ui_weak_for_selection = ui_weak.clone();
+    ui.on_toggle_selected(move |row: i32| {
+        if let Some(ui) = ui_weak_for_selection.upgrade() {
+            set_selection(&ui, |index, was_selected| (index as i32 == row).then_some(!was_selected));
+        }
+    });
+
+    let ui_weak_for_selection = ui_weak.clone();
+    ui.on_select_all(move || {
+        if let Some(ui) = ui_weak_for_selection.upgrade() {
+            set_selection(&ui, |_, _| Some(true));
+        }
+    });
+
+    let ui_weak_for_selection = ui_weak.clone();
+    ui.on_clear_selection(move || {
+        if let Some(ui) = ui_weak_for_selection.upgrade() {
+            set_selection(&ui, |_, _| Some(false));
+        }
+    });
+
+    let ui_weak_for_bulk = ui_weak.clone();
+    let session_state_for_bulk = Arc::clone(&session_state);
+    ui.on_bulk_delete(move || {
+        let (Some(ui), Some(session)) = (ui_weak_for_bulk.upgrade(), session_state_for_bulk.lock().unwrap().clone()) else {
+            return;
+        };
+        let record_ids = selected_ids(&ui);
+        match session.delete_entries(&record_ids) {
+            Ok(deleted) => {
+                refresh_table_data(&ui_weak_for_bulk, &session);
+                show_undo_toast(&ui_weak_for_bulk, &format!("{} moved to the trash", entries_label(deleted)), &record_ids);
+            }
+            Err(e) => {
+                error!("Failed to delete entries: {}", e);
+                show_error_toast(&ui_weak_for_bulk, "Failed to delete the entries");
+            }
+        }
+    });
+
+    let ui_weak_for_bulk = ui_weak.clone();
+    let session_state_for_bulk = Arc::clone(&session_state);
+    ui.on_bulk_move(move |folder: SharedString| {
+        let (Some(ui), Some(session)) = (ui_weak_for_bulk.upgrade(), session_state_for_bulk.lock().unwrap().clone()) else {
+            return;
+        };
+        match session.move_to_folder(&selected_ids(&ui), folder.as_str()) {
+            Ok(moved) => {
+                refresh_table_data(&ui_weak_for_bulk, &session);
+                let message = match folder.trim() {
+                    "" => format!("Took {} out of their folder", entries_label(moved)),
+                    folder => format!("Moved {} to {}", entries_label(moved), folder),
+                };
+                show_info_toast(&ui_weak_for_bulk, &message);
+            }
+            Err(e) => {
+                error!("Failed to move entries: {}", e);
+                show_error_toast(&ui_weak_for_bulk, "Failed to move the entries");
+            }
+        }
+    });
+
+    let ui_weak_for_bulk = ui_weak.clone();
+    let session_state_for_bulk = Arc::clone(&session_state);
+    ui.on_bulk_tag(move |tag: SharedString| {
+        let (Some(ui), Some(session)) = (ui_weak_for_bulk.upgrade(), session_state_for_bulk.lock().unwrap().clone()) else {
+            return;
+        };
+        match session.tag_entries(&selected_ids(&ui), tag.as_str()) {
+            Ok(tagged) => {
+                refresh_table_data(&ui_weak_for_bulk, &session);
+                show_info_toast(&ui_weak_for_bulk, &format!("Tagged {} with {}", entries_label(tagged), tag.trim()));
+            }
+            Err(e) => {
+                error!("Failed to tag entries: {}", e);
+                show_error_toast(&ui_weak_for_bulk, "Failed to tag the entries");
+            }
+        }
+    });
+
+    let ui_weak_for_bulk = ui_weak.clone();
+    let session_state_for_bulk = Arc::clone(&session_state);
+    let cancel_for_bulk = Arc::clone(&cancel);
+    ui.on_bulk_export(move || {
+        if let Some(ui) = ui_weak_for_bulk.upgrade() {
+            let selection = selected_ids(&ui);
+            export_csv_handler(&session_state_for_bulk, ui_weak_for_bulk.clone(), Arc::clone(&cancel_for_bulk), Some(selection));
+        }
+    });
+
+    let ui_weak_for_trash = ui_weak.clone();
+    let session_state_for_trash = Arc::clone(&session_state);
+    ui.on_load_trash(move || {
+        if let Some(session) = &*session_state_for_trash.lock().unwrap() {
+            refresh_trash(&ui_weak_for_trash, session);
+        }
+    });
+
+    let ui_weak_for_trash = ui_weak.clone();
+    let session_state_for_trash = Arc::clone(&session_state);
+    // Takes a comma separated list, the Undo button restores every entry of a bulk delete
+    ui.on_restore_entry(move |ids: SharedString| {
+        let Some(session) = session_state_for_trash.lock().unwrap().clone() else {
+            return;
+        };
+        let Ok(record_ids) = ids.split(',').map(str::parse::<i32>).collect::<Result<Vec<_>, _>>() else {
+            error!("Failed to parse indices '{}'", ids);
+            return;
+        };
+        match session.restore_entries(&record_ids) {
+            Ok(0) => {}
+            Ok(1) => show_info_toast(&ui_weak_for_trash, "Entry restored"),
+            Ok(restored) => show_info_toast(&ui_weak_for_trash, &format!("{} restored", entries_label(restored))),
+            Err(e) => {
+                error!("Failed to restore entries {}: {}", ids, e);
+                show_error_toast(&ui_weak_for_trash, "Failed to restore the entry");
+            }
+        }
+        refresh_table_data(&ui_weak_for_trash, &session);
+        refresh_trash(&ui_weak_for_trash, &session);
+    });
+
+    let ui_weak_for_trash = ui_weak.clone();
+    let session_state_for_trash = Arc::clone(&session_state);
+    ui.on_purge_entry(move |id: SharedString| {
+        let Some(session) = session_state_for_trash.lock().unwrap().clone() else {
+            return;
+        };
+        let Ok(record_id) = id.parse::<i32>() else {
+            error!("Failed to parse index '{}'", id);
+            return;
+        };
+        if let Err(e) = session.purge_entry(record_id) {
+            error!("Failed to purge entry {}: {}", record_id, e);
+            show_error_toast(&ui_weak_for_trash, "Failed to delete the entry");
+        }
+        refresh_trash(&ui_weak_for_trash, &session);
+    });
+
+    let ui_weak_for_trash = ui_weak.clone();
+    let session_state_for_trash = Arc::clone(&session_state);
+    ui.on_empty_trash(move || {
+        let Some(session) = session_state_for_trash.lock().unwrap().clone() else {
+            return;
+        };
+        match session.empty_trash() {
+            Ok(purged) => show_info_toast(&ui_weak_for_trash, &format!("Deleted {} entries for good", purged)),
+            Err(e) => {
+                error!("Failed to empty the trash: {}", e);
+                show_error_toast(&ui_weak_for_trash, "Failed to empty the trash");
+            }
+        }
+        refresh_trash(&ui_weak_for_trash, &session);
+    });
+
     let ui_weak_for_clipboard = ui_weak.clone();
     let session_state_for_clipboard = Arc::clone(&session_state);
     ui.on_copy_to_clipboard(move |value: SharedString, field_name: SharedString| {
@@ -564,20 +1139,16 @@ fn get_initial_ui(db_exist: bool, manager: Rc<DatabaseManager>) -> Result<(), Bo
 
     let ui_weak_for_export = ui_weak.clone();
     let session_state_for_export = Arc::clone(&session_state);
+    let cancel_for_export = Arc::clone(&cancel);
     ui.on_export_csv(move || {
-        let session_guard = session_state_for_export.lock().unwrap();
-        if let Some(session) = &*session_guard {
-            export_csv_handler(session, ui_weak_for_export.clone());
-        }
+        export_csv_handler(&session_state_for_export, ui_weak_for_export.clone(), Arc::clone(&cancel_for_export), None);
     });
 
     let ui_weak_for_import = ui_weak.clone();
     let session_state_for_import = Arc::clone(&session_state);
+    let cancel_for_import = Arc::clone(&cancel);
     ui.on_import_csv(move || {
-        let session_guard = session_state_for_import.lock().unwrap();
-        if let Some(session) = &*session_guard {
-            import_csv_handler(session, ui_weak_for_import.clone());
-        }
+        import_csv_handler(&session_state_for_import, ui_weak_for_import.clone(), Arc::clone(&cancel_for_import));
     });
 
     let ui_weak_for_save = ui_weak.clone();
@@ -585,34 +1156,331 @@ fn get_initial_ui(db_exist: bool, manager: Rc<DatabaseManager>) -> Result<(), Bo
         save_all_handler(ui_weak_for_save.clone());
     });
 
+    let ui_weak_for_verify = ui_weak.clone();
+    let session_state_for_verify = Arc::clone(&session_state);
+    let cancel_for_verify = Arc::clone(&cancel);
+    ui.on_verify_vault(move || {
+        verify_vault_handler(&session_state_for_verify, ui_weak_for_verify.clone(), Arc::clone(&cancel_for_verify));
+    });
+
+    let ui_weak_for_report = ui_weak.clone();
+    let session_state_for_report = Arc::clone(&session_state);
+    ui.on_security_report(move || {
+        security_report_handler(&session_state_for_report, ui_weak_for_report.clone());
+    });
+
+    #[cfg(unix)]
+    let approvals = setup_approvals(&ui);
+    #[cfg(unix)]
+    approvals.set_timeout(app_settings.borrow().approval_timeout());
+    #[cfg(unix)]
+    let (_bridge, _agent) = setup_local_services(approvals.clone(), Arc::clone(&session_state));
+
+    let ui_weak_for_rotate = ui_weak.clone();
+    let session_state_for_rotate = Arc::clone(&session_state);
+    let cancel_for_rotate = Arc::clone(&cancel);
+    ui.on_rotate_master_key(move |password: SharedString| {
+        rotate_master_key_handler(&session_state_for_rotate, ui_weak_for_rotate.clone(), Arc::clone(&cancel_for_rotate), password);
+    });
+
+    let ui_weak_for_settings = ui_weak.clone();
+    let session_state_for_settings = Arc::clone(&session_state);
+    let app_settings_for_load = Rc::clone(&app_settings);
+    ui.on_load_settings(move || {
+        let (vault_settings, wipe_after_failures) = match &*session_state_for_settings.lock().unwrap() {
+            Some(session) => (session.settings(), session.wipe_after_failures().unwrap_or_else(|e| {
+                error!("Failed to read the wipe policy: {}", e);
+                None
+            })),
+            None => (VaultSettings::default(), None),
+        };
+        if let Some(ui) = ui_weak_for_settings.upgrade() {
+            let data = settings_data(&app_settings_for_load.borrow(), &vault_settings, wipe_after_failures);
+            ui.global::<AppData>().set_settings(data);
+        }
+        if let Some(session) = &*session_state_for_settings.lock().unwrap() {
+            refresh_key_slots(&ui_weak_for_settings, session);
+        }
+    });
+
+    let ui_weak_for_slots = ui_weak.clone();
+    let session_state_for_slots = Arc::clone(&session_state);
+    ui.on_add_key_file_slot(move || {
+        let Some(session) = session_state_for_slots.lock().unwrap().clone() else {
+            return;
+        };
+        let Some(path) = FileDialog::new().set_title("Choose the key file").pick_file() else {
+            return;
+        };
+        let name = path.file_name().map_or_else(|| path.to_string_lossy(), |name| name.to_string_lossy()).into_owned();
+
+        // Wrapping the master key derives a key with Argon2, keep the window responsive
+        show_busy_uncancellable(&ui_weak_for_slots, "Adding the key file");
+        let ui_weak = ui_weak_for_slots.clone();
+        std::thread::spawn(move || {
+            let result = KeyFile::read(&path).map_err(PandaboxError::from).and_then(|key_file| session.add_key_slot(&name, &key_file));
+            slint::invoke_from_event_loop(move || {
+                hide_busy(&ui_weak);
+                match result {
+                    Ok(()) => show_info_toast(&ui_weak, &format!("Added the key file {}", name)),
+                    Err(e) => {
+                        error!("Failed to add the key file {}: {}", path.display(), e);
+                        show_error_toast(&ui_weak, &e.to_string());
+                    }
+                }
+                refresh_key_slots(&ui_weak, &session);
+            }).ok();
+        });
+    });
+
+    let ui_weak_for_slots = ui_weak.clone();
+    let session_state_for_slots = Arc::clone(&session_state);
+    ui.on_remove_key_slot(move |name: SharedString| {
+        let Some(session) = session_state_for_slots.lock().unwrap().clone() else {
+            return;
+        };
+        match session.remove_key_slot(&name) {
+            Ok(()) => show_info_toast(&ui_weak_for_slots, &format!("Removed {}", name)),
+            Err(e) => {
+                error!("Failed to remove the key slot {}: {}", name, e);
+                show_error_toast(&ui_weak_for_slots, &e.to_string());
+            }
+        }
+        refresh_key_slots(&ui_weak_for_slots, &session);
+    });
+
+    let ui_weak_for_settings = ui_weak.clone();
+    let session_state_for_settings = Arc::clone(&session_state);
+    let app_settings_for_save = Rc::clone(&app_settings);
+    ui.on_save_settings(move |data: SettingsData| {
+        let mut app_settings = app_settings_for_save.borrow_mut();
+        app_settings.theme = Theme::parse(data.theme.as_str());
+        app_settings.generator.length = data.generator_length.max(0) as usize;
+        app_settings.generator.symbols = data.generator_symbols;
+        app_settings.approval_timeout_seconds = data.approval_timeout_seconds.max(0) as u64;
+        app_settings.min_master_password_entropy = data.min_master_password_entropy.max(0) as u32;
+        *app_settings = app_settings.clone().sanitized();
+
+        #[cfg(unix)]
+        approvals.set_timeout(app_settings.approval_timeout());
+        if let Some(ui) = ui_weak_for_settings.upgrade() {
+            ui.invoke_apply_theme(SharedString::from(app_settings.theme.as_str()));
+        }
+
+        let mut saved = match app_settings.save() {
+            Ok(()) => true,
+            Err(e) => {
+                error!("Failed to save settings to {}: {}", AppSettings::path().display(), e);
+                false
+            }
+        };
+        if let Some(session) = &*session_state_for_settings.lock().unwrap() {
+            let vault_settings = VaultSettings {
+                clipboard_clear_seconds: data.clipboard_clear_seconds.max(0) as u64,
+                max_password_age_days: data.max_password_age_days.max(1) as u64,
+                trash_retention_days: data.trash_retention_days.max(0) as u64,
+            };
+            if let Err(e) = session.save_settings(&vault_settings) {
+                error!("Failed to save vault settings: {}", e);
+                saved = false;
+            }
+            let wipe_after_failures = (data.wipe_after_failures > 0).then_some(data.wipe_after_failures as u32);
+            if let Err(e) = session.set_wipe_after_failures(wipe_after_failures) {
+                error!("Failed to save the wipe policy: {}", e);
+                saved = false;
+            }
+        }
+
+        if saved {
+            show_info_toast(&ui_weak_for_settings, "Settings saved");
+        } else {
+            show_error_toast(&ui_weak_for_settings, "Failed to save settings");
+        }
+    });
+
+    let ui_weak_for_search = ui_weak.clone();
+    let session_state_for_search = Arc::clone(&session_state);
+    ui.on_search(move |_query: SharedString| {
+        let session_guard = session_state_for_search.lock().unwrap();
+        if let Some(session) = &*session_guard {
+            refresh_table_data(&ui_weak_for_search, session);
+        }
+    });
+
+    let ui_weak_for_row = ui_weak.clone();
+    ui.on_record_row(move |record_id: SharedString| {
+        ui_weak_for_row
+            .upgrade()
+            .and_then(|ui| {
+                ui.global::<AppData>()
+                    .get_table_rows()
+                    .iter()
+                    .position(|row| row.row_data(1).is_some_and(|cell| cell.text == record_id))
+            })
+            .map_or(-1, |index| index as i32)
+    });
+
     ui.run().unwrap();
 
+    // Reopen at the size the window was left at
+    let size = ui.window().size().to_logical(ui.window().scale_factor());
+    let mut app_settings = app_settings.borrow_mut();
+    app_settings.window_width = size.width.round() as u32;
+    app_settings.window_height = size.height.round() as u32;
+    if let Err(e) = app_settings.save() {
+        warn!("Failed to save the window size: {}", e);
+    }
+
     Ok(())
 }
 
+/// Replace the master key on a worker thread once the master password is confirmed
+fn rotate_master_key_handler(session_state: &SessionState, ui_weak: Weak<EntryWindow>, cancel: CancelFlag, password: SharedString) {
+    let Some(session) = session_state.lock().unwrap().clone() else {
+        return;
+    };
+    // The new key is wrapped like the current one, with the key file when the vault needs it
+    let key_file_path = match session.requires_key_file() {
+        Ok(true) => match FileDialog::new().set_title("Choose the key file").pick_file() {
+            Some(path) => Some(path),
+            None => return,
+        },
+        Ok(false) => None,
+        Err(e) => {
+            error!("Failed to look for a key file requirement: {}", e);
+            show_error_toast(&ui_weak, "Failed to rotate the master key");
+            return;
+        }
+    };
+
+    show_busy(&ui_weak, "Rotating the master key", &cancel);
+
+    // Every entry is re-encrypted, keep the window responsive meanwhile
+    let session_state = Arc::clone(session_state);
+    std::thread::spawn(move || {
+        let mut reported = 0;
+        let result = key_file_path
+            .map(KeyFile::read)
+            .transpose()
+            .map_err(PandaboxError::from)
+            .and_then(|key_file| session.rotate_master_key(&password, key_file.as_ref(), |done, total| {
+                report_progress(&ui_weak, "Re-encrypted", &mut reported, done, total);
+                !cancel.load(Ordering::Relaxed)
+            }));
+
+        // The old key no longer decrypts anything, switch to the new session right away
+        let rotated = match result {
+            Ok(Some((rotated, removed))) => {
+                let rotated = Arc::new(rotated);
+                *session_state.lock().unwrap() = Some(Arc::clone(&rotated));
+                // The recovery key opened the old key, the user gets a new one to write down
+                let recovery_key = if removed.iter().any(|slot| slot == RECOVERY_SLOT) {
+                    match rotated.create_recovery_key() {
+                        Ok(recovery_key) => Some(recovery_key),
+                        Err(e) => {
+                            error!("Failed to create a new recovery key: {}", e);
+                            None
+                        }
+                    }
+                } else {
+                    None
+                };
+                Ok(Some((rotated, removed, recovery_key)))
+            }
+            Ok(None) => Ok(None),
+            Err(e) => Err(e),
+        };
+
+        slint::invoke_from_event_loop(move || {
+            hide_busy(&ui_weak);
+            match rotated {
+                Ok(Some((rotated, removed, recovery_key))) => {
+                    refresh_key_slots(&ui_weak, &rotated);
+                    let key_files = removed.iter().filter(|slot| *slot != RECOVERY_SLOT).count();
+                    if key_files > 0 {
+                        show_info_toast(&ui_weak, &format!("Rotated the master key, add the {} removed key files again", key_files));
+                    } else {
+                        show_info_toast(&ui_weak, "Rotated the master key");
+                    }
+                    if let (Some(recovery_key), Some(ui)) = (recovery_key, ui_weak.upgrade()) {
+                        let words: Vec<SharedString> = recovery_key.split(' ').map(SharedString::from).collect();
+                        ui.set_recovery_words(ModelRc::new(VecModel::from(words)));
+                        ui.set_recovery_key_return_page(Page::Passlock);
+                        ui.set_current_page(Page::RecoveryKey);
+                    }
+                }
+                Ok(None) => show_info_toast(&ui_weak, "Rotation cancelled, nothing was changed"),
+                Err(e) => {
+                    error!("Failed to rotate the master key: {}", e);
+                    show_error_toast(&ui_weak, &e.to_string());
+                }
+            }
+        }).ok();
+    });
+}
+
+/// List the key slots on the settings page, along with those a rotation would remove, and
+/// offer the key files on the unlock page
+fn refresh_key_slots(ui_weak: &Weak<EntryWindow>, session: &Session) {
+    let listed = session
+        .key_slots()
+        .and_then(|slots| Ok((slots, session.key_slots_removed_by_rotation()?)));
+    let (slots, removed_by_rotation) = match listed {
+        Ok(listed) => listed,
+        Err(e) => {
+            error!("Failed to list the key slots: {}", e);
+            return;
+        }
+    };
+    let Some(ui) = ui_weak.upgrade() else {
+        return;
+    };
+
+    ui.set_can_unlock_with_key_file(slots.iter().any(|slot| slot.kind == KEY_FILE_KIND));
+    let rows: Vec<KeySlotData> = slots.iter().map(key_slot_data).collect();
+    ui.global::<AppData>().set_key_slots(ModelRc::new(VecModel::from(rows)));
+    let rows: Vec<KeySlotData> = removed_by_rotation.iter().map(key_slot_data).collect();
+    ui.global::<AppData>().set_rotation_removed_slots(ModelRc::new(VecModel::from(rows)));
+}
+
+fn key_slot_data(slot: &KeySlotInfo) -> KeySlotData {
+    KeySlotData {
+        name: SharedString::from(slot.slot.as_str()),
+        description: SharedString::from(match slot.kind.as_str() {
+            PASSWORD_KIND if slot.requires_key_file => "Master password and key file",
+            PASSWORD_KIND => "Master password",
+            RECOVERY_KIND => "Recovery key from the emergency kit",
+            KEY_FILE_KIND => "Key file",
+            _ => "Unknown unlock method",
+        }),
+        removable: slot.slot != PASSWORD_SLOT,
+    }
+}
+
+fn settings_data(app_settings: &AppSettings, vault_settings: &VaultSettings, wipe_after_failures: Option<u32>) -> SettingsData {
+    SettingsData {
+        theme: SharedString::from(app_settings.theme.as_str()),
+        generator_length: app_settings.generator.length as i32,
+        generator_symbols: app_settings.generator.symbols,
+        approval_timeout_seconds: app_settings.approval_timeout_seconds as i32,
+        min_master_password_entropy: app_settings.min_master_password_entropy as i32,
+        clipboard_clear_seconds: vault_settings.clipboard_clear_seconds as i32,
+        max_password_age_days: vault_settings.max_password_age_days as i32,
+        trash_retention_days: vault_settings.trash_retention_days as i32,
+        wipe_after_failures: wipe_after_failures.unwrap_or(0) as i32,
+    }
+}
+
 fn copy_to_clipboard_handler(value: SharedString, field_name: SharedString, ui_weak: Weak<EntryWindow>, session: &Session) {
     // Fetch and decrypt password if we're copying a password field
-    let value_to_copy = if field_name.as_str() == "Password" {
+    let is_password = field_name.as_str() == "Password";
+    let value_to_copy = Zeroizing::new(if is_password {
         // Parse the record ID
         let record_id = match value.as_str().parse::<i32>() {
             Ok(id) => id,
             Err(e) => {
                 error!("Failed to parse record ID: {}", e);
-                if let Some(ui) = ui_weak.upgrade() {
-                    ui.global::<AppData>().set_toast_message(SharedString::from("Invalid record ID"));
-                    ui.global::<AppData>().set_show_toast(true);
-                    
-                    let ui_weak_timer = ui.as_weak();
-                    slint::invoke_from_event_loop(move || {
-                        let timer = Timer::default();
-                        timer.start(TimerMode::SingleShot, std::time::Duration::from_secs(2), move || {
-                            if let Some(ui) = ui_weak_timer.upgrade() {
-                                ui.global::<AppData>().set_show_toast(false);
-                            }
-                        });
-                        std::mem::forget(timer);
-                    }).ok();
-                }
+                show_error_toast(&ui_weak, "Invalid record ID");
                 return;
             }
         };
@@ -622,28 +1490,13 @@ fn copy_to_clipboard_handler(value: SharedString, field_name: SharedString, ui_w
             Ok(decrypted) => decrypted,
             Err(e) => {
                 error!("Failed to fetch/decrypt password: {}", e);
-                // Show error toast
-                if let Some(ui) = ui_weak.upgrade() {
-                    ui.global::<AppData>().set_toast_message(SharedString::from("Failed to decrypt password"));
-                    ui.global::<AppData>().set_show_toast(true);
-                    
-                    let ui_weak_timer = ui.as_weak();
-                    slint::invoke_from_event_loop(move || {
-                        let timer = Timer::default();
-                        timer.start(TimerMode::SingleShot, std::time::Duration::from_secs(2), move || {
-                            if let Some(ui) = ui_weak_timer.upgrade() {
-                                ui.global::<AppData>().set_show_toast(false);
-                            }
-                        });
-                        std::mem::forget(timer);
-                    }).ok();
-                }
+                show_error_toast(&ui_weak, "Failed to decrypt password");
                 return;
             }
         }
     } else {
         value.to_string()
-    };
+    });
 
     // Copy to clipboard with retry logic for Flatpak environments
     let mut clipboard_result = Clipboard::new();
@@ -656,7 +1509,7 @@ fn copy_to_clipboard_handler(value: SharedString, field_name: SharedString, ui_w
     
     match clipboard_result {
         Ok(mut clipboard) => {
-            match clipboard.set_text(&value_to_copy) {
+            match clipboard.set_text(value_to_copy.as_str()) {
                 Ok(_) => {
                     println!("Copied {} to clipboard", field_name);
                     
@@ -665,28 +1518,12 @@ fn copy_to_clipboard_handler(value: SharedString, field_name: SharedString, ui_w
                         std::thread::sleep(std::time::Duration::from_millis(100));
                         drop(clipboard);
                     });
-                    
-                    // Show toast notification
-                    if let Some(ui) = ui_weak.upgrade() {
-                        let message = format!("{} copied!", field_name);
-                        
-                        // Set the toast message and show it
-                        ui.global::<AppData>().set_toast_message(SharedString::from(message));
-                        ui.global::<AppData>().set_show_toast(true);
-                        
-                        // Create a timer to hide the toast after 2 seconds
-                        let ui_weak_timer = ui.as_weak();
-                        slint::invoke_from_event_loop(move || {
-                            let timer = Timer::default();
-                            timer.start(TimerMode::SingleShot, std::time::Duration::from_secs(2), move || {
-                                if let Some(ui) = ui_weak_timer.upgrade() {
-                                    ui.global::<AppData>().set_show_toast(false);
-                                }
-                            });
-                            // Keep timer alive by leaking it (it will auto-cleanup after firing)
-                            std::mem::forget(timer);
-                        }).ok();
+
+                    if is_password && let Some(delay) = session.settings().clipboard_clear_delay() {
+                        clear_clipboard_later(value_to_copy, delay);
                     }
+                    
+                    show_info_toast(&ui_weak, &format!("{} copied!", field_name));
                 }
                 Err(e) => {
                     error!("Failed to copy to clipboard: {}", e);
@@ -701,17 +1538,64 @@ fn copy_to_clipboard_handler(value: SharedString, field_name: SharedString, ui_w
     }
 }
 
+/// Empty the clipboard after `delay` unless something else was copied in the meantime
+fn clear_clipboard_later(copied: Zeroizing<String>, delay: Duration) {
+    std::thread::spawn(move || {
+        std::thread::sleep(delay);
+        let Ok(mut clipboard) = Clipboard::new() else {
+            return;
+        };
+        let current = clipboard.get_text().map(Zeroizing::new);
+        if current.is_ok_and(|current| current == copied) {
+            match clipboard.clear() {
+                Ok(()) => debug!("Cleared the copied password from the clipboard"),
+                Err(e) => warn!("Failed to clear the clipboard: {}", e),
+            }
+        }
+    });
+}
+
 fn show_error_toast(ui_weak: &Weak<EntryWindow>, message: &str) {
+    show_toast(ui_weak, message, ToastKind::Error, SharedString::new(), Duration::from_secs(3));
+}
+
+/// Toast confirming that something was done, or deliberately not done
+fn show_info_toast(ui_weak: &Weak<EntryWindow>, message: &str) {
+    show_toast(ui_weak, message, ToastKind::Info, SharedString::new(), Duration::from_secs(3));
+}
+
+/// Toast with an Undo button restoring the entries that were just deleted
+fn show_undo_toast(ui_weak: &Weak<EntryWindow>, message: &str, record_ids: &[i32]) {
+    let undo_ids = record_ids.iter().map(i32::to_string).collect::<Vec<_>>().join(",");
+    show_toast(ui_weak, message, ToastKind::Info, SharedString::from(undo_ids), UNDO_TOAST_DURATION);
+}
+
+/// How a toast looks: failures stand out from confirmations
+#[derive(Clone, Copy, PartialEq)]
+enum ToastKind {
+    Info,
+    Error,
+}
+
+fn show_toast(ui_weak: &Weak<EntryWindow>, message: &str, kind: ToastKind, undo_id: SharedString, duration: Duration) {
     if let Some(ui) = ui_weak.upgrade() {
-        ui.global::<AppData>().set_toast_message(SharedString::from(message));
+        let message = SharedString::from(message);
+        ui.global::<AppData>().set_toast_message(message.clone());
+        ui.global::<AppData>().set_toast_error(kind == ToastKind::Error);
+        ui.global::<AppData>().set_toast_undo_id(undo_id.clone());
         ui.global::<AppData>().set_show_toast(true);
         
         let ui_weak_timer = ui.as_weak();
         slint::invoke_from_event_loop(move || {
             let timer = Timer::default();
-            timer.start(TimerMode::SingleShot, std::time::Duration::from_secs(3), move || {
+            timer.start(TimerMode::SingleShot, duration, move || {
                 if let Some(ui) = ui_weak_timer.upgrade() {
-                    ui.global::<AppData>().set_show_toast(false);
+                    // A newer toast keeps its own time
+                    let app_data = ui.global::<AppData>();
+                    if app_data.get_toast_message() == message && app_data.get_toast_undo_id() == undo_id {
+                        app_data.set_show_toast(false);
+                        app_data.set_toast_undo_id(SharedString::new());
+                    }
                 }
             });
             std::mem::forget(timer);
@@ -723,8 +1607,25 @@ fn refresh_table_data(ui_weak: &Weak<EntryWindow>, session: &Session) {
     if let Some(ui) = ui_weak.upgrade() {
         println!("Refreshing table data...");
         
-        // Get all records through the session
-        match session.get_all_records() {
+        // Get all records through the session, narrowed down by the search box
+        let query = ui.global::<AppData>().get_search_text();
+        let records = if query.trim().is_empty() {
+            session.get_all_records()
+        } else {
+            session.search_records(query.as_str())
+        };
+        let urls = session.get_record_urls().unwrap_or_else(|e| {
+            error!("Failed to fetch websites: {}", e);
+            HashMap::new()
+        });
+        let tags = session.get_record_tags().unwrap_or_else(|e| {
+            error!("Failed to fetch tags: {}", e);
+            HashMap::new()
+        });
+        // Rows stay ticked across refreshes while they are listed
+        let selected: HashSet<i32> = selected_ids(&ui).into_iter().collect();
+
+        match records {
             Ok(records) => {
                 println!("Retrieved {} records from database", records.len());
                 
@@ -732,22 +1633,19 @@ fn refresh_table_data(ui_weak: &Weak<EntryWindow>, session: &Session) {
                 let table_model = Rc::new(VecModel::default());
                 
                 // Add each record to the model
+                let mut selected_count = 0;
                 for record in records {
-                    let row = vec![
-                        StandardListViewItem::from(record.id.to_string().as_str()),
-                        StandardListViewItem::from(record.service.as_str()),
-                        StandardListViewItem::from(record.email.as_str()),
-                        StandardListViewItem::from(record.username.as_str()),
-                        StandardListViewItem::from("••••••••"), // Hide password in display
-                        StandardListViewItem::from(record.notes.as_str()),
-                    ];
-                    
-                    let row_model = Rc::new(VecModel::from(row));
+                    let record_urls = urls.get(&record.id).map(Vec::as_slice).unwrap_or_default();
+                    let record_tags = tags.get(&record.id).map(Vec::as_slice).unwrap_or_default();
+                    let is_selected = selected.contains(&record.id);
+                    selected_count += is_selected as i32;
+                    let row_model = Rc::new(VecModel::from(record_row(&record, record_urls, record_tags, is_selected)));
                     table_model.push(row_model.into());
                 }
                 
                 // Update the UI with the new model
                 ui.global::<AppData>().set_table_rows(ModelRc::from(table_model));
+                ui.global::<AppData>().set_selected_count(selected_count);
             }
             Err(e) => {
                 error!("Failed to fetch records: {}", e);
@@ -755,3 +1653,288 @@ fn refresh_table_data(ui_weak: &Weak<EntryWindow>, session: &Session) {
         }
     }
 }
+
+/// Start the browser extension bridge and the SSH agent
+#[cfg(unix)]
+fn setup_local_services(approvals: ApprovalQueue, session_state: SessionState) -> (Option<BridgeServer>, Option<AgentServer>) {
+    let bridge = match pandabox::bridge::server::start(Arc::clone(&session_state), approvals.clone()) {
+        Ok(server) => Some(server),
+        Err(e) => {
+            warn!("Browser bridge unavailable: {}", e);
+            None
+        }
+    };
+    let agent = match pandabox::ssh_agent::server::start(session_state, approvals) {
+        Ok(server) => Some(server),
+        Err(e) => {
+            warn!("SSH agent unavailable: {}", e);
+            None
+        }
+    };
+    (bridge, agent)
+}
+
+/// Route approval requests of the background services to the GUI prompt
+#[cfg(unix)]
+fn setup_approvals(ui: &EntryWindow) -> ApprovalQueue {
+    let ui_weak_for_notify = ui.as_weak();
+    let approvals = ApprovalQueue::new(move |request: Option<ApprovalRequest>| {
+        let ui_weak = ui_weak_for_notify.clone();
+        slint::invoke_from_event_loop(move || show_approval(&ui_weak, request)).ok();
+    });
+
+    let approvals_for_respond = approvals.clone();
+    ui.on_approval_respond(move |allowed: bool| approvals_for_respond.respond(allowed));
+    approvals
+}
+
+/// Serve the vault over the freedesktop Secret Service API and mirror D-Bus changes in the GUI
+#[cfg(target_os = "linux")]
+fn setup_secret_service(ui: &EntryWindow, manager: Arc<DatabaseManager>, session_state: SessionState) -> Option<SecretService> {
+    let ui_weak = ui.as_weak();
+    let session_state_for_events = Arc::clone(&session_state);
+    let events = move |event: VaultEvent| {
+        let ui_weak = ui_weak.clone();
+        let session_state = session_state_for_events.clone();
+        slint::invoke_from_event_loop(move || match event {
+            VaultEvent::RecordsChanged => {
+                if let Some(session) = &*session_state.lock().unwrap() {
+                    refresh_table_data(&ui_weak, session);
+                }
+            }
+            VaultEvent::Locked => {
+                if let Some(ui) = ui_weak.upgrade() {
+                    ui.global::<AppData>().set_table_rows(ModelRc::from(Rc::new(VecModel::<ModelRc<StandardListViewItem>>::default())));
+                    ui.set_current_page(Page::Authenticate);
+                }
+            }
+            VaultEvent::UnlockRequested => {
+                show_info_toast(&ui_weak, "An application is waiting for the vault to be unlocked");
+            }
+        })
+        .ok();
+    };
+
+    match SecretService::start(manager, session_state, events) {
+        Ok(service) => Some(service),
+        Err(e) => {
+            warn!("Secret Service provider unavailable: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(unix)]
+fn show_approval(ui_weak: &Weak<EntryWindow>, request: Option<ApprovalRequest>) {
+    if let Some(ui) = ui_weak.upgrade() {
+        match request {
+            Some(request) => {
+                ui.set_approval_title(SharedString::from(request.title));
+                ui.set_approval_message(SharedString::from(request.message));
+                ui.set_approval_subject(SharedString::from(request.subject));
+                ui.set_show_approval_prompt(true);
+            }
+            None => ui.set_show_approval_prompt(false),
+        }
+    }
+}
+
+fn record_row(record: &Record, urls: &[String], tags: &[String], selected: bool) -> Vec<StandardListViewItem> {
+    vec![
+        StandardListViewItem::from(if selected { SELECTED_MARK } else { "" }),
+        StandardListViewItem::from(record.id.to_string().as_str()),
+        StandardListViewItem::from(record.service.as_str()),
+        StandardListViewItem::from(record.email.as_str()),
+        StandardListViewItem::from(record.username.as_str()),
+        StandardListViewItem::from("••••••••"), // Hide password in display
+        StandardListViewItem::from(record.notes.as_str()),
+        StandardListViewItem::from(two_factor_label(record.two_factor)),
+        StandardListViewItem::from(urls_label(urls).as_str()),
+        StandardListViewItem::from(kind_label(record.kind(), record.confirm_use)),
+        StandardListViewItem::from(record.folder.as_str()),
+        StandardListViewItem::from(tags.join(", ").as_str()),
+    ]
+}
+
+/// Ids of the rows ticked for a bulk action
+fn selected_ids(ui: &EntryWindow) -> Vec<i32> {
+    ui.global::<AppData>()
+        .get_table_rows()
+        .iter()
+        .filter(|row| row.row_data(0).is_some_and(|cell| cell.text == SELECTED_MARK))
+        .filter_map(|row| row.row_data(1)?.text.parse().ok())
+        .collect()
+}
+
+/// Tick or untick the rows for which `select` returns `true` or `false`, `None` leaves a row as
+/// it is. Updates the number of ticked rows.
+fn set_selection(ui: &EntryWindow, mut select: impl FnMut(usize, bool) -> Option<bool>) {
+    let rows = ui.global::<AppData>().get_table_rows();
+    let mut selected_count = 0;
+    for (index, row) in rows.iter().enumerate() {
+        let was_selected = row.row_data(0).is_some_and(|cell| cell.text == SELECTED_MARK);
+        let is_selected = select(index, was_selected).unwrap_or(was_selected);
+        if is_selected != was_selected {
+            row.set_row_data(0, StandardListViewItem::from(if is_selected { SELECTED_MARK } else { "" }));
+        }
+        selected_count += is_selected as i32;
+    }
+    ui.global::<AppData>().set_selected_count(selected_count);
+}
+
+fn entries_label(count: usize) -> String {
+    if count == 1 { String::from("1 entry") } else { format!("{} entries", count) }
+}
+
+fn urls_label<S: AsRef<str>>(urls: &[S]) -> String {
+    urls.iter().map(AsRef::as_ref).collect::<Vec<_>>().join(", ")
+}
+
+fn two_factor_label(enabled: bool) -> &'static str {
+    if enabled { "✓" } else { "" }
+}
+
+// The form reads the kind and confirmation back from this label
+fn kind_label(kind: RecordKind, confirm_use: bool) -> &'static str {
+    match (kind, confirm_use) {
+        (RecordKind::Login, _) => "Login",
+        (RecordKind::SshKey, false) => "SSH key",
+        (RecordKind::SshKey, true) => "SSH key (confirm)",
+    }
+}
+
+fn security_report_handler(session_state: &SessionState, ui_weak: Weak<EntryWindow>) {
+    let Some(session) = session_state.lock().unwrap().clone() else {
+        return;
+    };
+
+    show_busy_uncancellable(&ui_weak, "Building the security report");
+
+    // Every password is decrypted, keep the window responsive meanwhile
+    std::thread::spawn(move || {
+        let max_age_days = session.settings().max_password_age_days;
+        let result = session.health_report(max_age_days);
+
+        slint::invoke_from_event_loop(move || {
+            hide_busy(&ui_weak);
+            match result {
+                Ok(report) => {
+                    info!("Vault health report found {} issues", report.issue_count());
+                    if let Some(ui) = ui_weak.upgrade() {
+                        let app_data = ui.global::<AppData>();
+                        app_data.set_health_reused(health_items(&report.reused));
+                        app_data.set_health_weak(health_items(&report.weak));
+                        app_data.set_health_old(health_items(&report.old));
+                        app_data.set_health_missing_two_factor(health_items(&report.missing_two_factor));
+                        app_data.set_health_max_age_days(max_age_days as i32);
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to build vault health report: {}", e);
+                    show_error_toast(&ui_weak, "Failed to build security report");
+                }
+            }
+        }).ok();
+    });
+}
+
+fn verify_vault_handler(session_state: &SessionState, ui_weak: Weak<EntryWindow>, cancel: CancelFlag) {
+    let Some(session) = session_state.lock().unwrap().clone() else {
+        return;
+    };
+
+    show_busy(&ui_weak, "Verifying the vault", &cancel);
+
+    // Every password is decrypted, keep the window responsive meanwhile
+    std::thread::spawn(move || {
+        let mut reported = 0;
+        let result = session.verify(|done, total| {
+            report_progress(&ui_weak, "Verified", &mut reported, done, total);
+            !cancel.load(Ordering::Relaxed)
+        });
+
+        slint::invoke_from_event_loop(move || {
+            hide_busy(&ui_weak);
+            let report = match result {
+                Ok(Some(report)) => report,
+                Ok(None) => {
+                    show_info_toast(&ui_weak, "Verification cancelled");
+                    return;
+                }
+                Err(e) => {
+                    error!("Failed to verify the vault: {}", e);
+                    show_error_toast(&ui_weak, &format!("Verification failed: {}", e));
+                    return;
+                }
+            };
+            info!("Vault verification found {} problems", report.issue_count());
+            if let Some(ui) = ui_weak.upgrade() {
+                let summary = if report.is_ok() {
+                    String::from("No problem found: the database is intact and every entry decrypts.")
+                } else {
+                    format!("{} problems found, the affected entries are listed below.", report.issue_count())
+                };
+                let vault: Vec<HealthIssue> = report
+                    .vault
+                    .iter()
+                    .map(|problem| HealthIssue { record_id: 0, service: String::from("Vault"), detail: problem.clone() })
+                    .collect();
+                let app_data = ui.global::<AppData>();
+                app_data.set_verify_summary(SharedString::from(summary));
+                app_data.set_verify_vault(health_items(&vault));
+                app_data.set_verify_corrupt(health_items(&report.corrupt));
+                app_data.set_verify_undecryptable(health_items(&report.undecryptable));
+                app_data.set_verify_orphaned(health_items(&report.orphaned));
+            }
+        }).ok();
+    });
+}
+
+fn refresh_trash(ui_weak: &Weak<EntryWindow>, session: &Session) {
+    let records = match session.trashed_records() {
+        Ok(records) => records,
+        Err(e) => {
+            error!("Failed to load the trash: {}", e);
+            return;
+        }
+    };
+    let Some(ui) = ui_weak.upgrade() else {
+        return;
+    };
+
+    let retention_days = session.settings().trash_retention().map(|retention| retention.as_secs() / SECONDS_PER_DAY);
+    let now = unix_now();
+    let items: Vec<TrashItem> = records
+        .iter()
+        .map(|record| {
+            let days = (now - record.deleted_at.unwrap_or(now)).max(0) as u64 / SECONDS_PER_DAY;
+            let deleted = match days {
+                0 => String::from("Deleted today"),
+                1 => String::from("Deleted yesterday"),
+                days => format!("Deleted {} days ago", days),
+            };
+            let detail = match retention_days {
+                Some(retention_days) => format!("{}, purged in {} days", deleted, retention_days.saturating_sub(days).max(1)),
+                None => deleted,
+            };
+            TrashItem {
+                id: SharedString::from(record.id.to_string()),
+                service: SharedString::from(record.service.as_str()),
+                detail: SharedString::from(detail),
+            }
+        })
+        .collect();
+    ui.global::<AppData>().set_trash_items(ModelRc::new(VecModel::from(items)));
+}
+
+fn health_items(issues: &[HealthIssue]) -> ModelRc<HealthItem> {
+    let items: Vec<HealthItem> = issues
+        .iter()
+        .map(|issue| HealthItem {
+            id: SharedString::from(issue.record_id.to_string()),
+            service: SharedString::from(issue.service.as_str()),
+            detail: SharedString::from(issue.detail.as_str()),
+        })
+        .collect();
+    ModelRc::new(VecModel::from(items))
+}
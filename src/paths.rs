@@ -0,0 +1,139 @@
+// Where Pandabox keeps its files. Follows the XDG base directory specification, the vault
+// lives in the data directory unless another file is picked with `--vault` or `PANDABOX_VAULT`.
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use log::info;
+
+/// Environment variable naming the vault file to open
+pub const VAULT_ENV: &str = "PANDABOX_VAULT";
+
+const APP_DIR: &str = "pandabox";
+const VAULT_FILE: &str = "pandabox.db";
+// Directory in the home folder used by releases before the XDG layout
+const LEGACY_DIR: &str = "Pandabox";
+// SQLite keeps pages of a WAL database next to the file, they have to move with it. The vault
+// itself goes last so an interrupted migration is retried on the next start.
+const SQLITE_SUFFIXES: [&str; 3] = ["-wal", "-shm", ""];
+
+/// What the paths are worked out from: the home directory and the environment variables that
+/// move them. Read from the process once with `Environment::current`, given explicitly elsewhere.
+#[derive(Debug, Clone, Default)]
+pub struct Environment {
+    pub home: PathBuf,
+    pub xdg_data_home: Option<OsString>,
+    pub xdg_config_home: Option<OsString>,
+    /// `PANDABOX_VAULT`
+    pub vault: Option<OsString>,
+}
+
+impl Environment {
+    pub fn current() -> Self {
+        Environment {
+            home: env::var_os("HOME")
+                .or_else(|| env::var_os("USERPROFILE"))
+                .map(PathBuf::from)
+                .unwrap_or_else(|| PathBuf::from(".")),
+            xdg_data_home: env::var_os("XDG_DATA_HOME"),
+            xdg_config_home: env::var_os("XDG_CONFIG_HOME"),
+            vault: env::var_os(VAULT_ENV),
+        }
+    }
+
+    /// `$XDG_DATA_HOME/pandabox`, holding the vault
+    pub fn data_dir(&self) -> PathBuf {
+        self.xdg_dir(self.xdg_data_home.as_ref(), ".local/share").join(APP_DIR)
+    }
+
+    /// `$XDG_CONFIG_HOME/pandabox`, holding the application settings
+    pub fn config_dir(&self) -> PathBuf {
+        self.xdg_dir(self.xdg_config_home.as_ref(), ".config").join(APP_DIR)
+    }
+
+    /// The vault opened when none is given
+    pub fn default_vault_path(&self) -> PathBuf {
+        self.data_dir().join(VAULT_FILE)
+    }
+
+    /// The vault picked by the user, the `--vault` argument taking precedence over `PANDABOX_VAULT`
+    pub fn vault_override(&self, argument: Option<PathBuf>) -> Option<PathBuf> {
+        argument.or_else(|| self.vault.clone().filter(|path| !path.is_empty()).map(PathBuf::from))
+    }
+
+    /// Move a vault left in `~/Pandabox` by an older release to the data directory. Nothing is
+    /// touched once a vault exists in the data directory. Returns whether a vault was moved.
+    pub fn migrate_legacy_vault(&self) -> io::Result<bool> {
+        let legacy_dir = self.home.join(LEGACY_DIR);
+        let legacy_vault = legacy_dir.join(VAULT_FILE);
+        let vault = self.default_vault_path();
+        if vault.exists() || !legacy_vault.exists() {
+            return Ok(false);
+        }
+
+        fs::create_dir_all(self.data_dir())?;
+        for suffix in SQLITE_SUFFIXES {
+            let from = with_suffix(&legacy_vault, suffix);
+            if from.exists() {
+                move_file(&from, &with_suffix(&vault, suffix))?;
+            }
+        }
+        // Only goes away when nothing else was stored next to the vault
+        fs::remove_dir(&legacy_dir).ok();
+
+        info!("Moved the vault from {} to {}", legacy_vault.display(), vault.display());
+        Ok(true)
+    }
+
+    /// `variable` when set to an absolute path, `~/fallback` otherwise
+    fn xdg_dir(&self, variable: Option<&OsString>, fallback: &str) -> PathBuf {
+        variable
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .unwrap_or_else(|| self.home.join(fallback))
+    }
+}
+
+/// `$XDG_DATA_HOME/pandabox`, holding the vault
+pub fn data_dir() -> PathBuf {
+    Environment::current().data_dir()
+}
+
+/// `$XDG_CONFIG_HOME/pandabox`, holding the application settings
+pub fn config_dir() -> PathBuf {
+    Environment::current().config_dir()
+}
+
+/// The vault opened when none is given
+pub fn default_vault_path() -> PathBuf {
+    Environment::current().default_vault_path()
+}
+
+/// The vault picked by the user, see `Environment::vault_override`
+pub fn vault_override(argument: Option<PathBuf>) -> Option<PathBuf> {
+    Environment::current().vault_override(argument)
+}
+
+/// Move a vault left by an older release, see `Environment::migrate_legacy_vault`
+pub fn migrate_legacy_vault() -> io::Result<bool> {
+    Environment::current().migrate_legacy_vault()
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+// A rename fails across file systems, copy and remove instead
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if fs::rename(from, to).is_err() {
+        if let Err(e) = fs::copy(from, to) {
+            fs::remove_file(to).ok();
+            return Err(e);
+        }
+        fs::remove_file(from)?;
+    }
+    Ok(())
+}
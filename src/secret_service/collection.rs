@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use log::info;
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::{OwnedObjectPath, OwnedValue};
+use zbus::{interface, ObjectServer};
+use zeroize::Zeroizing;
+use crate::database::models::RecordKind;
+use crate::session::session::Entry;
+use super::{item_path, Backend, Secret, SecretError, VaultEvent, COLLECTION_LABEL};
+
+const LABEL_PROPERTY: &str = "org.freedesktop.Secret.Item.Label";
+const ATTRIBUTES_PROPERTY: &str = "org.freedesktop.Secret.Item.Attributes";
+
+/// `org.freedesktop.Secret.Collection` holding every record of the vault
+pub struct Collection {
+    backend: Backend,
+}
+
+impl Collection {
+    pub fn new(backend: Backend) -> Self {
+        Collection { backend }
+    }
+}
+
+#[interface(name = "org.freedesktop.Secret.Collection")]
+impl Collection {
+    /// The vault itself cannot be deleted over D-Bus
+    async fn delete(&self) -> Result<OwnedObjectPath, SecretError> {
+        Err(SecretError::not_supported("The Pandabox collection cannot be deleted"))
+    }
+
+    async fn search_items(
+        &self,
+        attributes: HashMap<String, String>,
+        #[zbus(object_server)] server: &ObjectServer,
+    ) -> Result<Vec<OwnedObjectPath>, SecretError> {
+        self.backend.sync_items(server).await?;
+        Ok(self.backend.search(&attributes)?.into_iter().map(item_path).collect())
+    }
+
+    /// Store a secret as a new record. The label becomes the service name and the
+    /// `username`/`email` attributes fill in the matching record fields.
+    #[zbus(out_args("item", "prompt"))]
+    async fn create_item(
+        &self,
+        properties: HashMap<String, OwnedValue>,
+        secret: Secret,
+        replace: bool,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+        #[zbus(object_server)] server: &ObjectServer,
+    ) -> Result<(OwnedObjectPath, OwnedObjectPath), SecretError> {
+        let (session_path, _, value, _) = secret;
+        self.backend.check_session(&session_path)?;
+
+        let label = properties
+            .get(LABEL_PROPERTY)
+            .and_then(|label| String::try_from(label.try_clone().ok()?).ok())
+            .unwrap_or_default();
+        let attributes: HashMap<String, String> = properties
+            .get(ATTRIBUTES_PROPERTY)
+            .and_then(|attributes| HashMap::try_from(attributes.try_clone().ok()?).ok())
+            .unwrap_or_default();
+        let password = String::from_utf8(value)
+            .map(Zeroizing::new)
+            .map_err(|_| SecretError::not_supported("Secrets must be UTF-8 text"))?;
+
+        let username = ["username", "user", "account"]
+            .iter()
+            .find_map(|name| attributes.get(*name))
+            .cloned()
+            .unwrap_or_default();
+        let email = attributes.get("email").cloned().unwrap_or_default();
+        let service = if label.is_empty() {
+            attributes.get("service").cloned().unwrap_or_else(|| String::from(COLLECTION_LABEL))
+        } else {
+            label
+        };
+
+        let existing = if replace && !attributes.is_empty() {
+            self.backend.search(&attributes)?.first().copied()
+        } else {
+            None
+        };
+
+        let record_id = {
+            let state = self.backend.session_state.lock().unwrap();
+            let vault = state
+                .as_ref()
+                .ok_or_else(|| SecretError::IsLocked(String::from("The Pandabox vault is locked")))?;
+
+            match existing {
+                Some(record_id) => {
+                    let (record, _) = self.backend.record(record_id)?;
+                    let mut entry = vault.decrypt_entry(&record).map_err(SecretError::failed)?;
+                    entry.service = service;
+                    entry.password = password;
+                    let updated = vault.update_entry(record_id, &entry);
+                    if !updated {
+                        return Err(SecretError::failed("Failed to update the record"));
+                    }
+                    record_id
+                }
+                None => {
+                    let entry = Entry {
+                        service,
+                        email,
+                        username,
+                        password,
+                        kind: RecordKind::Login,
+                        ..Entry::default()
+                    };
+                    vault.insert_entry(&entry).map_err(SecretError::failed)?.id
+                }
+            }
+        };
+
+        let stored: Vec<(&str, &str)> = attributes.iter().map(|(name, value)| (name.as_str(), value.as_str())).collect();
+        self.backend
+            .manager
+            .set_secret_attributes(record_id, &stored)
+            .map_err(SecretError::failed)?;
+        self.backend.sync_items(server).await?;
+
+        let item = item_path(record_id);
+        if existing.is_some() {
+            Collection::item_changed(&emitter, item.clone()).await?;
+        } else {
+            info!("Created record {} over the Secret Service", record_id);
+            Collection::item_created(&emitter, item.clone()).await?;
+        }
+        (self.backend.events)(VaultEvent::RecordsChanged);
+        Ok((item, OwnedObjectPath::try_from("/").unwrap()))
+    }
+
+    #[zbus(property)]
+    async fn items(&self, #[zbus(object_server)] server: &ObjectServer) -> zbus::fdo::Result<Vec<OwnedObjectPath>> {
+        Ok(self.backend.sync_items(server).await?)
+    }
+
+    #[zbus(property)]
+    async fn label(&self) -> String {
+        String::from(COLLECTION_LABEL)
+    }
+
+    #[zbus(property)]
+    async fn locked(&self) -> bool {
+        self.backend.is_locked()
+    }
+
+    #[zbus(property)]
+    async fn created(&self) -> u64 {
+        0
+    }
+
+    #[zbus(property)]
+    async fn modified(&self) -> u64 {
+        0
+    }
+
+    #[zbus(signal)]
+    pub async fn item_created(emitter: &SignalEmitter<'_>, item: OwnedObjectPath) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    pub async fn item_deleted(emitter: &SignalEmitter<'_>, item: OwnedObjectPath) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    pub async fn item_changed(emitter: &SignalEmitter<'_>, item: OwnedObjectPath) -> zbus::Result<()>;
+}
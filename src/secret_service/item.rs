@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::{ObjectPath, OwnedObjectPath};
+use zbus::{fdo, interface, Connection, ObjectServer};
+use zeroize::Zeroizing;
+use crate::session::session::Entry;
+use super::collection::Collection;
+use super::{item_path, Backend, Secret, SecretError, VaultEvent, COLLECTION_PATH};
+
+/// `org.freedesktop.Secret.Item` for a single vault record
+pub struct Item {
+    backend: Backend,
+    record_id: i32,
+}
+
+impl Item {
+    pub fn new(backend: Backend, record_id: i32) -> Self {
+        Item { backend, record_id }
+    }
+
+    /// Save the record with `change` applied, keeping every other field as it is
+    fn update(&self, change: impl FnOnce(&mut Entry)) -> Result<(), SecretError> {
+        let (record, _) = self.backend.record(self.record_id)?;
+        let state = self.backend.session_state.lock().unwrap();
+        let vault = state
+            .as_ref()
+            .ok_or_else(|| SecretError::IsLocked(String::from("The Pandabox vault is locked")))?;
+
+        let mut entry = vault.decrypt_entry(&record).map_err(SecretError::failed)?;
+        change(&mut entry);
+
+        let updated = vault.update_entry(record.id, &entry);
+        if updated {
+            (self.backend.events)(VaultEvent::RecordsChanged);
+            Ok(())
+        } else {
+            Err(SecretError::failed("Failed to update the record"))
+        }
+    }
+
+    async fn emit_changed(&self, connection: &Connection) -> zbus::Result<()> {
+        let emitter = SignalEmitter::new(connection, COLLECTION_PATH)?;
+        Collection::item_changed(&emitter, item_path(self.record_id)).await
+    }
+}
+
+#[interface(name = "org.freedesktop.Secret.Item")]
+impl Item {
+    async fn delete(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(object_server)] server: &ObjectServer,
+    ) -> Result<OwnedObjectPath, SecretError> {
+        {
+            let state = self.backend.session_state.lock().unwrap();
+            let vault = state
+                .as_ref()
+                .ok_or_else(|| SecretError::IsLocked(String::from("The Pandabox vault is locked")))?;
+            if !vault.delete_entry(self.record_id) {
+                return Err(SecretError::failed("Failed to delete the record"));
+            }
+        }
+
+        let path = item_path(self.record_id);
+        self.backend.exported.lock().unwrap().remove(&self.record_id);
+        server.remove::<Item, _>(&path).await?;
+
+        let emitter = SignalEmitter::new(connection, COLLECTION_PATH)?;
+        Collection::item_deleted(&emitter, path).await?;
+        (self.backend.events)(VaultEvent::RecordsChanged);
+        Ok(OwnedObjectPath::try_from("/").unwrap())
+    }
+
+    async fn get_secret(&self, session: ObjectPath<'_>) -> Result<Secret, SecretError> {
+        self.backend.secret(self.record_id, &session)
+    }
+
+    async fn set_secret(
+        &self,
+        secret: Secret,
+        #[zbus(connection)] connection: &Connection,
+    ) -> Result<(), SecretError> {
+        let (session, _, value, _) = secret;
+        self.backend.check_session(&session)?;
+        let password = String::from_utf8(value).map_err(|_| SecretError::not_supported("Secrets must be UTF-8 text"))?;
+
+        self.update(|entry| entry.password = Zeroizing::new(password))?;
+        self.emit_changed(connection).await?;
+        Ok(())
+    }
+
+    #[zbus(property)]
+    async fn locked(&self) -> bool {
+        self.backend.is_locked()
+    }
+
+    #[zbus(property)]
+    async fn attributes(&self) -> fdo::Result<HashMap<String, String>> {
+        let (_, attributes) = self.backend.record(self.record_id)?;
+        Ok(attributes)
+    }
+
+    #[zbus(property)]
+    async fn set_attributes(&mut self, attributes: HashMap<String, String>) -> fdo::Result<()> {
+        if self.backend.is_locked() {
+            return Err(fdo::Error::AccessDenied(String::from("The Pandabox vault is locked")));
+        }
+
+        let stored: Vec<(&str, &str)> = attributes.iter().map(|(name, value)| (name.as_str(), value.as_str())).collect();
+        self.backend
+            .manager
+            .set_secret_attributes(self.record_id, &stored)
+            .map_err(|e| fdo::Error::Failed(e.to_string()))?;
+        Ok(())
+    }
+
+    #[zbus(property)]
+    async fn label(&self) -> fdo::Result<String> {
+        let (record, _) = self.backend.record(self.record_id)?;
+        Ok(record.service)
+    }
+
+    #[zbus(property)]
+    async fn set_label(&mut self, label: String) -> fdo::Result<()> {
+        Ok(self.update(|entry| entry.service = label)?)
+    }
+
+    #[zbus(property)]
+    async fn created(&self) -> fdo::Result<u64> {
+        self.modified().await
+    }
+
+    /// Records only track when their password last changed
+    #[zbus(property)]
+    async fn modified(&self) -> fdo::Result<u64> {
+        let (record, _) = self.backend.record(self.record_id)?;
+        Ok(record.password_changed_at.max(0) as u64)
+    }
+}
@@ -0,0 +1,293 @@
+// Freedesktop Secret Service provider (org.freedesktop.secrets) exposing the vault as a single
+// collection. Items mirror the vault records and secrets are only served while the Pandabox
+// session is unlocked; locking the collection over D-Bus locks the application.
+mod collection;
+mod item;
+mod service;
+
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use log::{error, info};
+use zbus::blocking::connection;
+use zbus::fdo;
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, Value};
+use crate::database::manager::DatabaseManager;
+use crate::database::models::Record;
+use crate::session::session::SessionState;
+use self::collection::Collection;
+use self::item::Item;
+use self::service::{Prompt, Service};
+
+pub const SERVICE_NAME: &str = "org.freedesktop.secrets";
+pub const SERVICE_PATH: &str = "/org/freedesktop/secrets";
+pub const COLLECTION_PATH: &str = "/org/freedesktop/secrets/collection/pandabox";
+pub const COLLECTION_LABEL: &str = "Pandabox";
+
+/// D-Bus address to serve on instead of the session bus, e.g. a private `dbus-daemon` for testing
+pub const BUS_ADDRESS_VAR: &str = "PANDABOX_SECRET_SERVICE_BUS";
+
+const PLAIN_ALGORITHM: &str = "plain";
+const SECRET_CONTENT_TYPE: &str = "text/plain; charset=utf8";
+
+/// `(session, parameters, value, content_type)` as defined by the specification
+pub type Secret = (OwnedObjectPath, Vec<u8>, Vec<u8>, String);
+
+type Attributes = HashMap<String, String>;
+
+/// A prompt waiting for the vault to be unlocked, with the objects it unlocks
+type PendingPrompt = (OwnedObjectPath, Vec<OwnedObjectPath>);
+
+/// Changes made over D-Bus that the GUI has to reflect.
+pub enum VaultEvent {
+    /// Items were created, changed or deleted
+    RecordsChanged,
+    /// A client locked the collection, the session has been closed
+    Locked,
+    /// A client is waiting for the user to unlock the vault
+    UnlockRequested,
+}
+
+#[derive(Debug, zbus::DBusError)]
+#[zbus(prefix = "org.freedesktop.Secret.Error")]
+pub enum SecretError {
+    #[zbus(error)]
+    ZBus(zbus::Error),
+    IsLocked(String),
+    NoSession(String),
+    NoSuchObject(String),
+}
+
+impl SecretError {
+    fn failed(message: impl ToString) -> Self {
+        SecretError::ZBus(zbus::Error::FDO(Box::new(fdo::Error::Failed(message.to_string()))))
+    }
+
+    fn not_supported(message: impl ToString) -> Self {
+        SecretError::ZBus(zbus::Error::FDO(Box::new(fdo::Error::NotSupported(message.to_string()))))
+    }
+}
+
+impl From<fdo::Error> for SecretError {
+    fn from(e: fdo::Error) -> Self {
+        SecretError::ZBus(zbus::Error::FDO(Box::new(e)))
+    }
+}
+
+// Property accessors have to report `fdo::Error`
+impl From<SecretError> for fdo::Error {
+    fn from(e: SecretError) -> Self {
+        match e {
+            SecretError::ZBus(zbus::Error::FDO(e)) => *e,
+            SecretError::IsLocked(message) => fdo::Error::AccessDenied(message),
+            e => fdo::Error::Failed(e.to_string()),
+        }
+    }
+}
+
+/// State shared by every exported object.
+#[derive(Clone)]
+struct Backend {
+    manager: Arc<DatabaseManager>,
+    session_state: SessionState,
+    events: Arc<dyn Fn(VaultEvent) + Send + Sync>,
+    // Paths of the open Secret Service sessions
+    sessions: Arc<Mutex<HashSet<OwnedObjectPath>>>,
+    // Record ids with an item currently exported
+    exported: Arc<Mutex<HashSet<i32>>>,
+    pending_prompts: Arc<Mutex<Vec<PendingPrompt>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl Backend {
+    fn is_locked(&self) -> bool {
+        self.session_state.lock().unwrap().is_none()
+    }
+
+    fn lock_vault(&self) {
+        if self.session_state.lock().unwrap().take().is_some() {
+            info!("Vault locked over D-Bus");
+            (self.events)(VaultEvent::Locked);
+        }
+    }
+
+    fn new_path(&self, kind: &str) -> OwnedObjectPath {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        OwnedObjectPath::try_from(format!("{}/{}/s{}", SERVICE_PATH, kind, id)).unwrap()
+    }
+
+    fn check_session(&self, session: &ObjectPath<'_>) -> Result<(), SecretError> {
+        if self.sessions.lock().unwrap().iter().any(|open| open.as_ref() == *session) {
+            Ok(())
+        } else {
+            Err(SecretError::NoSession(format!("No session at {}", session)))
+        }
+    }
+
+    /// Load every record together with its lookup attributes
+    fn records(&self) -> Result<Vec<(Record, Attributes)>, SecretError> {
+        let records = self.manager.get_all_records().map_err(SecretError::failed)?;
+        let mut stored: HashMap<i32, Vec<(String, String)>> = HashMap::new();
+        for (record_id, name, value) in self.manager.get_all_secret_attributes().map_err(SecretError::failed)? {
+            stored.entry(record_id).or_default().push((name, value));
+        }
+
+        Ok(records
+            .into_iter()
+            .map(|record| {
+                let attributes = attributes_for(&record, stored.remove(&record.id).unwrap_or_default());
+                (record, attributes)
+            })
+            .collect())
+    }
+
+    fn record(&self, record_id: i32) -> Result<(Record, Attributes), SecretError> {
+        self.records()?
+            .into_iter()
+            .find(|(record, _)| record.id == record_id)
+            .ok_or_else(|| SecretError::NoSuchObject(format!("No item {}", record_id)))
+    }
+
+    /// Ids of the records whose attributes contain every requested attribute
+    fn search(&self, query: &Attributes) -> Result<Vec<i32>, SecretError> {
+        Ok(self
+            .records()?
+            .into_iter()
+            .filter(|(_, attributes)| {
+                query.iter().all(|(name, value)| attributes.get(name) == Some(value))
+            })
+            .map(|(record, _)| record.id)
+            .collect())
+    }
+
+    /// Export an item for every record and drop the items of deleted records.
+    /// Clients only learn item paths through the calls that run this first.
+    async fn sync_items(&self, server: &zbus::ObjectServer) -> Result<Vec<OwnedObjectPath>, SecretError> {
+        let ids: HashSet<i32> = self.records()?.into_iter().map(|(record, _)| record.id).collect();
+        let mut exported = self.exported.lock().unwrap().clone();
+
+        for removed in exported.difference(&ids).copied().collect::<Vec<_>>() {
+            server.remove::<Item, _>(item_path(removed)).await?;
+            exported.remove(&removed);
+        }
+        for added in ids.difference(&exported).copied().collect::<Vec<_>>() {
+            server.at(item_path(added), Item::new(self.clone(), added)).await?;
+            exported.insert(added);
+        }
+
+        let mut paths: Vec<i32> = exported.iter().copied().collect();
+        paths.sort_unstable();
+        *self.exported.lock().unwrap() = exported;
+        Ok(paths.into_iter().map(item_path).collect())
+    }
+
+    /// Decrypt the secret of a record for an open session
+    fn secret(&self, record_id: i32, session: &ObjectPath<'_>) -> Result<Secret, SecretError> {
+        self.check_session(session)?;
+        let (record, _) = self.record(record_id)?;
+        match &*self.session_state.lock().unwrap() {
+            Some(vault) => {
+                let password = vault.decrypt_password(&record).map_err(SecretError::failed)?;
+                Ok((
+                    OwnedObjectPath::from(session.to_owned()),
+                    Vec::new(),
+                    password.into_bytes(),
+                    String::from(SECRET_CONTENT_TYPE),
+                ))
+            }
+            None => Err(SecretError::IsLocked(String::from("The Pandabox vault is locked"))),
+        }
+    }
+}
+
+/// Lookup attributes of a record: the stored attributes on top of ones derived from its fields
+fn attributes_for(record: &Record, stored: Vec<(String, String)>) -> Attributes {
+    let mut attributes = HashMap::from([
+        (String::from("service"), record.service.clone()),
+        (String::from("username"), record.username.clone()),
+        (String::from("email"), record.email.clone()),
+    ]);
+    attributes.extend(stored);
+    attributes
+}
+
+fn item_path(record_id: i32) -> OwnedObjectPath {
+    OwnedObjectPath::try_from(format!("{}/{}", COLLECTION_PATH, record_id)).unwrap()
+}
+
+fn record_id_from_path(path: &ObjectPath<'_>) -> Option<i32> {
+    path.as_str()
+        .strip_prefix(COLLECTION_PATH)?
+        .strip_prefix('/')?
+        .parse()
+        .ok()
+}
+
+/// Handle to the running provider, used to tie the collection lock state to the session.
+#[derive(Clone)]
+pub struct SecretService {
+    connection: zbus::blocking::Connection,
+    backend: Backend,
+}
+
+impl SecretService {
+    /// Claim `org.freedesktop.secrets` on the session bus (or on the bus named by
+    /// `PANDABOX_SECRET_SERVICE_BUS`) and export the vault collection.
+    pub fn start<F>(manager: Arc<DatabaseManager>, session_state: SessionState, events: F) -> zbus::Result<Self>
+    where
+        F: Fn(VaultEvent) + Send + Sync + 'static,
+    {
+        let backend = Backend {
+            manager,
+            session_state,
+            events: Arc::new(events),
+            sessions: Arc::default(),
+            exported: Arc::default(),
+            pending_prompts: Arc::default(),
+            next_id: Arc::default(),
+        };
+
+        let builder = match env::var(BUS_ADDRESS_VAR) {
+            Ok(address) => connection::Builder::address(address.as_str())?,
+            Err(_) => connection::Builder::session()?,
+        };
+        let connection = builder
+            .serve_at(SERVICE_PATH, Service::new(backend.clone()))?
+            .serve_at(COLLECTION_PATH, Collection::new(backend.clone()))?
+            .name(SERVICE_NAME)?
+            .build()?;
+
+        info!("Secret Service provider registered as {}", SERVICE_NAME);
+        Ok(SecretService { connection, backend })
+    }
+
+    /// Called once the user unlocked the vault, completes the prompts waiting for it
+    pub fn vault_unlocked(&self) {
+        self.emit_locked_changed(false);
+
+        let prompts: Vec<_> = self.backend.pending_prompts.lock().unwrap().drain(..).collect();
+        for (path, objects) in prompts {
+            let result = self
+                .connection
+                .emit_signal(None::<&str>, &path, "org.freedesktop.Secret.Prompt", "Completed", &(false, Value::from(objects)))
+                .and_then(|_| self.connection.object_server().remove::<Prompt, _>(&path).map(|_| ()));
+            if let Err(e) = result {
+                error!("Failed to complete Secret Service prompt: {}", e);
+            }
+        }
+    }
+
+    fn emit_locked_changed(&self, locked: bool) {
+        let changed = HashMap::from([("Locked", Value::from(locked))]);
+        if let Err(e) = self.connection.emit_signal(
+            None::<&str>,
+            COLLECTION_PATH,
+            "org.freedesktop.DBus.Properties",
+            "PropertiesChanged",
+            &("org.freedesktop.Secret.Collection", changed, Vec::<&str>::new()),
+        ) {
+            error!("Failed to signal collection lock state: {}", e);
+        }
+    }
+}
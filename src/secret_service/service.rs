@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value};
+use zbus::{fdo, interface, ObjectServer};
+use super::{
+    record_id_from_path, Backend, Secret, SecretError, VaultEvent, COLLECTION_PATH, PLAIN_ALGORITHM,
+};
+
+fn no_object() -> OwnedObjectPath {
+    OwnedObjectPath::try_from("/").unwrap()
+}
+
+fn collection_path() -> OwnedObjectPath {
+    OwnedObjectPath::try_from(COLLECTION_PATH).unwrap()
+}
+
+/// `org.freedesktop.Secret.Service` at `/org/freedesktop/secrets`
+pub struct Service {
+    backend: Backend,
+}
+
+impl Service {
+    pub fn new(backend: Backend) -> Self {
+        Service { backend }
+    }
+}
+
+#[interface(name = "org.freedesktop.Secret.Service")]
+impl Service {
+    /// Only the `plain` algorithm is offered, secrets never leave the local bus
+    #[zbus(out_args("output", "result"))]
+    async fn open_session(
+        &self,
+        algorithm: &str,
+        _input: OwnedValue,
+        #[zbus(object_server)] server: &ObjectServer,
+    ) -> fdo::Result<(Value<'static>, OwnedObjectPath)> {
+        if algorithm != PLAIN_ALGORITHM {
+            return Err(fdo::Error::NotSupported(format!("Algorithm {} is not supported", algorithm)));
+        }
+
+        let path = self.backend.new_path("session");
+        server.at(&path, SecretSession::new(self.backend.clone(), path.clone())).await?;
+        self.backend.sessions.lock().unwrap().insert(path.clone());
+        Ok((Value::from(""), path))
+    }
+
+    /// The vault is the only collection, so creating one hands out the existing collection
+    #[zbus(out_args("collection", "prompt"))]
+    async fn create_collection(
+        &self,
+        _properties: HashMap<String, OwnedValue>,
+        _alias: &str,
+    ) -> (OwnedObjectPath, OwnedObjectPath) {
+        (collection_path(), no_object())
+    }
+
+    #[zbus(out_args("unlocked", "locked"))]
+    async fn search_items(
+        &self,
+        attributes: HashMap<String, String>,
+        #[zbus(object_server)] server: &ObjectServer,
+    ) -> Result<(Vec<OwnedObjectPath>, Vec<OwnedObjectPath>), SecretError> {
+        self.backend.sync_items(server).await?;
+        let items: Vec<OwnedObjectPath> = self.backend.search(&attributes)?.into_iter().map(super::item_path).collect();
+        if self.backend.is_locked() {
+            Ok((Vec::new(), items))
+        } else {
+            Ok((items, Vec::new()))
+        }
+    }
+
+    /// Unlocking needs the master password, so while locked a prompt is returned that
+    /// completes once the user unlocks Pandabox
+    #[zbus(out_args("unlocked", "prompt"))]
+    async fn unlock(
+        &self,
+        objects: Vec<OwnedObjectPath>,
+        #[zbus(object_server)] server: &ObjectServer,
+    ) -> fdo::Result<(Vec<OwnedObjectPath>, OwnedObjectPath)> {
+        if !self.backend.is_locked() {
+            return Ok((objects, no_object()));
+        }
+
+        let path = self.backend.new_path("prompt");
+        server.at(&path, Prompt::new(self.backend.clone(), path.clone(), objects)).await?;
+        Ok((Vec::new(), path))
+    }
+
+    /// Locking any object locks the whole vault and ends the Pandabox session
+    #[zbus(out_args("locked", "Prompt"))]
+    async fn lock(
+        &self,
+        objects: Vec<OwnedObjectPath>,
+        #[zbus(object_server)] server: &ObjectServer,
+    ) -> fdo::Result<(Vec<OwnedObjectPath>, OwnedObjectPath)> {
+        self.backend.lock_vault();
+        let collection = server.interface::<_, super::collection::Collection>(COLLECTION_PATH).await?;
+        collection.get().await.locked_changed(collection.signal_emitter()).await?;
+        Ok((objects, no_object()))
+    }
+
+    async fn get_secrets(
+        &self,
+        items: Vec<OwnedObjectPath>,
+        session: ObjectPath<'_>,
+    ) -> Result<HashMap<OwnedObjectPath, Secret>, SecretError> {
+        self.backend.check_session(&session)?;
+
+        let mut secrets = HashMap::new();
+        for item in items {
+            let Some(record_id) = record_id_from_path(&item) else { continue };
+            match self.backend.secret(record_id, &session) {
+                Ok(secret) => {
+                    secrets.insert(item, secret);
+                }
+                // Locked items are left out of the result
+                Err(SecretError::IsLocked(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(secrets)
+    }
+
+    async fn read_alias(&self, name: &str) -> OwnedObjectPath {
+        if name == "default" {
+            collection_path()
+        } else {
+            no_object()
+        }
+    }
+
+    async fn set_alias(&self, name: &str, collection: ObjectPath<'_>) -> Result<(), SecretError> {
+        if collection.as_str() == COLLECTION_PATH || collection.as_str() == "/" {
+            Ok(())
+        } else {
+            Err(SecretError::NoSuchObject(format!("Cannot alias {} to {}", name, collection)))
+        }
+    }
+
+    #[zbus(property)]
+    async fn collections(&self) -> Vec<OwnedObjectPath> {
+        vec![collection_path()]
+    }
+}
+
+/// `org.freedesktop.Secret.Session`, one per client that called `OpenSession`
+pub struct SecretSession {
+    backend: Backend,
+    path: OwnedObjectPath,
+}
+
+impl SecretSession {
+    fn new(backend: Backend, path: OwnedObjectPath) -> Self {
+        SecretSession { backend, path }
+    }
+}
+
+#[interface(name = "org.freedesktop.Secret.Session")]
+impl SecretSession {
+    async fn close(&self, #[zbus(object_server)] server: &ObjectServer) -> fdo::Result<()> {
+        self.backend.sessions.lock().unwrap().remove(&self.path);
+        server.remove::<SecretSession, _>(&self.path).await?;
+        Ok(())
+    }
+}
+
+/// `org.freedesktop.Secret.Prompt` waiting for the user to unlock Pandabox
+pub struct Prompt {
+    backend: Backend,
+    path: OwnedObjectPath,
+    objects: Vec<OwnedObjectPath>,
+}
+
+impl Prompt {
+    fn new(backend: Backend, path: OwnedObjectPath, objects: Vec<OwnedObjectPath>) -> Self {
+        Prompt { backend, path, objects }
+    }
+}
+
+#[interface(name = "org.freedesktop.Secret.Prompt")]
+impl Prompt {
+    async fn prompt(
+        &self,
+        _window_id: &str,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+        #[zbus(object_server)] server: &ObjectServer,
+    ) -> fdo::Result<()> {
+        if !self.backend.is_locked() {
+            Prompt::completed(&emitter, false, Value::from(self.objects.clone())).await?;
+            server.remove::<Prompt, _>(&self.path).await?;
+            return Ok(());
+        }
+
+        self.backend
+            .pending_prompts
+            .lock()
+            .unwrap()
+            .push((self.path.clone(), self.objects.clone()));
+        (self.backend.events)(VaultEvent::UnlockRequested);
+        Ok(())
+    }
+
+    async fn dismiss(
+        &self,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+        #[zbus(object_server)] server: &ObjectServer,
+    ) -> fdo::Result<()> {
+        self.backend.pending_prompts.lock().unwrap().retain(|(path, _)| *path != self.path);
+        Prompt::completed(&emitter, true, Value::from(Vec::<OwnedObjectPath>::new())).await?;
+        server.remove::<Prompt, _>(&self.path).await?;
+        Ok(())
+    }
+
+    #[zbus(signal)]
+    async fn completed(emitter: &SignalEmitter<'_>, dismissed: bool, result: Value<'_>) -> zbus::Result<()>;
+}
@@ -0,0 +1,139 @@
+// Plain text CSV export of the vault and the import of such files.
+use std::error::Error;
+use std::io::{Read, Write};
+use csv::{Reader, Writer};
+use log::error;
+use serde::{Deserialize, Serialize};
+use zeroize::{Zeroize, Zeroizing};
+use crate::database::models::{Record, RecordKind};
+use crate::error::PandaboxError;
+use crate::session::session::{Entry, ImportedEntry, Session};
+use crate::session::url_match::split_urls;
+
+/// One row of a CSV export, with the password decrypted
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CsvRecord {
+    pub service: String,
+    pub email: String,
+    pub username: String,
+    pub password: String,
+    pub notes: String,
+    #[serde(default)]
+    pub two_factor: bool,
+    #[serde(default)]
+    pub kind: String,
+    #[serde(default)]
+    pub confirm_use: bool,
+    /// Websites saved on the record, separated by spaces
+    #[serde(default)]
+    pub urls: String,
+    #[serde(default)]
+    pub folder: String,
+    /// Tags of the record, separated by commas
+    #[serde(default)]
+    pub tags: String,
+}
+
+/// Decrypt every record for export. `progress` receives the number of records decrypted so
+/// far and the total, returning `false` stops and yields `None`. A record that does not decrypt
+/// fails the whole export rather than leaving it out of the file.
+pub fn decrypt_records(session: &Session, progress: impl FnMut(usize, usize) -> bool) -> Result<Option<Vec<CsvRecord>>, PandaboxError> {
+    decrypt(session, session.get_all_records()?, progress)
+}
+
+/// Decrypt the records with the given ids for export, like `decrypt_records`
+pub fn decrypt_selected_records(session: &Session, record_ids: &[i32], progress: impl FnMut(usize, usize) -> bool) -> Result<Option<Vec<CsvRecord>>, PandaboxError> {
+    decrypt(session, session.get_records_by_ids(record_ids)?, progress)
+}
+
+fn decrypt(session: &Session, records: Vec<Record>, mut progress: impl FnMut(usize, usize) -> bool) -> Result<Option<Vec<CsvRecord>>, PandaboxError> {
+    let total = records.len();
+    let mut urls = session.get_record_urls()?;
+    let mut tags = session.get_record_tags()?;
+    let mut csv_records = Vec::with_capacity(total);
+    let mut failed = Vec::new();
+
+    for (index, record) in records.into_iter().enumerate() {
+        match session.decrypt_password(&record) {
+            Ok(decrypted_password) => csv_records.push(CsvRecord {
+                service: record.service,
+                email: record.email,
+                username: record.username,
+                password: decrypted_password,
+                notes: record.notes,
+                two_factor: record.two_factor,
+                kind: record.kind,
+                confirm_use: record.confirm_use,
+                urls: urls.remove(&record.id).unwrap_or_default().join(" "),
+                folder: record.folder,
+                tags: tags.remove(&record.id).unwrap_or_default().join(", "),
+            }),
+            Err(e) => {
+                error!("Failed to decrypt password for {}: {}", record.service, e);
+                failed.push(record.id.to_string());
+            }
+        }
+        if !progress(index + 1, total) {
+            wipe(csv_records);
+            return Ok(None);
+        }
+    }
+
+    if !failed.is_empty() {
+        wipe(csv_records);
+        return Err(PandaboxError::CorruptVault(format!(
+            "the passwords of records {} do not decrypt, run Verify vault for details",
+            failed.join(", ")
+        )));
+    }
+    Ok(Some(csv_records))
+}
+
+/// Write the records as CSV, wiping each password once written
+pub fn write_csv(writer: impl Write, csv_records: Vec<CsvRecord>) -> csv::Result<()> {
+    let mut wtr = Writer::from_writer(writer);
+    let mut csv_records = csv_records.into_iter();
+    for mut csv_record in csv_records.by_ref() {
+        let result = wtr.serialize(&csv_record);
+        csv_record.password.zeroize();
+        if let Err(e) = result {
+            wipe(csv_records.collect());
+            return Err(e);
+        }
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Parse every row of a CSV export, failing on the first row that cannot be read
+pub fn read_csv(reader: impl Read) -> Result<Vec<ImportedEntry>, Box<dyn Error + Send + Sync>> {
+    let mut rdr = Reader::from_reader(reader);
+    rdr.deserialize()
+        .enumerate()
+        .map(|(index, result)| {
+            let csv_record: CsvRecord = result.map_err(|e| format!("row {}: {}", index + 1, e))?;
+            let urls = split_urls(&csv_record.urls).into_iter().map(str::to_string).collect();
+            Ok(ImportedEntry {
+                entry: Entry {
+                    service: csv_record.service,
+                    email: csv_record.email,
+                    username: csv_record.username,
+                    password: Zeroizing::new(csv_record.password),
+                    notes: csv_record.notes,
+                    two_factor: csv_record.two_factor,
+                    kind: RecordKind::parse(&csv_record.kind),
+                    confirm_use: csv_record.confirm_use,
+                },
+                folder: csv_record.folder,
+                urls,
+                tags: csv_record.tags.split(',').map(str::to_string).collect(),
+            })
+        })
+        .collect()
+}
+
+fn wipe(csv_records: Vec<CsvRecord>) {
+    for mut csv_record in csv_records {
+        csv_record.password.zeroize();
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+use diesel::prelude::*;
+use log::error;
+use zeroize::Zeroizing;
+use crate::database::models::RecordKind;
+use crate::encrypt::strength::{estimate_entropy, PasswordStrength};
+use crate::session::session::{unix_now, Session};
+
+pub const DEFAULT_MAX_PASSWORD_AGE_DAYS: u64 = 365;
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// A single finding of the health report, pointing back to the affected record.
+#[derive(Debug, Clone)]
+pub struct HealthIssue {
+    pub record_id: i32,
+    pub service: String,
+    pub detail: String,
+}
+
+/// Result of auditing every record of the vault.
+#[derive(Debug, Default)]
+pub struct HealthReport {
+    pub reused: Vec<HealthIssue>,
+    pub weak: Vec<HealthIssue>,
+    pub old: Vec<HealthIssue>,
+    pub missing_two_factor: Vec<HealthIssue>,
+}
+
+impl HealthReport {
+    pub fn issue_count(&self) -> usize {
+        self.reused.len() + self.weak.len() + self.old.len() + self.missing_two_factor.len()
+    }
+}
+
+impl Session {
+    /// Decrypt every password in memory and report reused, weak and old passwords as well as
+    /// entries without two-factor authentication. Plaintexts are wiped before returning.
+    pub fn health_report(&self, max_password_age_days: u64) -> QueryResult<HealthReport> {
+        // SSH keys are not passwords, they have no strength, reuse or two-factor to audit
+        let records: Vec<_> = self
+            .get_all_records()?
+            .into_iter()
+            .filter(|record| record.kind() == RecordKind::Login)
+            .collect();
+        let mut report = HealthReport::default();
+        let now = unix_now();
+        let max_age = max_password_age_days as i64 * SECONDS_PER_DAY;
+
+        let mut plaintexts: Vec<(usize, Zeroizing<String>)> = Vec::with_capacity(records.len());
+        for (index, record) in records.iter().enumerate() {
+            match self.decrypt_password(record) {
+                Ok(password) => plaintexts.push((index, Zeroizing::new(password))),
+                Err(e) => error!("Failed to decrypt password for {}: {}", record.service, e),
+            }
+        }
+
+        // Group records sharing the exact same password
+        let mut by_password: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (index, password) in &plaintexts {
+            by_password.entry(password.as_str()).or_default().push(*index);
+        }
+        for (index, password) in &plaintexts {
+            let record = &records[*index];
+            if let Some(group) = by_password.get(password.as_str()).filter(|group| group.len() > 1) {
+                let others: Vec<&str> = group
+                    .iter()
+                    .filter(|other| *other != index)
+                    .map(|other| records[*other].service.as_str())
+                    .collect();
+                report.reused.push(HealthIssue {
+                    record_id: record.id,
+                    service: record.service.clone(),
+                    detail: format!("Also used by {}", others.join(", ")),
+                });
+            }
+
+            let bits = estimate_entropy(password);
+            let strength = PasswordStrength::from_entropy(bits);
+            if strength <= PasswordStrength::Weak {
+                report.weak.push(HealthIssue {
+                    record_id: record.id,
+                    service: record.service.clone(),
+                    detail: format!("{} ({:.0} bits)", strength.label(), bits),
+                });
+            }
+        }
+
+        for record in &records {
+            let age = now - record.password_changed_at;
+            if age > max_age {
+                report.old.push(HealthIssue {
+                    record_id: record.id,
+                    service: record.service.clone(),
+                    detail: format!("Changed {} days ago", age / SECONDS_PER_DAY),
+                });
+            }
+
+            if !record.two_factor {
+                report.missing_two_factor.push(HealthIssue {
+                    record_id: record.id,
+                    service: record.service.clone(),
+                    detail: String::from("Two-factor authentication not enabled"),
+                });
+            }
+        }
+
+        Ok(report)
+    }
+}
@@ -0,0 +1,50 @@
+// Slows down guessing the master password. After a few failed unlocks every further attempt
+// has to wait twice as long as the one before, and a vault can ask to be wiped once too many
+// attempts failed. The counter lives in the vault so restarting Pandabox does not reset it.
+use std::time::Duration;
+use crate::database::models::UnlockAttempts;
+
+/// Failed unlocks allowed before attempts are delayed
+pub const FREE_ATTEMPTS: u32 = 3;
+/// Longest wait between two attempts
+pub const MAX_UNLOCK_DELAY: Duration = Duration::from_secs(5 * 60);
+/// Smallest wipe limit accepted, so a single typo never destroys a vault
+pub const MIN_WIPE_AFTER_FAILURES: u32 = 3;
+
+/// How long to wait after `failed_unlocks` consecutive failures: nothing for the first
+/// `FREE_ATTEMPTS`, then 2, 4, 8, ... seconds up to `MAX_UNLOCK_DELAY`
+pub fn unlock_delay(failed_unlocks: u32) -> Duration {
+    match failed_unlocks.checked_sub(FREE_ATTEMPTS) {
+        None | Some(0) => Duration::ZERO,
+        Some(excess) => Duration::from_secs(1u64 << excess.min(16)).min(MAX_UNLOCK_DELAY),
+    }
+}
+
+impl UnlockAttempts {
+    pub fn failed(&self) -> u32 {
+        self.failed_unlocks.max(0) as u32
+    }
+
+    /// The wipe limit, `None` when the vault is never wiped
+    pub fn wipe_limit(&self) -> Option<u32> {
+        (self.wipe_after_failures > 0).then_some(self.wipe_after_failures as u32)
+    }
+
+    /// Failed unlocks left before the vault is wiped
+    pub fn remaining(&self) -> Option<u32> {
+        self.wipe_limit().map(|limit| limit.saturating_sub(self.failed()))
+    }
+
+    pub fn wipe_due(&self) -> bool {
+        self.remaining() == Some(0)
+    }
+
+    /// Time left at unix time `now` before the next attempt is allowed, `None` when it is
+    /// allowed right away. A clock set back since the last failure restarts the full delay.
+    pub fn retry_after(&self, now: i64) -> Option<Duration> {
+        let delay = unlock_delay(self.failed());
+        let elapsed = now - self.last_failed_unlock;
+        let waited = if elapsed < 0 { Duration::ZERO } else { Duration::from_secs(elapsed as u64) };
+        delay.checked_sub(waited).filter(|left| !left.is_zero())
+    }
+}
@@ -1 +1,7 @@
-pub mod session;
\ No newline at end of file
+#[allow(clippy::module_inception)]
+pub mod session;
+pub mod health;
+pub mod verify;
+pub mod url_match;
+pub mod export;
+pub mod lockout;
@@ -1,82 +1,499 @@
-use std::rc::Rc;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
 use base64::Engine;
 use diesel::prelude::*;
-use slint::SharedString;
-use crate::database::manager::DatabaseManager;
-use crate::encrypt::cryptography::CryptEngine;
-use log::error;
+use zeroize::{Zeroize, Zeroizing};
+use crate::database::manager::{DatabaseManager, PasswordSwap};
+use crate::database::models::{KeySlotInfo, NewMasterRecord, NewRecord, NewRecordDetails, Record, RecordChanges, RecordKind, PASSWORD_SLOT, RECOVERY_SLOT};
+use crate::encrypt::cryptography::{CryptEngine, CIPHERTEXT_V1};
+use crate::encrypt::key_file::KeyFile;
+use crate::encrypt::key_slot::{KeyProtector, MasterPassword, RecoveryKey, RECOVERY_KIND};
+use crate::encrypt::recovery::{generate_recovery_key, normalize_recovery_key};
+use crate::encrypt::secure_memory::{NoCoreDumps, SecretBytes};
+use crate::error::PandaboxError;
+use crate::settings::VaultSettings;
+use crate::session::lockout::MIN_WIPE_AFTER_FAILURES;
+use crate::session::url_match::{normalize_url, NormalizedUrl, UrlMatch};
+use log::{error, info, warn};
+
+/// Ciphertext format written by this version, see the `create_vault_format` migration. Format
+/// 2 encrypts every field with XChaCha20-Poly1305 (`CIPHERTEXT_V2`), format 1 vaults may still
+/// hold ChaCha20-Poly1305 fields and are upgraded in the background by `upgrade_cipher_suite`.
+pub const CIPHERTEXT_FORMAT: i32 = 2;
+
+// Times the master key rotation starts over when entries change while it runs
+const ROTATION_ATTEMPTS: usize = 3;
+
+// Fields of older formats than this one have no version byte and cannot be read until the
+// vault is upgraded when it is unlocked
+const VERSIONED_CIPHERTEXT_FORMAT: i32 = 1;
+
+/// The unlocked session shared between the GUI and background services, `None` while locked.
+/// Long operations clone the inner `Arc` so the lock is not held while they run.
+pub type SessionState = Arc<Mutex<Option<Arc<Session>>>>;
+
+/// A record as the user sees it, with the password in plain text. The session encrypts it on
+/// the way into the vault.
+#[derive(Clone, Default)]
+pub struct Entry {
+    pub service: String,
+    pub email: String,
+    pub username: String,
+    pub password: Zeroizing<String>,
+    pub notes: String,
+    pub two_factor: bool,
+    pub kind: RecordKind,
+    pub confirm_use: bool,
+}
+
+/// An entry read back from an export, together with what is stored next to it
+#[derive(Clone, Default)]
+pub struct ImportedEntry {
+    pub entry: Entry,
+    pub folder: String,
+    pub urls: Vec<String>,
+    pub tags: Vec<String>,
+}
 
 pub struct Session {
-    // Whether the session is active or not
-    is_active: bool,
     // The unencrypted session key
-    key: Vec<u8>,
+    key: SecretBytes,
     // crypto engine
     crypto_engine: CryptEngine,
-    database_manager: Rc<DatabaseManager>
+    pub(super) database_manager: Arc<DatabaseManager>,
+    // No core dumps while the vault is unlocked
+    _no_core_dumps: NoCoreDumps,
 }
 
 impl Session {
-    pub fn new(key: Vec<u8>, crypto_engine: CryptEngine, db_manager: Rc<DatabaseManager>) -> Session {
+    pub fn new(key: Vec<u8>, crypto_engine: CryptEngine, db_manager: Arc<DatabaseManager>) -> Session {
         Session {
-            is_active: true,
-            key: key,
-            crypto_engine: crypto_engine,
-            database_manager: db_manager
+            key: SecretBytes::from_vec(key),
+            crypto_engine,
+            database_manager: db_manager,
+            _no_core_dumps: NoCoreDumps::new(),
+        }
+    }
+
+    /// Initialize an empty vault with a new master key protected by `password` and return it
+    /// unlocked. With a `key_file` the vault can only be unlocked with that file as well.
+    pub fn create(manager: Arc<DatabaseManager>, password: &str, key_file: Option<&KeyFile>) -> Result<Session, PandaboxError> {
+        // Writing the password slot again would lock the existing entries away for good
+        if manager.check_master_table_exists()? {
+            return Err(PandaboxError::KeySlotRefused(String::from("the vault already has a master password")));
+        }
+        let master_key = CryptEngine::generate_master_key();
+        let engine = write_key_slot(&manager, PASSWORD_SLOT, &MasterPassword { password, key_file }, &master_key)?;
+        manager.set_ciphertext_format(CIPHERTEXT_FORMAT)?;
+        Ok(Session::new(master_key, engine, manager))
+    }
+
+    /// Derive the key from the master password and unlock the vault with it.
+    /// `key_file` is only used by vaults created with one, they fail with `KeyFileRequired`
+    /// without it.
+    pub fn unlock(manager: Arc<DatabaseManager>, password: &str, key_file: Option<&KeyFile>) -> Result<Session, PandaboxError> {
+        Session::unlock_with(manager, &MasterPassword { password, key_file })
+    }
+
+    /// Unlock the vault with any of its key slots of the kind of `protector`. Failed attempts
+    /// are counted in the vault: past a few of them unlocking is refused until the delay of
+    /// `lockout::unlock_delay` has passed, and the vault is wiped when it asks for it.
+    pub fn unlock_with(manager: Arc<DatabaseManager>, protector: &dyn KeyProtector) -> Result<Session, PandaboxError> {
+        let (master_key, engine) = open_key_slots(&manager, protector)?;
+        Session::open(master_key, engine, manager)
+    }
+
+    /// Unlock the vault with its recovery key and protect it with `new_password` and, when
+    /// given, `key_file` from now on. The key file used so far is no longer needed, in case it
+    /// was lost too. Wrong recovery keys count as failed unlocks like wrong master passwords do.
+    pub fn recover(
+        manager: Arc<DatabaseManager>,
+        recovery_key: &str,
+        new_password: &str,
+        key_file: Option<&KeyFile>,
+    ) -> Result<Session, PandaboxError> {
+        if manager.get_key_slots_of_kind(RECOVERY_KIND)?.is_empty() {
+            return Err(PandaboxError::NoRecoveryKey);
+        }
+        // A typo is not an attempt, the checksum catches it before anything is derived
+        let Some(recovery_key) = normalize_recovery_key(recovery_key) else {
+            let remaining_attempts = manager.get_unlock_attempts()?.remaining();
+            return Err(PandaboxError::WrongRecoveryKey { remaining_attempts });
+        };
+
+        let (master_key, _) = match open_key_slots(&manager, &RecoveryKey(&recovery_key)) {
+            Ok(opened) => opened,
+            Err(PandaboxError::WrongPassword { remaining_attempts }) => {
+                return Err(PandaboxError::WrongRecoveryKey { remaining_attempts });
+            }
+            Err(e) => return Err(e),
+        };
+
+        let password = MasterPassword { password: new_password, key_file };
+        let engine = write_key_slot(&manager, PASSWORD_SLOT, &password, &master_key)?;
+        info!("Recovered the vault and replaced its master password");
+        Session::open(master_key, engine, manager)
+    }
+
+    /// The session of a vault that was just unlocked, upgrading its ciphertexts first when
+    /// they were written before they had a version
+    fn open(master_key: Vec<u8>, engine: CryptEngine, manager: Arc<DatabaseManager>) -> Result<Session, PandaboxError> {
+        let format = manager.get_ciphertext_format()?;
+        let session = Session::new(master_key, engine, manager);
+        if format < VERSIONED_CIPHERTEXT_FORMAT {
+            session.upgrade_ciphertexts()?;
+        }
+        if let Err(e) = session.purge_expired_trash() {
+            error!("Failed to purge the trash: {}", e);
+        }
+        Ok(session)
+    }
+
+    /// Re-encrypt every field of a vault written before ciphertexts were bound to their
+    /// record, in one transaction. Fields that do not decrypt are left as they are, they could
+    /// not be read before either.
+    fn upgrade_ciphertexts(&self) -> Result<(), PandaboxError> {
+        let mut passwords = Vec::new();
+        for record in self.database_manager.get_records_with_trash()? {
+            match self.decrypt_legacy_field(&record.password) {
+                Ok(password) => passwords.push((record.id, self.encrypt_field(&password, &password_binding(record.id))?)),
+                Err(e) => warn!("Record {} does not decrypt, leaving it as it is: {}", record.id, e),
+            }
+        }
+        let settings = match self.database_manager.get_vault_settings()? {
+            Some(encrypted) => match self.decrypt_legacy_field(&encrypted) {
+                Ok(settings) => Some(self.encrypt_field(&settings, &settings_binding())?),
+                Err(e) => {
+                    warn!("The vault settings do not decrypt, leaving them as they are: {}", e);
+                    None
+                }
+            },
+            None => None,
+        };
+
+        self.database_manager.rewrite_ciphertexts(&passwords, settings.as_deref(), CIPHERTEXT_FORMAT)?;
+        info!("Upgraded {} records to ciphertext format {}", passwords.len(), CIPHERTEXT_FORMAT);
+        Ok(())
+    }
+
+    /// Whether fields written with an older cipher suite are left, see `upgrade_cipher_suite`
+    pub fn needs_cipher_upgrade(&self) -> QueryResult<bool> {
+        Ok(self.database_manager.get_ciphertext_format()? < CIPHERTEXT_FORMAT)
+    }
+
+    /// Re-encrypt the fields still written with an older cipher suite with the current one.
+    /// Meant to run in the background while the vault is in use: fields changed meanwhile are
+    /// left as they are, they were written with the current suite. `progress` receives the
+    /// number of records checked and the total, returning `false` stops and yields `None`
+    /// before anything is written. Returns the number of fields still outdated, a field that
+    /// does not decrypt is left as it is and keeps the vault flagged for an upgrade.
+    pub fn upgrade_cipher_suite(&self, mut progress: impl FnMut(usize, usize) -> bool) -> Result<Option<usize>, PandaboxError> {
+        let records = self.database_manager.get_records_with_trash()?;
+        let total = records.len();
+        let mut passwords = Vec::new();
+        for (index, record) in records.into_iter().enumerate() {
+            if is_outdated(&record.password) {
+                match self.decrypt_password(&record).map(Zeroizing::new) {
+                    Ok(plaintext) => {
+                        let encrypted = self.encrypt_field(&plaintext, &password_binding(record.id))?;
+                        passwords.push((record.id, record.password, encrypted));
+                    }
+                    Err(e) => warn!("Record {} does not decrypt, leaving it as it is: {}", record.id, e),
+                }
+            }
+            if !progress(index + 1, total) {
+                return Ok(None);
+            }
+        }
+        let settings = match self.database_manager.get_vault_settings()? {
+            Some(encrypted) if is_outdated(&encrypted) => match self.decrypt_field(&encrypted, &settings_binding()) {
+                Ok(settings) => Some((self.encrypt_field(&Zeroizing::new(settings), &settings_binding())?, encrypted)),
+                Err(e) => {
+                    warn!("The vault settings do not decrypt, leaving them as they are: {}", e);
+                    None
+                }
+            },
+            _ => None,
+        };
+
+        let (upgraded, outdated) = self.database_manager.swap_ciphertexts(
+            &passwords,
+            settings.as_ref().map(|(upgraded, read)| (read.as_str(), upgraded.as_str())),
+            CIPHERTEXT_FORMAT,
+            is_outdated,
+        )?;
+        info!("Upgraded {} fields to ciphertext format {}", upgraded, CIPHERTEXT_FORMAT);
+        if outdated > 0 {
+            warn!("{} fields are still outdated, keeping the ciphertext format", outdated);
+        }
+        Ok(Some(outdated))
+    }
+
+    /// Wrap the master key with a new recovery key, replacing the previous one, and return the
+    /// key for the user to write down. It is not stored anywhere else.
+    pub fn create_recovery_key(&self) -> Result<Zeroizing<String>, PandaboxError> {
+        let recovery_key = generate_recovery_key();
+        write_key_slot(&self.database_manager, RECOVERY_SLOT, &RecoveryKey(&recovery_key), self.get_key())?;
+        Ok(recovery_key)
+    }
+
+    /// The key slots `rotate_master_key` deletes, every one but the master password slot
+    pub fn key_slots_removed_by_rotation(&self) -> QueryResult<Vec<KeySlotInfo>> {
+        let mut slots = self.database_manager.list_key_slots()?;
+        slots.retain(|slot| slot.slot != PASSWORD_SLOT);
+        Ok(slots)
+    }
+
+    /// Replace the master key itself after a suspected compromise. Every encrypted field is
+    /// decrypted with the current key and encrypted with a new one, `progress` receives the
+    /// number of records done and the total, returning `false` stops and yields `None` before
+    /// anything is written. The new key is then stored in one transaction, wrapped with
+    /// `password` and, for vaults that need it, `key_file`. The other key slots still hold the
+    /// old key, so they are deleted; their names are returned with the new session, which
+    /// replaces this one. Entries written meanwhile through another session would be lost with
+    /// the old key, so the rotation starts over when one changed and gives up with
+    /// `VaultChanged` after `ROTATION_ATTEMPTS`.
+    pub fn rotate_master_key(
+        &self,
+        password: &str,
+        key_file: Option<&KeyFile>,
+        mut progress: impl FnMut(usize, usize) -> bool,
+    ) -> Result<Option<(Session, Vec<String>)>, PandaboxError> {
+        // Checked without counting a failed unlock, the vault is already open
+        let mut current = self.database_manager.get_master_record()?;
+        let password = MasterPassword { password, key_file: key_file.filter(|_| current.requires_key_file) };
+        let opened = password
+            .engine(&current.salt, current.requires_key_file)?
+            .decrypt_master_key(&current.nonce, &current.encrypted_master_key)
+            .map(Zeroizing::new);
+        current.encrypted_master_key.zeroize();
+        current.nonce.zeroize();
+        current.salt.zeroize();
+        if !opened.is_ok_and(|key| key.as_slice() == &*self.key) {
+            let remaining_attempts = self.database_manager.get_unlock_attempts()?.remaining();
+            return Err(PandaboxError::WrongPassword { remaining_attempts });
         }
+
+        let master_key = CryptEngine::generate_master_key();
+        let (engine, wrapped) = wrap_master_key(&password, &master_key)?;
+        let rotated = Session::new(master_key, engine, Arc::clone(&self.database_manager));
+
+        for _ in 0..ROTATION_ATTEMPTS {
+            let Some(passwords) = self.re_encrypt_passwords(&rotated, &mut progress)? else {
+                return Ok(None);
+            };
+            let stored_settings = self.database_manager.get_vault_settings()?;
+            let settings = match &stored_settings {
+                Some(encrypted) => {
+                    let settings = self.decrypt_field(encrypted, &settings_binding())
+                        .map(Zeroizing::new)
+                        .map_err(|e| PandaboxError::CorruptVault(format!("the vault settings do not decrypt: {}", e)))?;
+                    Some(rotated.encrypt_field(&settings, &settings_binding())?)
+                }
+                None => None,
+            };
+
+            let replaced = self.database_manager.replace_master_key(
+                &passwords,
+                (stored_settings.as_deref(), settings.as_deref()),
+                CIPHERTEXT_FORMAT,
+                &wrapped.slot(PASSWORD_SLOT, &password),
+            )?;
+            if let Some(removed) = replaced {
+                info!("Rotated the master key of {} records, removed the key slots {:?}", passwords.len(), removed);
+                return Ok(Some((rotated, removed)));
+            }
+            warn!("The vault changed while the master key was rotated, starting over");
+        }
+        Err(PandaboxError::VaultChanged)
     }
 
-    fn get_key(&self) -> &Vec<u8> {
+    /// Every password of the vault, the trash included, as its record id, the ciphertext read
+    /// and the same password encrypted by `rotated`. `None` when `progress` stopped.
+    fn re_encrypt_passwords(
+        &self,
+        rotated: &Session,
+        progress: &mut impl FnMut(usize, usize) -> bool,
+    ) -> Result<Option<Vec<PasswordSwap>>, PandaboxError> {
+        // A field that does not decrypt now would be lost for good with the old key
+        let records = self.database_manager.get_records_with_trash()?;
+        let total = records.len();
+        let mut passwords = Vec::with_capacity(total);
+        let mut failed = Vec::new();
+        for (index, record) in records.into_iter().enumerate() {
+            match self.decrypt_password(&record).map(Zeroizing::new) {
+                Ok(plaintext) => {
+                    let encrypted = rotated.encrypt_field(&plaintext, &password_binding(record.id))?;
+                    passwords.push((record.id, record.password, encrypted));
+                }
+                Err(_) => failed.push(record.id.to_string()),
+            }
+            if !progress(index + 1, total) {
+                return Ok(None);
+            }
+        }
+        if !failed.is_empty() {
+            return Err(PandaboxError::CorruptVault(format!(
+                "the passwords of records {} do not decrypt, run Verify vault for details",
+                failed.join(", ")
+            )));
+        }
+        Ok(Some(passwords))
+    }
+
+    /// Wrap the master key in a new slot called `name` that `protector` opens, e.g. a second
+    /// password or a key file. Names are unique within the vault.
+    pub fn add_key_slot(&self, name: &str, protector: &dyn KeyProtector) -> Result<(), PandaboxError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(PandaboxError::KeySlotRefused(String::from("key slots need a name")));
+        }
+        if self.database_manager.has_key_slot(name)? {
+            return Err(PandaboxError::KeySlotRefused(format!("a key slot named \"{}\" already exists", name)));
+        }
+        write_key_slot(&self.database_manager, name, protector, self.get_key())?;
+        info!("Added the {} key slot \"{}\"", protector.kind(), name);
+        Ok(())
+    }
+
+    /// Delete the slot called `name`, whatever it held no longer opens the vault. The master
+    /// password slot cannot be removed, the unlock attempts are counted in it.
+    pub fn remove_key_slot(&self, name: &str) -> Result<(), PandaboxError> {
+        if name == PASSWORD_SLOT {
+            return Err(PandaboxError::KeySlotRefused(String::from("the master password slot is always kept")));
+        }
+        if self.database_manager.remove_key_slot(name)? == 0 {
+            return Err(PandaboxError::KeySlotRefused(format!("there is no key slot named \"{}\"", name)));
+        }
+        info!("Removed the key slot \"{}\"", name);
+        Ok(())
+    }
+
+    /// The key slots of the vault, oldest first
+    pub fn key_slots(&self) -> QueryResult<Vec<KeySlotInfo>> {
+        self.database_manager.list_key_slots()
+    }
+
+    /// Whether unlocking with the master password also takes the key file
+    pub fn requires_key_file(&self) -> QueryResult<bool> {
+        Ok(self.database_manager.get_master_record()?.requires_key_file)
+    }
+
+    /// Failed unlocks after which this vault is wiped, `None` when it never is
+    pub fn wipe_after_failures(&self) -> QueryResult<Option<u32>> {
+        Ok(self.database_manager.get_unlock_attempts()?.wipe_limit())
+    }
+
+    /// Wipe the vault after `limit` failed unlocks in a row, raised to at least
+    /// `MIN_WIPE_AFTER_FAILURES`, or never with `None`
+    pub fn set_wipe_after_failures(&self, limit: Option<u32>) -> QueryResult<()> {
+        let limit = limit.map_or(0, |limit| limit.clamp(MIN_WIPE_AFTER_FAILURES, i32::MAX as u32) as i32);
+        self.database_manager.set_wipe_after_failures(limit)?;
+        Ok(())
+    }
+
+    fn get_key(&self) -> &SecretBytes {
         &self.key
     }
     
     /// Get all records from the database
-    pub fn get_all_records(&self) -> QueryResult<Vec<crate::database::models::Record>> {
+    pub fn get_all_records(&self) -> QueryResult<Vec<Record>> {
         self.database_manager.get_all_records()
     }
 
-    pub fn insert_entry(&self, service: &SharedString, email: &SharedString, username: &SharedString, password: &SharedString, notes: &SharedString) -> QueryResult<crate::database::models::Record>
+    pub fn insert_entry(&self, entry: &Entry) -> QueryResult<Record>
     {
-        let encrypted_password = match self.crypto_engine.encrypt_record(password.as_str().as_ref(), self.get_key().clone()) {
-            Ok(encrypted) => base64::engine::general_purpose::STANDARD.encode(encrypted),
-            Err(e) => {
-                error!("Failed to encrypt password: {}", e);
-                return Err(diesel::result::Error::DeserializationError(
-                    Box::new(std::io::Error::new(
-                        std::io::ErrorKind::Other,
-                        format!("Encryption error: {}", e)
-                    ))
-                ));
-            }
-        };    
-        
-        self.database_manager.insert_entry(
-            service.as_str(),
-            email.as_str(),
-            username.as_str(),
-            encrypted_password.as_str(),
-            notes.as_str(),
+        // The password is encrypted once the record id it is bound to is known
+        self.database_manager.insert_entry(&NewRecord {
+            service: &entry.service,
+            email: &entry.email,
+            username: &entry.username,
+            password: "",
+            notes: &entry.notes,
+            password_changed_at: unix_now(),
+            two_factor: entry.two_factor,
+            kind: entry.kind.as_str(),
+            confirm_use: entry.confirm_use,
+        }, |record_id| self.encrypt_field(&entry.password, &password_binding(record_id)))
+    }
+
+    /// Insert all entries in a single transaction, so an encryption or database error leaves
+    /// the vault untouched. `progress` receives the number of records written so far and the
+    /// total, returning `false` cancels the import.
+    pub fn insert_entries(&self, entries: &[Entry], progress: impl FnMut(usize, usize) -> bool) -> QueryResult<usize> {
+        self.insert_all(&entries.iter().collect::<Vec<_>>(), &[], progress)
+    }
+
+    /// Insert the entries read from an export together with their websites, in a single
+    /// transaction like `insert_entries`
+    pub fn import_entries(&self, imported: &[ImportedEntry], progress: impl FnMut(usize, usize) -> bool) -> QueryResult<usize> {
+        let entries: Vec<&Entry> = imported.iter().map(|imported| &imported.entry).collect();
+        let details: Vec<NewRecordDetails> = imported
+            .iter()
+            .map(|imported| NewRecordDetails {
+                folder: imported.folder.trim().to_string(),
+                urls: normalize_urls(&imported.urls),
+                tags: imported
+                    .tags
+                    .iter()
+                    .map(|tag| tag.trim())
+                    .filter(|tag| !tag.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            })
+            .collect();
+        self.insert_all(&entries, &details, progress)
+    }
+
+    fn insert_all(&self, entries: &[&Entry], details: &[NewRecordDetails], progress: impl FnMut(usize, usize) -> bool) -> QueryResult<usize> {
+        let now = unix_now();
+        let new_records: Vec<NewRecord> = entries
+            .iter()
+            .map(|entry| NewRecord {
+                service: &entry.service,
+                email: &entry.email,
+                username: &entry.username,
+                password: "",
+                notes: &entry.notes,
+                password_changed_at: now,
+                two_factor: entry.two_factor,
+                kind: entry.kind.as_str(),
+                confirm_use: entry.confirm_use,
+            })
+            .collect();
+
+        self.database_manager.insert_entries(
+            &new_records,
+            details,
+            |index, record_id| self.encrypt_field(&entries[index].password, &password_binding(record_id)),
+            progress,
         )
     }
 
-    pub fn update_entry(&self, record_id: i32, service: &SharedString, email: &SharedString, username: &SharedString, password: &SharedString, notes: &SharedString) -> bool
+    pub fn update_entry(&self, record_id: i32, entry: &Entry) -> bool
     {
-        let encrypted_password = match self.crypto_engine.encrypt_record(password.as_str().as_ref(), self.get_key().clone()) {
-            Ok(encrypted) => base64::engine::general_purpose::STANDARD.encode(encrypted),
-            Err(e) => {
-                error!("Failed to encrypt password: {}", e);
-                return false;
-            }
+        // Only a new password resets its age, re-saving the same one keeps the original date
+        let password_changed = match self.get_decrypted_password(record_id) {
+            Ok(existing) => Zeroizing::new(existing) != entry.password,
+            Err(_) => true,
         };
 
-        match self.database_manager.update_record(
-            record_id,
-            service.as_str(),
-            email.as_str(),
-            username.as_str(),
-            encrypted_password.as_str(),
-            notes.as_str(),
-        ) {
+        let Ok(encrypted_password) = self.encrypt_field(&entry.password, &password_binding(record_id)) else {
+            return false;
+        };
+
+        match self.database_manager.update_record(record_id, &RecordChanges {
+            service: &entry.service,
+            email: &entry.email,
+            username: &entry.username,
+            password: encrypted_password.as_str(),
+            notes: &entry.notes,
+            password_changed_at: password_changed.then(unix_now),
+            two_factor: entry.two_factor,
+            kind: entry.kind.as_str(),
+            confirm_use: entry.confirm_use,
+        }) {
             Ok(_) => true,
             Err(e) => {
                 error!("Failed to update entry: {}", e);
@@ -85,12 +502,14 @@ impl Session {
         }
     }
 
+    /// Move the record to the trash, see `restore_entry` and `purge_entry`
     pub fn delete_entry(&self, record_id: i32) -> bool
     {
-        match self.database_manager.delete_entry(
-            record_id
+        match self.database_manager.trash_entry(
+            record_id,
+            unix_now()
         ) {
-            Ok(_) => true,
+            Ok(trashed) => trashed > 0,
             Err(e) => {
                 error!("Failed to delete entry: {}", e);
                 false
@@ -98,13 +517,191 @@ impl Session {
         }
     }
 
-    /// Decrypt a password on-demand (for copying to clipboard)
-    pub fn decrypt_password(&self, encrypted_password_base64: &str) -> Result<String, Box<dyn std::error::Error>> {
+    /// Take a deleted record out of the trash, `false` when it is not in it
+    pub fn restore_entry(&self, record_id: i32) -> QueryResult<bool> {
+        Ok(self.database_manager.restore_entry(record_id)? > 0)
+    }
+
+    /// Move several records to the trash in one go, returns how many were moved
+    pub fn delete_entries(&self, record_ids: &[i32]) -> QueryResult<usize> {
+        self.database_manager.trash_entries(record_ids, unix_now())
+    }
+
+    /// Take several records out of the trash, returns how many were restored
+    pub fn restore_entries(&self, record_ids: &[i32]) -> QueryResult<usize> {
+        self.database_manager.restore_entries(record_ids)
+    }
+
+    /// Delete a record in the trash for good
+    pub fn purge_entry(&self, record_id: i32) -> QueryResult<bool> {
+        if self.database_manager.get_record_by_id(record_id)?.deleted_at.is_none() {
+            return Ok(false);
+        }
+        Ok(self.database_manager.delete_entry(record_id)? > 0)
+    }
+
+    /// Records in the trash, most recently deleted first
+    pub fn trashed_records(&self) -> QueryResult<Vec<Record>> {
+        self.database_manager.get_trashed_records()
+    }
+
+    /// Delete every record in the trash for good, returns how many there were
+    pub fn empty_trash(&self) -> QueryResult<usize> {
+        self.database_manager.purge_trash(i64::MAX)
+    }
+
+    /// Delete the records that stayed in the trash longer than the vault settings keep them
+    pub fn purge_expired_trash(&self) -> QueryResult<usize> {
+        let Some(retention) = self.settings().trash_retention() else {
+            return Ok(0);
+        };
+        let purged = self.database_manager.purge_trash(unix_now() - retention.as_secs() as i64)?;
+        if purged > 0 {
+            info!("Purged {} records from the trash", purged);
+        }
+        Ok(purged)
+    }
+
+    /// Records holding SSH private keys, still encrypted
+    pub fn get_ssh_key_records(&self) -> QueryResult<Vec<Record>> {
+        self.database_manager.get_records_by_kind(RecordKind::SshKey.as_str())
+    }
+
+    /// Replace the websites attached to a record, skipping entries that are not valid URLs
+    pub fn set_record_urls(&self, record_id: i32, urls: &[&str]) -> QueryResult<usize> {
+        self.database_manager.set_record_urls(record_id, &normalize_urls(urls))
+    }
+
+    /// Websites attached to each record, keyed by record id
+    pub fn get_record_urls(&self) -> QueryResult<HashMap<i32, Vec<String>>> {
+        let mut urls: HashMap<i32, Vec<String>> = HashMap::new();
+        for (record_id, url) in self.database_manager.get_all_record_urls()? {
+            urls.entry(record_id).or_default().push(url);
+        }
+        Ok(urls)
+    }
+
+    /// Tags of every record, keyed by record id and sorted by name
+    pub fn get_record_tags(&self) -> QueryResult<HashMap<i32, Vec<String>>> {
+        let mut tags: HashMap<i32, Vec<String>> = HashMap::new();
+        for (record_id, tag) in self.database_manager.get_all_record_tags()? {
+            tags.entry(record_id).or_default().push(tag);
+        }
+        Ok(tags)
+    }
+
+    /// File the records under `folder`, an empty name takes them out of their folder. Returns
+    /// the number of records moved.
+    pub fn move_to_folder(&self, record_ids: &[i32], folder: &str) -> QueryResult<usize> {
+        self.database_manager.set_folder(record_ids, folder.trim())
+    }
+
+    /// Tag the records with `tag`, returns how many of them did not have it yet
+    pub fn tag_entries(&self, record_ids: &[i32], tag: &str) -> QueryResult<usize> {
+        let tag = tag.trim();
+        if tag.is_empty() {
+            return Ok(0);
+        }
+        self.database_manager.add_tag(record_ids, tag)
+    }
+
+    /// The records with the given ids, those in the trash left out
+    pub fn get_records_by_ids(&self, record_ids: &[i32]) -> QueryResult<Vec<Record>> {
+        self.database_manager.get_records_by_ids(record_ids)
+    }
+
+    /// Records holding credentials for the given URL
+    pub fn find_credentials_for_url(&self, url: &str, mode: UrlMatch) -> QueryResult<Vec<Record>> {
+        match normalize_url(url) {
+            Some(normalized) => self.database_manager.find_records_by_url(&normalized, mode),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Records matching the query as text or, for anything that looks like a domain,
+    /// records with a website on the same base domain
+    pub fn search_records(&self, query: &str) -> QueryResult<Vec<Record>> {
+        let query = query.trim();
+        let mut results = self.database_manager.search_records(query)?;
+        if query.contains('.') {
+            for record in self.find_credentials_for_url(query, UrlMatch::BaseDomain)? {
+                if !results.iter().any(|existing| existing.id == record.id) {
+                    results.push(record);
+                }
+            }
+            results.sort_by_key(|record| record.id);
+        }
+        Ok(results)
+    }
+
+    /// The settings of this vault, the defaults until they are first saved
+    pub fn settings(&self) -> VaultSettings {
+        match self.stored_settings() {
+            Ok(settings) => settings.unwrap_or_default(),
+            Err(e) => {
+                error!("Failed to load vault settings: {}", e);
+                VaultSettings::default()
+            }
+        }
+    }
+
+    /// The settings saved in this vault, `None` until they are first saved
+    pub fn stored_settings(&self) -> Result<Option<VaultSettings>, Box<dyn std::error::Error>> {
+        let Some(encrypted) = self.database_manager.get_vault_settings()? else {
+            return Ok(None);
+        };
+        let json = Zeroizing::new(self.decrypt_field(&encrypted, &settings_binding())?);
+        Ok(Some(serde_json::from_str(&json)?))
+    }
+
+    pub fn save_settings(&self, settings: &VaultSettings) -> QueryResult<()> {
+        let json = serde_json::to_string(settings).map_err(|e| diesel::result::Error::SerializationError(Box::new(e)))?;
+        let encrypted = self.encrypt_field(&json, &settings_binding())?;
+        self.database_manager.set_vault_settings(&encrypted)?;
+        Ok(())
+    }
+
+    /// Encrypt a field with the session key, bound to where it is stored by `binding` and
+    /// base64 encoded for storage
+    fn encrypt_field(&self, plaintext: &str, binding: &[u8]) -> QueryResult<String> {
+        match self.crypto_engine.encrypt_record(plaintext.as_bytes(), self.get_key(), binding) {
+            Ok(encrypted) => Ok(base64::engine::general_purpose::STANDARD.encode(encrypted)),
+            Err(e) => {
+                error!("Failed to encrypt a field: {}", e);
+                Err(diesel::result::Error::DeserializationError(
+                    Box::new(std::io::Error::other(format!("Encryption error: {}", e)))
+                ))
+            }
+        }
+    }
+
+    /// The entry stored in `record`, with its password decrypted
+    pub fn decrypt_entry(&self, record: &Record) -> Result<Entry, Box<dyn std::error::Error>> {
+        Ok(Entry {
+            service: record.service.clone(),
+            email: record.email.clone(),
+            username: record.username.clone(),
+            password: Zeroizing::new(self.decrypt_password(record)?),
+            notes: record.notes.clone(),
+            two_factor: record.two_factor,
+            kind: record.kind(),
+            confirm_use: record.confirm_use,
+        })
+    }
+
+    /// Decrypt the password of `record` on-demand (for copying to clipboard). A password moved
+    /// over from another record does not decrypt.
+    pub fn decrypt_password(&self, record: &Record) -> Result<String, Box<dyn std::error::Error>> {
+        self.decrypt_field(&record.password, &password_binding(record.id))
+    }
+
+    /// Decrypt a field written by `encrypt_field` with the same `binding`
+    fn decrypt_field(&self, encrypted_base64: &str, binding: &[u8]) -> Result<String, Box<dyn std::error::Error>> {
         // Decode from base64
-        let encrypted_bytes = base64::engine::general_purpose::STANDARD.decode(encrypted_password_base64)?;
+        let encrypted_bytes = base64::engine::general_purpose::STANDARD.decode(encrypted_base64)?;
         
         // Decrypt using the session key
-        let decrypted_bytes = self.crypto_engine.decrypt_record(&encrypted_bytes, self.get_key().clone())
+        let decrypted_bytes = self.crypto_engine.decrypt_record(&encrypted_bytes, self.get_key(), binding)
             .map_err(|e| format!("Decryption error: {:?}", e))?;
         
         // Convert to string
@@ -113,12 +710,160 @@ impl Session {
         Ok(password)
     }
 
+    /// Decrypt a field written before ciphertexts were bound to their record
+    fn decrypt_legacy_field(&self, encrypted_base64: &str) -> Result<Zeroizing<String>, Box<dyn std::error::Error>> {
+        let encrypted_bytes = base64::engine::general_purpose::STANDARD.decode(encrypted_base64)?;
+        let decrypted_bytes = self.crypto_engine.decrypt_legacy_record(&encrypted_bytes, self.get_key())
+            .map_err(|e| format!("Decryption error: {:?}", e))?;
+        Ok(Zeroizing::new(String::from_utf8(decrypted_bytes)?))
+    }
+
     /// Get encrypted password from database by record ID and decrypt it
     pub fn get_decrypted_password(&self, record_id: i32) -> Result<String, Box<dyn std::error::Error>> {
         // Fetch the record from database
         let record = self.database_manager.get_record_by_id(record_id)?;
         
         // Decrypt the password
-        self.decrypt_password(&record.password)
+        self.decrypt_password(&record)
     }
 }
+
+/// Normalize websites for storage, skipping entries that are not valid URLs
+fn normalize_urls(urls: &[impl AsRef<str>]) -> Vec<NormalizedUrl> {
+    urls.iter()
+        .filter_map(|url| {
+            let normalized = normalize_url(url.as_ref());
+            if normalized.is_none() {
+                warn!("Ignoring invalid website '{}'", url.as_ref());
+            }
+            normalized
+        })
+        .collect()
+}
+
+/// Whether a field was encrypted with an older cipher suite than the one written now
+fn is_outdated(encrypted_base64: &str) -> bool {
+    base64::engine::general_purpose::STANDARD
+        .decode(encrypted_base64)
+        .is_ok_and(|encrypted| encrypted.first() == Some(&CIPHERTEXT_V1))
+}
+
+/// Associated data binding an encrypted password to its record
+fn password_binding(record_id: i32) -> Vec<u8> {
+    format!("pandabox/records/{}/password", record_id).into_bytes()
+}
+
+/// Associated data of the encrypted vault settings, kept in a single row
+fn settings_binding() -> Vec<u8> {
+    b"pandabox/vault_settings/1/data".to_vec()
+}
+
+/// Wrap `master_key` with `protector` in the slot called `name`, replacing what the slot held,
+/// and return the engine that wrapped it
+fn write_key_slot(manager: &DatabaseManager, name: &str, protector: &dyn KeyProtector, master_key: &[u8]) -> Result<CryptEngine, PandaboxError> {
+    let (engine, wrapped) = wrap_master_key(protector, master_key)?;
+    manager.set_key_slot(&wrapped.slot(name, protector))?;
+    Ok(engine)
+}
+
+/// A master key wrapped with a new salt, ready to be stored in a key slot
+struct WrappedKey {
+    salt: Zeroizing<Vec<u8>>,
+    nonce: Vec<u8>,
+    ciphertext: Vec<u8>,
+}
+
+impl WrappedKey {
+    fn slot<'a>(&'a self, name: &'a str, protector: &dyn KeyProtector) -> NewMasterRecord<'a> {
+        NewMasterRecord {
+            encrypted_master_key: &self.ciphertext,
+            nonce: &self.nonce,
+            salt: &self.salt,
+            slot: name,
+            requires_key_file: protector.uses_key_file(),
+            kind: protector.kind(),
+        }
+    }
+}
+
+fn wrap_master_key(protector: &dyn KeyProtector, master_key: &[u8]) -> Result<(CryptEngine, WrappedKey), PandaboxError> {
+    let salt = Zeroizing::new(CryptEngine::generate_salt());
+    let engine = protector.engine(&salt, protector.uses_key_file())?;
+    let (nonce, ciphertext) = engine
+        .encrypt_master_key(master_key)
+        .map_err(|e| PandaboxError::Crypto(e.to_string()))?;
+    Ok((engine, WrappedKey { salt, nonce, ciphertext }))
+}
+
+/// Decrypt the master key with the first slot of its kind that `protector` opens, applying the
+/// unlock delays and the wipe policy. Failing every slot counts as one failed unlock and is
+/// reported as `WrongPassword`.
+fn open_key_slots(manager: &DatabaseManager, protector: &dyn KeyProtector) -> Result<(Vec<u8>, CryptEngine), PandaboxError> {
+    // Every vault keeps its password slot, the unlock attempts are counted in it
+    if !manager.check_master_table_exists()? {
+        return Err(PandaboxError::CorruptVault(format!("the {} key slot is missing", PASSWORD_SLOT)));
+    }
+    let mut slots = manager.get_key_slots_of_kind(protector.kind())?;
+    if slots.is_empty() {
+        return Err(PandaboxError::NoKeySlot(protector.kind()));
+    }
+
+    let attempts = manager.get_unlock_attempts()?;
+    if let Some(delay) = attempts.retry_after(unix_now()) {
+        return Err(PandaboxError::UnlockDelayed(delay));
+    }
+
+    let mut opened = None;
+    let mut tried = false;
+    for slot in &slots {
+        // Slots needing a key file that was not given are skipped without trying them
+        let engine = match protector.engine(&slot.salt, slot.requires_key_file) {
+            Ok(engine) => engine,
+            Err(PandaboxError::KeyFileRequired) => continue,
+            Err(e) => return Err(e),
+        };
+        tried = true;
+        if let Ok(decrypted_key) = engine.decrypt_master_key(&slot.nonce, &slot.encrypted_master_key) {
+            info!("Unlocked with the key slot \"{}\"", slot.slot);
+            opened = Some((decrypted_key, engine));
+            break;
+        }
+    }
+    for slot in &mut slots {
+        slot.encrypted_master_key.zeroize();
+        slot.nonce.zeroize();
+        slot.salt.zeroize();
+    }
+
+    let Some((decrypted_key, engine)) = opened else {
+        // Asking for the file is not an attempt, nothing was tried yet
+        if !tried {
+            return Err(PandaboxError::KeyFileRequired);
+        }
+        let attempts = manager.record_failed_unlock(unix_now())?;
+        warn!("Failed unlock, {} in a row", attempts.failed());
+        if attempts.wipe_due() {
+            manager.wipe()?;
+            warn!("Wiped the vault after {} failed unlocks", attempts.failed());
+            return Err(PandaboxError::VaultWiped);
+        }
+        return Err(PandaboxError::WrongPassword { remaining_attempts: attempts.remaining() });
+    };
+
+    // A master key of the wrong size would only fail later when decrypting records
+    if decrypted_key.len() != 32 {
+        return Err(PandaboxError::CorruptVault(String::from("the master key has an invalid length")));
+    }
+    if attempts.failed() > 0 {
+        manager.reset_failed_unlocks()?;
+    }
+    Ok((decrypted_key, engine))
+}
+
+/// Current time as seconds since the unix epoch
+pub fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or(0)
+}
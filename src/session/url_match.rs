@@ -0,0 +1,72 @@
+use url::{Host, Url};
+
+/// How strictly a URL has to match the websites saved on a record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UrlMatch {
+    /// Same scheme, host, port and path
+    Exact,
+    /// Same host name, e.g. `login.example.com`
+    Host,
+    /// Same registrable domain, e.g. `example.com` for `login.example.com`
+    #[default]
+    BaseDomain,
+}
+
+/// A website reduced to the parts used for matching.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NormalizedUrl {
+    pub url: String,
+    pub host: String,
+    pub base_domain: String,
+}
+
+/// Normalize user input like `Example.com/login/` or `https://www.example.co.uk?ref=1`.
+/// Query strings, fragments, credentials and trailing slashes are dropped and the host is
+/// reduced to its registrable domain using the public suffix list.
+pub fn normalize_url(input: &str) -> Option<NormalizedUrl> {
+    let input = input.trim();
+    if input.is_empty() {
+        return None;
+    }
+
+    // Only input without a scheme is read as a https URL, `https://` alone is no website
+    let mut url = Url::parse(input)
+        .ok()
+        .filter(|url| url.has_host())
+        .or_else(|| {
+            if input.contains("://") {
+                return None;
+            }
+            Url::parse(&format!("https://{}", input)).ok()
+        })?;
+    url.set_query(None);
+    url.set_fragment(None);
+    url.set_username("").ok();
+    url.set_password(None).ok();
+
+    let host = url.host_str()?.trim_end_matches('.').to_lowercase();
+    if host.is_empty() {
+        return None;
+    }
+    // IP addresses have no registrable domain, they only ever match themselves
+    let base_domain = match url.host() {
+        Some(Host::Domain(_)) => psl::domain_str(&host).map(str::to_string),
+        _ => None,
+    }
+    .unwrap_or_else(|| host.clone());
+
+    let normalized = url.as_str().trim_end_matches('/').to_string();
+    Some(NormalizedUrl {
+        url: normalized,
+        host,
+        base_domain,
+    })
+}
+
+/// Split the free-form websites field of the form into individual entries
+pub fn split_urls(field: &str) -> Vec<&str> {
+    field
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|url| !url.is_empty())
+        .collect()
+}
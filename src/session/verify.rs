@@ -0,0 +1,122 @@
+use base64::Engine;
+use zeroize::Zeroizing;
+use crate::database::models::RecordKind;
+use crate::encrypt::cryptography::record_nonce_len;
+use crate::error::PandaboxError;
+use crate::session::health::HealthIssue;
+use crate::session::session::{Session, CIPHERTEXT_FORMAT};
+
+// Length of the Poly1305 tag ending every ciphertext
+const TAG_LEN: usize = 16;
+
+/// Result of checking the whole vault, see `Session::verify`.
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    /// Problems of the vault file itself: SQLite integrity, migrations and vault settings
+    pub vault: Vec<String>,
+    /// Records whose stored values are malformed
+    pub corrupt: Vec<HealthIssue>,
+    /// Records whose password is well formed but does not decrypt with this vault's key
+    pub undecryptable: Vec<HealthIssue>,
+    /// Websites and lookup attributes left behind by deleted records
+    pub orphaned: Vec<HealthIssue>,
+}
+
+impl VerifyReport {
+    pub fn issue_count(&self) -> usize {
+        self.vault.len() + self.corrupt.len() + self.undecryptable.len() + self.orphaned.len()
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.issue_count() == 0
+    }
+}
+
+impl Session {
+    /// Check the vault file, its migrations and every encrypted value. `progress` receives the
+    /// number of records checked so far and the total, returning `false` stops and yields
+    /// `None`. Decrypted passwords are wiped right away.
+    pub fn verify(&self, mut progress: impl FnMut(usize, usize) -> bool) -> Result<Option<VerifyReport>, PandaboxError> {
+        let manager = &self.database_manager;
+        let mut report = VerifyReport::default();
+
+        for problem in manager.integrity_check()? {
+            report.vault.push(format!("SQLite integrity check: {}", problem));
+        }
+        for migration in manager.pending_migrations()? {
+            report.vault.push(format!("Migration {} is not applied", migration));
+        }
+        for migration in manager.unknown_migrations()? {
+            report.vault.push(format!("Migration {} comes from a newer version of Pandabox", migration));
+        }
+        let format = manager.get_ciphertext_format()?;
+        if format > CIPHERTEXT_FORMAT {
+            report.vault.push(format!("Ciphertext format {} comes from a newer version of Pandabox", format));
+        }
+        if let Err(e) = self.stored_settings() {
+            report.vault.push(format!("Vault settings cannot be read: {}", e));
+        }
+
+        let records = manager.get_records_with_trash()?;
+        let total = records.len();
+        for (index, record) in records.iter().enumerate() {
+            let issue = |detail: String| HealthIssue {
+                record_id: record.id,
+                service: record.service.clone(),
+                detail,
+            };
+
+            if RecordKind::parse(&record.kind).as_str() != record.kind {
+                report.corrupt.push(issue(format!("Unknown kind \"{}\"", record.kind)));
+            }
+            if let Some(problem) = ciphertext_problem(&record.password) {
+                report.corrupt.push(issue(format!("Password {}", problem)));
+            } else if let Err(e) = self.decrypt_password(record).map(Zeroizing::new) {
+                report.undecryptable.push(issue(format!("Password does not decrypt: {}", e)));
+            }
+
+            if !progress(index + 1, total) {
+                return Ok(None);
+            }
+        }
+
+        for (record_id, url) in manager.get_orphaned_record_urls()? {
+            report.orphaned.push(HealthIssue {
+                record_id,
+                service: format!("Missing record {}", record_id),
+                detail: format!("Website {}", url),
+            });
+        }
+        for (record_id, tag) in manager.get_orphaned_record_tags()? {
+            report.orphaned.push(HealthIssue {
+                record_id,
+                service: format!("Missing record {}", record_id),
+                detail: format!("Tag {}", tag),
+            });
+        }
+        for (record_id, name) in manager.get_orphaned_secret_attributes()? {
+            report.orphaned.push(HealthIssue {
+                record_id,
+                service: format!("Missing record {}", record_id),
+                detail: format!("Lookup attribute {}", name),
+            });
+        }
+
+        Ok(Some(report))
+    }
+}
+
+/// Why `encrypted_base64` cannot be a ciphertext of this version, without decrypting it
+fn ciphertext_problem(encrypted_base64: &str) -> Option<&'static str> {
+    let Ok(encrypted) = base64::engine::general_purpose::STANDARD.decode(encrypted_base64) else {
+        return Some("is not valid base64");
+    };
+    let Some((&version, encrypted)) = encrypted.split_first() else {
+        return Some("is truncated");
+    };
+    match record_nonce_len(version) {
+        None => Some("has an unknown format"),
+        Some(nonce_len) if encrypted.len() < nonce_len + TAG_LEN => Some("is truncated"),
+        Some(_) => None,
+    }
+}
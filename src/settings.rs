@@ -0,0 +1,165 @@
+// User preferences. Application settings live in a TOML file in the config directory and apply
+// to every vault; vault settings are stored encrypted inside the vault they belong to.
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use crate::encrypt::strength::DEFAULT_MIN_MASTER_ENTROPY;
+use crate::paths;
+use crate::session::health::DEFAULT_MAX_PASSWORD_AGE_DAYS;
+
+const SETTINGS_FILE: &str = "settings.toml";
+
+pub const MIN_GENERATED_LENGTH: usize = 8;
+pub const MAX_GENERATED_LENGTH: usize = 128;
+/// Bounds of the entropy a new master password is required to have
+pub const MIN_MASTER_ENTROPY: u32 = 28;
+pub const MAX_MASTER_ENTROPY: u32 = 128;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Theme {
+    /// Follow the desktop
+    #[default]
+    System,
+    Light,
+    Dark,
+}
+
+impl Theme {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Theme::System => "system",
+            Theme::Light => "light",
+            Theme::Dark => "dark",
+        }
+    }
+
+    /// Unknown values fall back to following the desktop
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "light" => Theme::Light,
+            "dark" => Theme::Dark,
+            _ => Theme::System,
+        }
+    }
+}
+
+/// How the password generator builds passwords
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GeneratorSettings {
+    pub length: usize,
+    pub symbols: bool,
+}
+
+impl Default for GeneratorSettings {
+    fn default() -> Self {
+        GeneratorSettings { length: 32, symbols: true }
+    }
+}
+
+/// Settings shared by every vault, stored in `$XDG_CONFIG_HOME/pandabox/settings.toml`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppSettings {
+    pub theme: Theme,
+    pub window_width: u32,
+    pub window_height: u32,
+    /// Seconds a browser or SSH request waits for the user before it is denied
+    pub approval_timeout_seconds: u64,
+    pub generator: GeneratorSettings,
+    /// Bits of entropy a new master password needs
+    pub min_master_password_entropy: u32,
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        AppSettings {
+            theme: Theme::System,
+            window_width: 1280,
+            window_height: 780,
+            approval_timeout_seconds: 60,
+            generator: GeneratorSettings::default(),
+            min_master_password_entropy: DEFAULT_MIN_MASTER_ENTROPY,
+        }
+    }
+}
+
+impl AppSettings {
+    pub fn path() -> PathBuf {
+        paths::config_dir().join(SETTINGS_FILE)
+    }
+
+    /// Read the settings file. A missing file gives the defaults, an unreadable one is logged
+    /// and replaced by the defaults too so a bad edit never keeps the app from starting.
+    pub fn load() -> Self {
+        let path = Self::path();
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(_) => return AppSettings::default(),
+        };
+        match toml::from_str::<AppSettings>(&content) {
+            Ok(settings) => settings.sanitized(),
+            Err(e) => {
+                warn!("Ignoring invalid settings in {}: {}", path.display(), e);
+                AppSettings::default()
+            }
+        }
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        fs::create_dir_all(paths::config_dir())?;
+        fs::write(Self::path(), toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn approval_timeout(&self) -> Duration {
+        Duration::from_secs(self.approval_timeout_seconds)
+    }
+
+    /// Clamp values edited by hand into the supported ranges
+    pub fn sanitized(mut self) -> Self {
+        self.generator.length = self.generator.length.clamp(MIN_GENERATED_LENGTH, MAX_GENERATED_LENGTH);
+        self.approval_timeout_seconds = self.approval_timeout_seconds.clamp(5, 600);
+        self.window_width = self.window_width.max(640);
+        self.window_height = self.window_height.max(480);
+        self.min_master_password_entropy = self.min_master_password_entropy.clamp(MIN_MASTER_ENTROPY, MAX_MASTER_ENTROPY);
+        self
+    }
+}
+
+/// Settings of a single vault, kept encrypted in the vault itself
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VaultSettings {
+    /// Seconds before a copied password is cleared from the clipboard, 0 keeps it
+    pub clipboard_clear_seconds: u64,
+    /// Age after which the security report flags a password
+    pub max_password_age_days: u64,
+    /// Days deleted entries stay in the trash before they are purged, 0 keeps them
+    pub trash_retention_days: u64,
+}
+
+impl Default for VaultSettings {
+    fn default() -> Self {
+        VaultSettings {
+            clipboard_clear_seconds: 30,
+            max_password_age_days: DEFAULT_MAX_PASSWORD_AGE_DAYS,
+            trash_retention_days: 30,
+        }
+    }
+}
+
+impl VaultSettings {
+    pub fn clipboard_clear_delay(&self) -> Option<Duration> {
+        (self.clipboard_clear_seconds > 0).then(|| Duration::from_secs(self.clipboard_clear_seconds))
+    }
+
+    /// How long deleted entries are kept in the trash, `None` until it is emptied by hand
+    pub fn trash_retention(&self) -> Option<Duration> {
+        (self.trash_retention_days > 0).then(|| Duration::from_secs(self.trash_retention_days * 24 * 60 * 60))
+    }
+}
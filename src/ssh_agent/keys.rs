@@ -0,0 +1,59 @@
+use std::error::Error;
+use rsa::pkcs1v15;
+use rsa::signature::{SignatureEncoding, Signer};
+use rsa::{BigUint, RsaPrivateKey};
+use ssh_key::private::{KeypairData, RsaKeypair};
+use ssh_key::sha2::{Sha256, Sha512};
+use ssh_key::{Algorithm, HashAlg, PrivateKey, Signature};
+
+/// Sign request flags selecting the hash of RSA signatures
+pub const SSH_AGENT_RSA_SHA2_256: u32 = 2;
+pub const SSH_AGENT_RSA_SHA2_512: u32 = 4;
+
+/// Parse an OpenSSH private key as pasted into the form. The vault already encrypts the key,
+/// so passphrase protected keys are rejected rather than prompting for the passphrase on use.
+pub fn parse_private_key(pem: &str) -> Result<PrivateKey, Box<dyn Error>> {
+    let key = PrivateKey::from_openssh(pem.trim())?;
+    if key.is_encrypted() {
+        return Err("remove the passphrase before storing the key".into());
+    }
+
+    match key.algorithm() {
+        Algorithm::Ed25519 | Algorithm::Rsa { .. } | Algorithm::Ecdsa { .. } => Ok(key),
+        algorithm => Err(format!("{} keys are not supported", algorithm).into()),
+    }
+}
+
+/// SSH wire encoding of the public key, which identifies the key in agent requests
+pub fn public_key_blob(key: &PrivateKey) -> ssh_key::Result<Vec<u8>> {
+    key.public_key().to_bytes()
+}
+
+/// Sign `data` with `key`. RSA keys use the SHA-2 hash requested in `flags`.
+pub fn sign(key: &PrivateKey, data: &[u8], flags: u32) -> Result<Signature, Box<dyn Error>> {
+    match key.key_data() {
+        KeypairData::Rsa(keypair) => {
+            let private_key = rsa_private_key(keypair)?;
+            let (hash, signature) = if flags & SSH_AGENT_RSA_SHA2_512 != 0 {
+                (HashAlg::Sha512, pkcs1v15::SigningKey::<Sha512>::new(private_key).try_sign(data)?.to_vec())
+            } else if flags & SSH_AGENT_RSA_SHA2_256 != 0 {
+                (HashAlg::Sha256, pkcs1v15::SigningKey::<Sha256>::new(private_key).try_sign(data)?.to_vec())
+            } else {
+                // Legacy ssh-rsa signatures use SHA-1 and are refused
+                return Err("SHA-1 RSA signatures are not supported".into());
+            };
+            Ok(Signature::new(Algorithm::Rsa { hash: Some(hash) }, signature)?)
+        }
+        _ => Ok(key.try_sign(data)?),
+    }
+}
+
+// ssh-key's own conversion passes the first prime twice, which makes every RSA signature fail
+fn rsa_private_key(keypair: &RsaKeypair) -> Result<RsaPrivateKey, Box<dyn Error>> {
+    Ok(RsaPrivateKey::from_components(
+        BigUint::try_from(&keypair.public.n)?,
+        BigUint::try_from(&keypair.public.e)?,
+        BigUint::try_from(&keypair.private.d)?,
+        vec![BigUint::try_from(&keypair.private.p)?, BigUint::try_from(&keypair.private.q)?],
+    )?)
+}
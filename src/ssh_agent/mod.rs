@@ -0,0 +1,3 @@
+pub mod keys;
+#[cfg(unix)]
+pub mod server;
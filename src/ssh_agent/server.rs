@@ -0,0 +1,244 @@
+// ssh-agent protocol server on a Unix socket serving the SSH keys stored in the vault.
+// Keys are only listed and used while the vault is unlocked; keys marked for confirmation
+// have to be allowed in the GUI every time they sign.
+use std::fs;
+use std::io::{self, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::thread;
+use log::{error, info, warn};
+use ssh_key::{HashAlg, PrivateKey};
+use zeroize::Zeroizing;
+use crate::approval::{ApprovalQueue, ApprovalRequest};
+use crate::bridge::protocol::runtime_dir;
+use crate::bridge::server::create_runtime_dir;
+use crate::session::session::{Session, SessionState};
+use crate::ssh_agent::keys::{parse_private_key, public_key_blob, sign};
+
+pub const SOCKET_FILE: &str = "ssh-agent.sock";
+
+// Message numbers from the agent protocol (draft-miller-ssh-agent)
+const SSH_AGENT_FAILURE: u8 = 5;
+const SSH_AGENTC_REQUEST_IDENTITIES: u8 = 11;
+const SSH_AGENT_IDENTITIES_ANSWER: u8 = 12;
+const SSH_AGENTC_SIGN_REQUEST: u8 = 13;
+const SSH_AGENT_SIGN_RESPONSE: u8 = 14;
+
+const MAX_MESSAGE_SIZE: usize = 256 * 1024;
+
+/// Socket to point `SSH_AUTH_SOCK` at
+pub fn socket_path() -> PathBuf {
+    runtime_dir().join(SOCKET_FILE)
+}
+
+/// Handle to the running agent, removes the socket when dropped.
+pub struct AgentServer {
+    socket: PathBuf,
+}
+
+impl Drop for AgentServer {
+    fn drop(&mut self) {
+        fs::remove_file(&self.socket).ok();
+    }
+}
+
+/// An SSH key record of the unlocked vault.
+struct AgentKey {
+    service: String,
+    confirm_use: bool,
+    key: PrivateKey,
+}
+
+/// Start serving agent connections. Uses of keys marked for confirmation go through `approvals`.
+pub fn start(session_state: SessionState, approvals: ApprovalQueue) -> io::Result<AgentServer> {
+    create_runtime_dir()?;
+
+    let socket = socket_path();
+    if socket.exists() {
+        if UnixStream::connect(&socket).is_ok() {
+            return Err(io::Error::new(io::ErrorKind::AddrInUse, "another Pandabox instance owns the SSH agent"));
+        }
+        fs::remove_file(&socket)?;
+    }
+
+    let listener = UnixListener::bind(&socket)?;
+    info!("SSH agent listening on {}", socket.display());
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let session_state = session_state.clone();
+                    let approvals = approvals.clone();
+                    thread::spawn(move || {
+                        if let Err(e) = handle_connection(stream, &session_state, &approvals) {
+                            warn!("SSH agent connection failed: {}", e);
+                        }
+                    });
+                }
+                Err(e) => error!("SSH agent accept failed: {}", e),
+            }
+        }
+    });
+
+    Ok(AgentServer { socket })
+}
+
+/// Answer requests until the client hangs up. Every message is a big-endian u32 length
+/// followed by the message type and its payload.
+pub fn handle_connection(mut stream: UnixStream, session_state: &SessionState, approvals: &ApprovalQueue) -> io::Result<()> {
+    loop {
+        let mut length = [0u8; 4];
+        match stream.read_exact(&mut length) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        }
+
+        let length = u32::from_be_bytes(length) as usize;
+        if length == 0 || length > MAX_MESSAGE_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid message length"));
+        }
+        let mut message = vec![0u8; length];
+        stream.read_exact(&mut message)?;
+
+        let response = handle_message(&message, session_state, approvals).unwrap_or_else(|| vec![SSH_AGENT_FAILURE]);
+        stream.write_all(&(response.len() as u32).to_be_bytes())?;
+        stream.write_all(&response)?;
+    }
+}
+
+/// Build the response to a message, `None` for SSH_AGENT_FAILURE
+fn handle_message(message: &[u8], session_state: &SessionState, approvals: &ApprovalQueue) -> Option<Vec<u8>> {
+    let (&kind, payload) = message.split_first()?;
+    match kind {
+        SSH_AGENTC_REQUEST_IDENTITIES => Some(identities_answer(session_state)),
+        SSH_AGENTC_SIGN_REQUEST => sign_response(payload, session_state, approvals),
+        // Adding, removing and locking keys is done in Pandabox itself
+        _ => None,
+    }
+}
+
+fn identities_answer(session_state: &SessionState) -> Vec<u8> {
+    // A locked vault has no identities
+    let keys = match &*session_state.lock().unwrap() {
+        Some(session) => load_keys(session),
+        None => Vec::new(),
+    };
+
+    let identities: Vec<(Vec<u8>, &str)> = keys
+        .iter()
+        .filter_map(|agent_key| Some((public_key_blob(&agent_key.key).ok()?, agent_key.service.as_str())))
+        .collect();
+
+    let mut response = vec![SSH_AGENT_IDENTITIES_ANSWER];
+    put_u32(&mut response, identities.len() as u32);
+    for (blob, comment) in identities {
+        put_string(&mut response, &blob);
+        put_string(&mut response, comment.as_bytes());
+    }
+    response
+}
+
+fn sign_response(mut payload: &[u8], session_state: &SessionState, approvals: &ApprovalQueue) -> Option<Vec<u8>> {
+    let key_blob = read_string(&mut payload)?;
+    let data = read_string(&mut payload)?;
+    let flags = read_u32(&mut payload).unwrap_or(0);
+
+    // Find the key first so the session is not held while the user decides
+    let agent_key = {
+        let state = session_state.lock().unwrap();
+        load_keys(state.as_ref()?)
+            .into_iter()
+            .find(|agent_key| public_key_blob(&agent_key.key).is_ok_and(|blob| blob == key_blob))?
+    };
+
+    if agent_key.confirm_use {
+        let allowed = approvals.request(ApprovalRequest {
+            title: String::from("SSH key request"),
+            message: String::from("An SSH client wants to sign with the key:"),
+            subject: format!("{} ({})", agent_key.service, agent_key.key.fingerprint(HashAlg::Sha256)),
+        });
+        // The vault may have been locked while waiting
+        if !allowed || session_state.lock().unwrap().is_none() {
+            info!("Use of SSH key {} denied", agent_key.service);
+            return None;
+        }
+    }
+
+    match sign(&agent_key.key, data, flags) {
+        Ok(signature) => {
+            let mut blob = Vec::new();
+            put_string(&mut blob, signature.algorithm().as_str().as_bytes());
+            put_string(&mut blob, signature.as_bytes());
+
+            let mut response = vec![SSH_AGENT_SIGN_RESPONSE];
+            put_string(&mut response, &blob);
+            Some(response)
+        }
+        Err(e) => {
+            warn!("Failed to sign with SSH key {}: {}", agent_key.service, e);
+            None
+        }
+    }
+}
+
+/// Decrypt and parse every SSH key record, skipping the ones that cannot be used
+fn load_keys(session: &Session) -> Vec<AgentKey> {
+    let records = match session.get_ssh_key_records() {
+        Ok(records) => records,
+        Err(e) => {
+            error!("Failed to fetch SSH keys: {}", e);
+            return Vec::new();
+        }
+    };
+
+    records
+        .into_iter()
+        .filter_map(|record| {
+            let pem = match session.decrypt_password(&record) {
+                Ok(pem) => Zeroizing::new(pem),
+                Err(e) => {
+                    error!("Failed to decrypt SSH key {}: {}", record.service, e);
+                    return None;
+                }
+            };
+            match parse_private_key(&pem) {
+                Ok(key) => Some(AgentKey {
+                    service: record.service,
+                    confirm_use: record.confirm_use,
+                    key,
+                }),
+                Err(e) => {
+                    warn!("Skipping SSH key {}: {}", record.service, e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+fn read_u32(input: &mut &[u8]) -> Option<u32> {
+    let (value, rest) = input.split_first_chunk::<4>()?;
+    *input = rest;
+    Some(u32::from_be_bytes(*value))
+}
+
+fn read_string<'a>(input: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let length = read_u32(input)? as usize;
+    if input.len() < length {
+        return None;
+    }
+    let (value, rest) = input.split_at(length);
+    *input = rest;
+    Some(value)
+}
+
+fn put_u32(output: &mut Vec<u8>, value: u32) {
+    output.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_string(output: &mut Vec<u8>, value: &[u8]) {
+    put_u32(output, value.len() as u32);
+    output.extend_from_slice(value);
+}
@@ -0,0 +1,195 @@
+// Drives the browser bridge over socket pairs, answering its approval prompts from the test.
+#![cfg(unix)]
+use std::fs;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixStream;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use pandabox::approval::ApprovalQueue;
+use pandabox::bridge::protocol::BridgeResponse;
+use pandabox::bridge::server::{check_private_dir, handle_connection, tokens_match, READ_TIMEOUT};
+use pandabox::{DatabaseManager, Entry, RecordKind, Session, SessionState};
+use tempfile::TempDir;
+use zeroize::Zeroizing;
+
+const PASSWORD: &str = "correct horse battery staple";
+const TOKEN: &str = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+
+fn unlocked_vault() -> (TempDir, SessionState) {
+    let dir = tempfile::tempdir().unwrap();
+    let manager = Arc::new(DatabaseManager::open(dir.path().join("vault.db")).unwrap());
+    let session = Session::create(manager, PASSWORD, None).unwrap();
+    for (service, url) in [
+        ("Login", "https://login.example.com/signin"),
+        ("Shop", "shop.example.com"),
+        ("Other", "https://other.example.org"),
+    ] {
+        let entry = Entry {
+            service: service.to_string(),
+            email: String::new(),
+            username: service.to_lowercase(),
+            password: Zeroizing::new(format!("{} password", service)),
+            notes: String::new(),
+            two_factor: false,
+            kind: RecordKind::Login,
+            confirm_use: false,
+        };
+        let record_id = session.insert_entry(&entry).unwrap().id;
+        session.set_record_urls(record_id, &[url]).unwrap();
+    }
+    (dir, Arc::new(Mutex::new(Some(Arc::new(session)))))
+}
+
+/// A queue answering every prompt with `answer` right away, or leaving it to time out when
+/// there is none. Returns the subjects it was asked about.
+fn answering_queue(answer: Option<bool>) -> (ApprovalQueue, Arc<Mutex<Vec<String>>>) {
+    let asked = Arc::new(Mutex::new(Vec::new()));
+    let queue_cell: Arc<OnceLock<ApprovalQueue>> = Arc::new(OnceLock::new());
+    let queue = ApprovalQueue::new({
+        let asked = Arc::clone(&asked);
+        let queue_cell = Arc::clone(&queue_cell);
+        move |current| {
+            if let Some(request) = current {
+                asked.lock().unwrap().push(request.subject);
+                if let (Some(allowed), Some(queue)) = (answer, queue_cell.get()) {
+                    queue.respond(allowed);
+                }
+            }
+        }
+    });
+    queue_cell.set(queue.clone()).ok();
+    (queue, asked)
+}
+
+fn exchange(line: &str, session_state: &SessionState, approvals: &ApprovalQueue) -> BridgeResponse {
+    let (server, mut client) = UnixStream::pair().unwrap();
+    client.write_all(line.as_bytes()).unwrap();
+    handle_connection(server, TOKEN, session_state, approvals).unwrap();
+    let mut response = String::new();
+    BufReader::new(client).read_line(&mut response).unwrap();
+    serde_json::from_str(&response).unwrap()
+}
+
+fn lookup(token: &str, url: &str, mode: &str) -> String {
+    format!(
+        "{}\n",
+        serde_json::json!({ "token": token, "request": { "action": "lookup", "url": url, "match": mode } })
+    )
+}
+
+#[test]
+fn wrong_token_is_rejected_without_asking() {
+    let (_dir, session_state) = unlocked_vault();
+    let (approvals, asked) = answering_queue(Some(true));
+
+    let wrong = TOKEN.replace('0', "1");
+    for token in [wrong.as_str(), "", &TOKEN[1..]] {
+        let response = exchange(&lookup(token, "https://login.example.com", "host"), &session_state, &approvals);
+        assert!(matches!(response, BridgeResponse::Error { ref message } if message == "Invalid bridge token"), "{:?}", response);
+    }
+    let response = exchange("not json\n", &session_state, &approvals);
+    assert!(matches!(response, BridgeResponse::Error { .. }));
+    assert!(asked.lock().unwrap().is_empty());
+}
+
+#[test]
+fn locked_vault_returns_no_credentials() {
+    let (_dir, session_state) = unlocked_vault();
+    session_state.lock().unwrap().take();
+    let (approvals, asked) = answering_queue(Some(true));
+
+    let response = exchange(&lookup(TOKEN, "https://login.example.com", "base_domain"), &session_state, &approvals);
+    assert!(matches!(response, BridgeResponse::Locked), "{:?}", response);
+    let status = format!("{}\n", serde_json::json!({ "token": TOKEN, "request": { "action": "status" } }));
+    assert!(matches!(exchange(&status, &session_state, &approvals), BridgeResponse::Locked));
+    assert!(asked.lock().unwrap().is_empty());
+}
+
+#[test]
+fn denied_or_unanswered_lookups_return_nothing() {
+    let (_dir, session_state) = unlocked_vault();
+
+    let (denying, asked) = answering_queue(Some(false));
+    let response = exchange(&lookup(TOKEN, "https://login.example.com", "host"), &session_state, &denying);
+    assert!(matches!(response, BridgeResponse::Denied), "{:?}", response);
+    assert_eq!(*asked.lock().unwrap(), ["https://login.example.com"]);
+
+    let (silent, asked) = answering_queue(None);
+    silent.set_timeout(Duration::from_millis(100));
+    let response = exchange(&lookup(TOKEN, "https://login.example.com", "host"), &session_state, &silent);
+    assert!(matches!(response, BridgeResponse::Denied), "{:?}", response);
+    assert_eq!(asked.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn approved_lookups_return_only_matching_logins() {
+    let (_dir, session_state) = unlocked_vault();
+    let (approvals, _) = answering_queue(Some(true));
+
+    let services = |url: &str, mode: &str| match exchange(&lookup(TOKEN, url, mode), &session_state, &approvals) {
+        BridgeResponse::Credentials { credentials } => {
+            let mut found: Vec<(String, String)> = credentials.into_iter().map(|credential| (credential.service, credential.password)).collect();
+            found.sort();
+            found
+        }
+        other => panic!("expected credentials, got {:?}", other),
+    };
+    let login = (String::from("Login"), String::from("Login password"));
+    let shop = (String::from("Shop"), String::from("Shop password"));
+
+    assert_eq!(services("https://login.example.com/signin", "exact"), std::slice::from_ref(&login));
+    assert!(services("https://login.example.com/other", "exact").is_empty());
+    assert_eq!(services("https://login.example.com/other", "host"), std::slice::from_ref(&login));
+    assert_eq!(services("https://www.example.com", "base_domain"), [login, shop]);
+    assert!(services("https://example.net", "base_domain").is_empty());
+}
+
+#[test]
+fn silent_connections_time_out() {
+    let (_dir, session_state) = unlocked_vault();
+    let (approvals, _) = answering_queue(Some(true));
+    let (server, _client) = UnixStream::pair().unwrap();
+
+    let started = Instant::now();
+    let error = handle_connection(server, TOKEN, &session_state, &approvals).unwrap_err();
+    assert!(started.elapsed() >= READ_TIMEOUT);
+    let kind = error.downcast_ref::<std::io::Error>().map(std::io::Error::kind);
+    assert!(matches!(kind, Some(ErrorKind::WouldBlock | ErrorKind::TimedOut)), "{}", error);
+}
+
+#[test]
+fn tokens_are_compared_whole() {
+    assert!(tokens_match(TOKEN, TOKEN));
+    assert!(!tokens_match(&TOKEN.replace('f', "e"), TOKEN));
+    assert!(!tokens_match(&TOKEN[..63], TOKEN));
+    assert!(!tokens_match(&format!("{}0", TOKEN), TOKEN));
+    assert!(!tokens_match("", TOKEN));
+}
+
+#[test]
+fn runtime_directory_has_to_be_private() {
+    let dir = tempfile::tempdir().unwrap();
+    let private = dir.path().join("private");
+    fs::create_dir(&private).unwrap();
+    fs::set_permissions(&private, fs::Permissions::from_mode(0o700)).unwrap();
+    check_private_dir(&private).unwrap();
+
+    for mode in [0o750, 0o701, 0o777, 0o1700] {
+        fs::set_permissions(&private, fs::Permissions::from_mode(mode)).unwrap();
+        let error = check_private_dir(&private).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::PermissionDenied, "mode {:o}", mode);
+    }
+    fs::set_permissions(&private, fs::Permissions::from_mode(0o700)).unwrap();
+
+    // A link to a private directory could be swapped for another one
+    let link = dir.path().join("link");
+    std::os::unix::fs::symlink(&private, &link).unwrap();
+    assert_eq!(check_private_dir(&link).unwrap_err().kind(), ErrorKind::PermissionDenied);
+
+    let file = dir.path().join("file");
+    fs::write(&file, "").unwrap();
+    fs::set_permissions(&file, fs::Permissions::from_mode(0o700)).unwrap();
+    assert_eq!(check_private_dir(&file).unwrap_err().kind(), ErrorKind::PermissionDenied);
+    assert_eq!(check_private_dir(&dir.path().join("missing")).unwrap_err().kind(), ErrorKind::NotFound);
+}
@@ -0,0 +1,145 @@
+// Where the vault and settings are looked up, and the move of a vault left by older releases.
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use pandabox::paths::Environment;
+
+fn environment(home: &Path) -> Environment {
+    Environment { home: home.to_path_buf(), ..Environment::default() }
+}
+
+#[test]
+fn xdg_directories_are_used_when_absolute() {
+    let home = PathBuf::from("/home/alice");
+    let defaults = environment(&home);
+    assert_eq!(defaults.data_dir(), PathBuf::from("/home/alice/.local/share/pandabox"));
+    assert_eq!(defaults.config_dir(), PathBuf::from("/home/alice/.config/pandabox"));
+    assert_eq!(defaults.default_vault_path(), PathBuf::from("/home/alice/.local/share/pandabox/pandabox.db"));
+
+    let xdg = Environment {
+        xdg_data_home: Some(OsString::from("/data")),
+        xdg_config_home: Some(OsString::from("/config")),
+        ..environment(&home)
+    };
+    assert_eq!(xdg.default_vault_path(), PathBuf::from("/data/pandabox/pandabox.db"));
+    assert_eq!(xdg.config_dir(), PathBuf::from("/config/pandabox"));
+
+    // The specification asks for relative values to be ignored
+    let relative = Environment {
+        xdg_data_home: Some(OsString::from("data")),
+        xdg_config_home: Some(OsString::new()),
+        ..environment(&home)
+    };
+    assert_eq!(relative.data_dir(), defaults.data_dir());
+    assert_eq!(relative.config_dir(), defaults.config_dir());
+}
+
+#[test]
+fn vault_argument_takes_precedence_over_the_variable() {
+    let home = PathBuf::from("/home/alice");
+    let argument = Some(PathBuf::from("argument.db"));
+
+    let with_variable = Environment { vault: Some(OsString::from("/variable.db")), ..environment(&home) };
+    assert_eq!(with_variable.vault_override(argument.clone()), argument);
+    assert_eq!(with_variable.vault_override(None), Some(PathBuf::from("/variable.db")));
+
+    let empty_variable = Environment { vault: Some(OsString::new()), ..environment(&home) };
+    assert_eq!(empty_variable.vault_override(None), None);
+    assert_eq!(environment(&home).vault_override(argument.clone()), argument);
+    assert_eq!(environment(&home).vault_override(None), None);
+}
+
+#[test]
+fn legacy_vault_is_moved_with_its_wal_files() {
+    let home = tempfile::tempdir().unwrap();
+    let environment = environment(home.path());
+    let legacy = home.path().join("Pandabox");
+    fs::create_dir(&legacy).unwrap();
+    for (name, contents) in [("pandabox.db", "vault"), ("pandabox.db-wal", "wal"), ("pandabox.db-shm", "shm")] {
+        fs::write(legacy.join(name), contents).unwrap();
+    }
+
+    assert!(environment.migrate_legacy_vault().unwrap());
+    let vault = environment.default_vault_path();
+    assert_eq!(fs::read_to_string(&vault).unwrap(), "vault");
+    assert_eq!(fs::read_to_string(vault.with_extension("db-wal")).unwrap(), "wal");
+    assert_eq!(fs::read_to_string(vault.with_extension("db-shm")).unwrap(), "shm");
+    assert!(!legacy.exists());
+
+    // Done once, later starts find nothing to move
+    assert!(!environment.migrate_legacy_vault().unwrap());
+}
+
+#[test]
+fn legacy_vault_is_moved_without_wal_files() {
+    let home = tempfile::tempdir().unwrap();
+    let environment = environment(home.path());
+    let legacy = home.path().join("Pandabox");
+    fs::create_dir(&legacy).unwrap();
+    fs::write(legacy.join("pandabox.db"), "vault").unwrap();
+    // Anything else stored next to the vault keeps the directory
+    fs::write(legacy.join("notes.txt"), "notes").unwrap();
+
+    assert!(environment.migrate_legacy_vault().unwrap());
+    let vault = environment.default_vault_path();
+    assert_eq!(fs::read_to_string(&vault).unwrap(), "vault");
+    assert!(!vault.with_extension("db-wal").exists());
+    assert!(!vault.with_extension("db-shm").exists());
+    assert!(!legacy.join("pandabox.db").exists());
+    assert!(legacy.join("notes.txt").exists());
+}
+
+#[test]
+fn existing_vault_is_never_overwritten() {
+    let home = tempfile::tempdir().unwrap();
+    let environment = environment(home.path());
+    let legacy = home.path().join("Pandabox");
+    fs::create_dir(&legacy).unwrap();
+    fs::write(legacy.join("pandabox.db"), "legacy vault").unwrap();
+    fs::write(legacy.join("pandabox.db-wal"), "legacy wal").unwrap();
+    let vault = environment.default_vault_path();
+    fs::create_dir_all(vault.parent().unwrap()).unwrap();
+    fs::write(&vault, "current vault").unwrap();
+
+    assert!(!environment.migrate_legacy_vault().unwrap());
+    assert_eq!(fs::read_to_string(&vault).unwrap(), "current vault");
+    assert!(!vault.with_extension("db-wal").exists());
+    assert_eq!(fs::read_to_string(legacy.join("pandabox.db")).unwrap(), "legacy vault");
+    assert_eq!(fs::read_to_string(legacy.join("pandabox.db-wal")).unwrap(), "legacy wal");
+}
+
+#[test]
+fn missing_legacy_vault_moves_nothing() {
+    let home = tempfile::tempdir().unwrap();
+    let environment = environment(home.path());
+    assert!(!environment.migrate_legacy_vault().unwrap());
+    assert!(!environment.data_dir().exists());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn legacy_vault_is_copied_across_file_systems() {
+    use std::os::unix::fs::MetadataExt;
+
+    let home = tempfile::tempdir().unwrap();
+    let Ok(data) = tempfile::tempdir_in("/dev/shm") else {
+        eprintln!("/dev/shm is not writable, skipping");
+        return;
+    };
+    if fs::metadata(home.path()).unwrap().dev() == fs::metadata(data.path()).unwrap().dev() {
+        eprintln!("/dev/shm is on the same file system, skipping");
+        return;
+    }
+    let environment = Environment { xdg_data_home: Some(data.path().into()), ..environment(home.path()) };
+    let legacy = home.path().join("Pandabox");
+    fs::create_dir(&legacy).unwrap();
+    fs::write(legacy.join("pandabox.db"), "vault").unwrap();
+    fs::write(legacy.join("pandabox.db-wal"), "wal").unwrap();
+
+    assert!(environment.migrate_legacy_vault().unwrap());
+    let vault = environment.default_vault_path();
+    assert!(vault.starts_with(data.path()));
+    assert_eq!(fs::read_to_string(&vault).unwrap(), "vault");
+    assert_eq!(fs::read_to_string(vault.with_extension("db-wal")).unwrap(), "wal");
+    assert!(!legacy.exists());
+}
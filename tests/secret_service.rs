@@ -0,0 +1,157 @@
+// Talks to the Secret Service provider over a private dbus-daemon, the way libsecret clients do.
+#![cfg(target_os = "linux")]
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use pandabox::secret_service::{SecretService, BUS_ADDRESS_VAR, COLLECTION_PATH, SERVICE_NAME, SERVICE_PATH};
+use pandabox::{DatabaseManager, Session, SessionState};
+use zbus::blocking::{proxy, Connection, Proxy};
+use zbus::proxy::CacheProperties;
+use zbus::zvariant::{OwnedObjectPath, OwnedValue, Value};
+
+const PASSWORD: &str = "correct horse battery staple";
+
+type Secret = (OwnedObjectPath, Vec<u8>, Vec<u8>, String);
+
+/// A dbus-daemon listening on a socket in `dir`, killed when dropped
+struct PrivateBus {
+    daemon: Child,
+    address: String,
+}
+
+impl PrivateBus {
+    fn start(dir: &Path) -> Option<PrivateBus> {
+        let socket = dir.join("bus");
+        let config = dir.join("bus.conf");
+        fs::write(&config, format!(
+            r#"<!DOCTYPE busconfig PUBLIC "-//freedesktop//DTD D-Bus Bus Configuration 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/busconfig.dtd">
+<busconfig>
+  <type>session</type>
+  <listen>unix:path={}</listen>
+  <auth>EXTERNAL</auth>
+  <policy context="default">
+    <allow send_destination="*" eavesdrop="true"/>
+    <allow eavesdrop="true"/>
+    <allow own="*"/>
+  </policy>
+</busconfig>
+"#,
+            socket.display()
+        )).unwrap();
+
+        let daemon = Command::new("dbus-daemon")
+            .arg(format!("--config-file={}", config.display()))
+            .arg("--nofork")
+            .stdout(Stdio::null())
+            .spawn()
+            .ok()?;
+        for _ in 0..100 {
+            if socket.exists() {
+                return Some(PrivateBus { daemon, address: format!("unix:path={}", socket.display()) });
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        panic!("dbus-daemon did not create {}", socket.display());
+    }
+}
+
+impl Drop for PrivateBus {
+    fn drop(&mut self) {
+        self.daemon.kill().ok();
+        self.daemon.wait().ok();
+    }
+}
+
+fn proxy<'a>(connection: &Connection, path: &'a str, interface: &'a str) -> Proxy<'a> {
+    proxy::Builder::new(connection)
+        .destination(SERVICE_NAME).unwrap()
+        .path(path).unwrap()
+        .interface(interface).unwrap()
+        .cache_properties(CacheProperties::No)
+        .build()
+        .unwrap()
+}
+
+fn is_locked_error(result: zbus::Result<impl std::fmt::Debug>) -> bool {
+    match result {
+        Err(zbus::Error::MethodError(name, _, _)) => name.as_str() == "org.freedesktop.Secret.Error.IsLocked",
+        other => panic!("expected IsLocked, got {:?}", other),
+    }
+}
+
+#[test]
+fn items_are_created_found_read_and_deleted_over_dbus() {
+    let dir = tempfile::tempdir().unwrap();
+    let Some(bus) = PrivateBus::start(dir.path()) else {
+        eprintln!("dbus-daemon is not installed, skipping");
+        return;
+    };
+    // SAFETY: the only test of this binary, nothing else reads the environment meanwhile
+    unsafe { std::env::set_var(BUS_ADDRESS_VAR, &bus.address) };
+
+    let manager = Arc::new(DatabaseManager::open(dir.path().join("vault.db")).unwrap());
+    let session = Session::create(Arc::clone(&manager), PASSWORD, None).unwrap();
+    let session_state: SessionState = Arc::new(Mutex::new(Some(Arc::new(session))));
+    let _provider = SecretService::start(manager, Arc::clone(&session_state), |_| {}).unwrap();
+
+    let client = zbus::blocking::connection::Builder::address(bus.address.as_str()).unwrap().build().unwrap();
+    let service = proxy(&client, SERVICE_PATH, "org.freedesktop.Secret.Service");
+    let collection = proxy(&client, COLLECTION_PATH, "org.freedesktop.Secret.Collection");
+
+    let (_, secret_session): (OwnedValue, OwnedObjectPath) = service.call("OpenSession", &("plain", Value::from(""))).unwrap();
+    let refused: zbus::Result<(OwnedValue, OwnedObjectPath)> = service.call("OpenSession", &("dh-ietf1024-sha256-aes128-cbc-pkcs7", Value::from("")));
+    assert!(refused.is_err());
+
+    let attributes = HashMap::from([("application", "pandabox-test"), ("username", "alice")]);
+    let properties = HashMap::from([
+        ("org.freedesktop.Secret.Item.Label", Value::from("Example login")),
+        ("org.freedesktop.Secret.Item.Attributes", Value::from(attributes.clone())),
+    ]);
+    let secret = (&secret_session, Vec::<u8>::new(), b"hunter2".to_vec(), "text/plain; charset=utf8");
+    let (item, prompt): (OwnedObjectPath, OwnedObjectPath) = collection.call("CreateItem", &(&properties, &secret, false)).unwrap();
+    assert_eq!(prompt.as_str(), "/");
+
+    let (unlocked, locked): (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>) = service.call("SearchItems", &(&attributes,)).unwrap();
+    assert_eq!(unlocked, std::slice::from_ref(&item));
+    assert!(locked.is_empty());
+    let missing = HashMap::from([("application", "something-else")]);
+    let (unlocked, _): (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>) = service.call("SearchItems", &(&missing,)).unwrap();
+    assert!(unlocked.is_empty());
+
+    let item_proxy = proxy(&client, item.as_str(), "org.freedesktop.Secret.Item");
+    let (_, _, value, content_type): Secret = item_proxy.call("GetSecret", &(&secret_session,)).unwrap();
+    assert_eq!(value, b"hunter2");
+    assert_eq!(content_type, "text/plain; charset=utf8");
+    assert_eq!(item_proxy.get_property::<String>("Label").unwrap(), "Example login");
+    let stored = session_state.lock().unwrap().clone().unwrap();
+    let record = &stored.get_all_records().unwrap()[0];
+    assert_eq!(record.username, "alice");
+    assert_eq!(stored.decrypt_password(record).unwrap(), "hunter2");
+
+    // Locked: items are still found but listed as locked and their secrets are refused
+    session_state.lock().unwrap().take();
+    assert!(collection.get_property::<bool>("Locked").unwrap());
+    assert!(item_proxy.get_property::<bool>("Locked").unwrap());
+    let (unlocked, locked): (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>) = service.call("SearchItems", &(&attributes,)).unwrap();
+    assert!(unlocked.is_empty());
+    assert_eq!(locked, std::slice::from_ref(&item));
+    assert!(is_locked_error(item_proxy.call::<_, _, Secret>("GetSecret", &(&secret_session,))));
+    assert!(is_locked_error(collection.call::<_, _, (OwnedObjectPath, OwnedObjectPath)>("CreateItem", &(&properties, &secret, false))));
+    let secrets: HashMap<OwnedObjectPath, Secret> = service.call("GetSecrets", &(vec![&item], &secret_session)).unwrap();
+    assert!(secrets.is_empty());
+
+    // Unlocked again, deleting the item moves the record to the trash
+    *session_state.lock().unwrap() = Some(stored.clone());
+    assert!(!collection.get_property::<bool>("Locked").unwrap());
+    let prompt: OwnedObjectPath = item_proxy.call("Delete", &()).unwrap();
+    assert_eq!(prompt.as_str(), "/");
+    let (unlocked, locked): (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>) = service.call("SearchItems", &(&attributes,)).unwrap();
+    assert!(unlocked.is_empty() && locked.is_empty());
+    assert!(stored.get_all_records().unwrap().is_empty());
+    assert_eq!(stored.trashed_records().unwrap().len(), 1);
+}
@@ -0,0 +1,286 @@
+// Signs with generated keys and talks to the agent in protocol frames over socket pairs.
+#![cfg(unix)]
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use rand_core::OsRng;
+use rsa::signature::Verifier;
+use ssh_key::private::{KeypairData, RsaKeypair};
+use ssh_key::{Algorithm, EcdsaCurve, HashAlg, LineEnding, PrivateKey, Signature};
+use pandabox::approval::ApprovalQueue;
+use pandabox::ssh_agent::keys::{parse_private_key, public_key_blob, sign, SSH_AGENT_RSA_SHA2_256, SSH_AGENT_RSA_SHA2_512};
+use pandabox::ssh_agent::server::handle_connection;
+use pandabox::{DatabaseManager, Entry, RecordKind, Session, SessionState};
+use tempfile::TempDir;
+use zeroize::Zeroizing;
+
+const PASSWORD: &str = "correct horse battery staple";
+const DATA: &[u8] = b"session identifier and userauth request";
+
+// Message numbers from the agent protocol
+const SSH_AGENT_FAILURE: u8 = 5;
+const SSH_AGENTC_REQUEST_IDENTITIES: u8 = 11;
+const SSH_AGENT_IDENTITIES_ANSWER: u8 = 12;
+const SSH_AGENTC_SIGN_REQUEST: u8 = 13;
+const SSH_AGENT_SIGN_RESPONSE: u8 = 14;
+const SSH_AGENTC_ADD_IDENTITY: u8 = 17;
+
+/// Keys of every supported type, the RSA one is only generated once as that is slow
+fn keys() -> Vec<(&'static str, PrivateKey)> {
+    static RSA: OnceLock<PrivateKey> = OnceLock::new();
+    let rsa = RSA.get_or_init(|| {
+        let keypair = RsaKeypair::random(&mut OsRng, 2048).unwrap();
+        PrivateKey::new(KeypairData::from(keypair), "rsa").unwrap()
+    });
+    vec![
+        ("ed25519", PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap()),
+        ("ecdsa", PrivateKey::random(&mut OsRng, Algorithm::Ecdsa { curve: EcdsaCurve::NistP256 }).unwrap()),
+        ("rsa", rsa.clone()),
+    ]
+}
+
+/// A vault holding `keys` as SSH key records, the ones named in `confirm` ask before each use
+fn unlocked_vault(keys: &[(&str, PrivateKey)], confirm: &[&str]) -> (TempDir, SessionState) {
+    let dir = tempfile::tempdir().unwrap();
+    let manager = Arc::new(DatabaseManager::open(dir.path().join("vault.db")).unwrap());
+    let session = Session::create(manager, PASSWORD, None).unwrap();
+    for (name, key) in keys {
+        let pem = key.to_openssh(LineEnding::LF).unwrap();
+        session.insert_entry(&Entry {
+            service: name.to_string(),
+            email: String::new(),
+            username: String::new(),
+            password: Zeroizing::new(pem.to_string()),
+            notes: String::new(),
+            two_factor: false,
+            kind: RecordKind::SshKey,
+            confirm_use: confirm.contains(name),
+        }).unwrap();
+    }
+    (dir, Arc::new(Mutex::new(Some(Arc::new(session)))))
+}
+
+/// A queue answering every prompt with `allowed` right away
+fn answering_queue(allowed: bool) -> (ApprovalQueue, Arc<Mutex<Vec<String>>>) {
+    let asked = Arc::new(Mutex::new(Vec::new()));
+    let queue_cell: Arc<OnceLock<ApprovalQueue>> = Arc::new(OnceLock::new());
+    let queue = ApprovalQueue::new({
+        let asked = Arc::clone(&asked);
+        let queue_cell = Arc::clone(&queue_cell);
+        move |current| {
+            if let (Some(request), Some(queue)) = (current, queue_cell.get()) {
+                asked.lock().unwrap().push(request.subject);
+                queue.respond(allowed);
+            }
+        }
+    });
+    queue_cell.set(queue.clone()).ok();
+    (queue, asked)
+}
+
+/// A client connected to an agent running on its own thread
+struct Client {
+    stream: UnixStream,
+    agent: Option<thread::JoinHandle<std::io::Result<()>>>,
+}
+
+impl Client {
+    fn connect(session_state: &SessionState, approvals: &ApprovalQueue) -> Client {
+        let (server, stream) = UnixStream::pair().unwrap();
+        let session_state = Arc::clone(session_state);
+        let approvals = approvals.clone();
+        let agent = thread::spawn(move || handle_connection(server, &session_state, &approvals));
+        Client { stream, agent: Some(agent) }
+    }
+
+    /// Send one message framed by its length and read the framed response
+    fn request(&mut self, message: &[u8]) -> Vec<u8> {
+        self.stream.write_all(&(message.len() as u32).to_be_bytes()).unwrap();
+        self.stream.write_all(message).unwrap();
+        let mut length = [0u8; 4];
+        self.stream.read_exact(&mut length).unwrap();
+        let mut response = vec![0u8; u32::from_be_bytes(length) as usize];
+        self.stream.read_exact(&mut response).unwrap();
+        response
+    }
+
+    fn identities(&mut self) -> Vec<(Vec<u8>, String)> {
+        let response = self.request(&[SSH_AGENTC_REQUEST_IDENTITIES]);
+        let (&kind, mut payload) = response.split_first().unwrap();
+        assert_eq!(kind, SSH_AGENT_IDENTITIES_ANSWER);
+        let count = read_u32(&mut payload);
+        let identities = (0..count)
+            .map(|_| (read_string(&mut payload), String::from_utf8(read_string(&mut payload)).unwrap()))
+            .collect();
+        assert!(payload.is_empty());
+        identities
+    }
+
+    /// The signature returned for `key`, `None` for SSH_AGENT_FAILURE
+    fn sign(&mut self, key: &PrivateKey, flags: u32) -> Option<Signature> {
+        let mut message = vec![SSH_AGENTC_SIGN_REQUEST];
+        put_string(&mut message, &public_key_blob(key).unwrap());
+        put_string(&mut message, DATA);
+        message.extend_from_slice(&flags.to_be_bytes());
+
+        let response = self.request(&message);
+        let (&kind, mut payload) = response.split_first().unwrap();
+        if kind == SSH_AGENT_FAILURE {
+            assert!(payload.is_empty());
+            return None;
+        }
+        assert_eq!(kind, SSH_AGENT_SIGN_RESPONSE);
+        let blob = read_string(&mut payload);
+        let mut blob = blob.as_slice();
+        let algorithm = String::from_utf8(read_string(&mut blob)).unwrap();
+        let signature = read_string(&mut blob);
+        Some(Signature::new(Algorithm::new(&algorithm).unwrap(), signature).unwrap())
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        // Hanging up ends the connection cleanly
+        self.stream.shutdown(std::net::Shutdown::Both).ok();
+        if let Some(agent) = self.agent.take() {
+            agent.join().unwrap().unwrap();
+        }
+    }
+}
+
+fn verify(key: &PrivateKey, data: &[u8], signature: &Signature) -> Result<(), rsa::signature::Error> {
+    Verifier::verify(key.public_key(), data, signature)
+}
+
+fn read_u32(input: &mut &[u8]) -> u32 {
+    let (value, rest) = input.split_first_chunk::<4>().unwrap();
+    *input = rest;
+    u32::from_be_bytes(*value)
+}
+
+fn read_string(input: &mut &[u8]) -> Vec<u8> {
+    let length = read_u32(input) as usize;
+    let (value, rest) = input.split_at(length);
+    *input = rest;
+    value.to_vec()
+}
+
+fn put_string(output: &mut Vec<u8>, value: &[u8]) {
+    output.extend_from_slice(&(value.len() as u32).to_be_bytes());
+    output.extend_from_slice(value);
+}
+
+#[test]
+fn signatures_verify_with_the_public_key() {
+    for (name, key) in keys() {
+        let parsed = parse_private_key(&key.to_openssh(LineEnding::LF).unwrap()).unwrap();
+        for flags in [SSH_AGENT_RSA_SHA2_256, SSH_AGENT_RSA_SHA2_512] {
+            let signature = sign(&parsed, DATA, flags).unwrap_or_else(|e| panic!("{} did not sign: {}", name, e));
+            verify(&key, DATA, &signature).unwrap_or_else(|e| panic!("{} signature does not verify: {}", name, e));
+            assert!(verify(&key, b"other data", &signature).is_err());
+            if name == "rsa" {
+                let hash = if flags == SSH_AGENT_RSA_SHA2_512 { HashAlg::Sha512 } else { HashAlg::Sha256 };
+                assert_eq!(signature.algorithm(), Algorithm::Rsa { hash: Some(hash) });
+            }
+        }
+    }
+}
+
+#[test]
+fn rsa_without_sha2_flags_is_refused() {
+    let (_, key) = keys().pop().unwrap();
+    assert!(sign(&key, DATA, 0).is_err());
+}
+
+#[test]
+fn upstream_rsa_conversion_is_still_broken() {
+    // ssh-key builds the RSA key from the first prime twice. Once this fails, its own signing
+    // works and `rsa_private_key` can go.
+    let (_, key) = keys().pop().unwrap();
+    let KeypairData::Rsa(keypair) = key.key_data() else {
+        unreachable!()
+    };
+    let converted = rsa::RsaPrivateKey::try_from(keypair);
+    let signed = converted.map(|private_key| {
+        let signature = rsa::signature::Signer::<rsa::pkcs1v15::Signature>::sign(
+            &rsa::pkcs1v15::SigningKey::<rsa::sha2::Sha256>::new(private_key),
+            DATA,
+        );
+        let public_key = rsa::RsaPublicKey::try_from(&keypair.public).unwrap();
+        rsa::pkcs1v15::VerifyingKey::<rsa::sha2::Sha256>::new(public_key).verify(DATA, &signature)
+    });
+    assert!(!matches!(signed, Ok(Ok(()))), "ssh-key converts RSA keys correctly now");
+}
+
+#[test]
+fn unlocked_agent_lists_and_signs_with_every_key() {
+    let keys = keys();
+    let (_dir, session_state) = unlocked_vault(&keys, &[]);
+    let (approvals, asked) = answering_queue(true);
+    let mut client = Client::connect(&session_state, &approvals);
+
+    let identities = client.identities();
+    let expected: Vec<(Vec<u8>, String)> = keys.iter().map(|(name, key)| (public_key_blob(key).unwrap(), name.to_string())).collect();
+    assert_eq!(identities, expected);
+
+    for (name, key) in &keys {
+        for flags in [SSH_AGENT_RSA_SHA2_256, SSH_AGENT_RSA_SHA2_512] {
+            let signature = client.sign(key, flags).unwrap_or_else(|| panic!("{} did not sign", name));
+            verify(key, DATA, &signature).unwrap();
+        }
+    }
+    let (_, rsa) = &keys[2];
+    assert!(client.sign(rsa, 0).is_none());
+    // Keys are managed in Pandabox, not through the agent
+    assert_eq!(client.request(&[SSH_AGENTC_ADD_IDENTITY]), [SSH_AGENT_FAILURE]);
+    // An unknown key is not signed with
+    let unknown = PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap();
+    assert!(client.sign(&unknown, 0).is_none());
+    assert!(asked.lock().unwrap().is_empty());
+}
+
+#[test]
+fn locked_agent_has_no_identities_and_signs_nothing() {
+    let keys = keys();
+    let (_dir, session_state) = unlocked_vault(&keys, &[]);
+    let (approvals, _) = answering_queue(true);
+    session_state.lock().unwrap().take();
+    let mut client = Client::connect(&session_state, &approvals);
+
+    assert!(client.identities().is_empty());
+    for (_, key) in &keys {
+        assert!(client.sign(key, SSH_AGENT_RSA_SHA2_256).is_none());
+    }
+}
+
+#[test]
+fn keys_marked_for_confirmation_sign_only_when_allowed() {
+    let keys = keys();
+    let (_dir, session_state) = unlocked_vault(&keys[..1], &["ed25519"]);
+    let (_, key) = &keys[0];
+
+    let (denying, asked) = answering_queue(false);
+    let mut client = Client::connect(&session_state, &denying);
+    assert!(client.sign(key, 0).is_none());
+    assert_eq!(asked.lock().unwrap().len(), 1);
+    assert!(asked.lock().unwrap()[0].starts_with("ed25519 (SHA256:"));
+    drop(client);
+
+    let (allowing, asked) = answering_queue(true);
+    let mut client = Client::connect(&session_state, &allowing);
+    let signature = client.sign(key, 0).unwrap();
+    verify(key, DATA, &signature).unwrap();
+    assert_eq!(asked.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn oversized_frames_end_the_connection() {
+    let (_dir, session_state) = unlocked_vault(&[], &[]);
+    let (approvals, _) = answering_queue(true);
+    let (server, mut stream) = UnixStream::pair().unwrap();
+
+    stream.write_all(&u32::MAX.to_be_bytes()).unwrap();
+    let error = handle_connection(server, &session_state, &approvals).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+}
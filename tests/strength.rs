@@ -0,0 +1,56 @@
+// Edge cases of the password strength estimator.
+use pandabox::encrypt::strength::{estimate_entropy, PasswordStrength};
+
+fn bits_per_character(pool: u32) -> f64 {
+    f64::from(pool).log2()
+}
+
+#[test]
+fn empty_and_common_passwords_have_no_entropy() {
+    assert_eq!(estimate_entropy(""), 0.0);
+    assert_eq!(estimate_entropy("password"), 0.0);
+    assert_eq!(estimate_entropy("PassWord"), 0.0);
+    assert_eq!(estimate_entropy("12345678"), 0.0);
+}
+
+#[test]
+fn entropy_grows_with_length_and_pool() {
+    // Lowercase letters only, none following another in a sequence
+    assert!((estimate_entropy("xqzmvk") - 6.0 * bits_per_character(26)).abs() < 1e-9);
+    assert!((estimate_entropy("xQzmvk") - 6.0 * bits_per_character(52)).abs() < 1e-9);
+    assert!((estimate_entropy("xQz7vk") - 6.0 * bits_per_character(62)).abs() < 1e-9);
+    assert!((estimate_entropy("xQz7v!") - 6.0 * bits_per_character(95)).abs() < 1e-9);
+    // Any character outside ASCII widens the pool
+    assert!((estimate_entropy("xqzmvé") - 6.0 * bits_per_character(126)).abs() < 1e-9);
+}
+
+#[test]
+fn repeats_and_sequences_count_for_a_quarter() {
+    // One character, then three repeats
+    assert!((estimate_entropy("zzzz") - 1.75 * bits_per_character(26)).abs() < 1e-9);
+    // Alphabet, digits and keyboard rows, in both directions
+    assert!((estimate_entropy("abcd") - 1.75 * bits_per_character(26)).abs() < 1e-9);
+    assert!((estimate_entropy("dcba") - 1.75 * bits_per_character(26)).abs() < 1e-9);
+    assert!((estimate_entropy("6789") - 1.75 * bits_per_character(10)).abs() < 1e-9);
+    assert!((estimate_entropy("asdf") - 1.75 * bits_per_character(26)).abs() < 1e-9);
+}
+
+#[test]
+fn embedded_common_passwords_are_penalized_once() {
+    // 11 characters adding a full step, the 7 digits after the first one continue a sequence
+    let expected_length: f64 = 1.0 + 10.0 + 7.0 * 0.25;
+    // 12345678 contains 123456, only the longer one is paid for
+    let expected = (expected_length - 7.0 * 0.75) * bits_per_character(95);
+    assert!((estimate_entropy("Vq#8Lx!Kz-12345678") - expected).abs() < 1e-9);
+}
+
+#[test]
+fn strength_follows_the_entropy() {
+    assert_eq!(PasswordStrength::from_entropy(0.0), PasswordStrength::VeryWeak);
+    assert_eq!(PasswordStrength::from_entropy(28.0), PasswordStrength::Weak);
+    assert_eq!(PasswordStrength::from_entropy(36.0), PasswordStrength::Fair);
+    assert_eq!(PasswordStrength::from_entropy(60.0), PasswordStrength::Strong);
+    assert_eq!(PasswordStrength::from_entropy(128.0), PasswordStrength::VeryStrong);
+    assert!(PasswordStrength::from_entropy(estimate_entropy("letmein123")) <= PasswordStrength::Weak);
+    assert!(PasswordStrength::from_entropy(estimate_entropy("correct horse battery staple")) >= PasswordStrength::Strong);
+}